@@ -0,0 +1,244 @@
+use rusty8::asm;
+use rusty8::cartridge;
+use rusty8::cfg;
+use rusty8::decompile;
+use rusty8::disasm::{self, Syntax};
+use rusty8::lint;
+use rusty8::platform::Platform;
+use rusty8::symbols::SymbolMap;
+use rusty8::tracediff;
+
+/// The platform names `--platform` takes, for usage messages.
+pub const PLATFORMS: &str = "<chip8|hires|eti660|chip8x|chip8e|schip|xochip|megachip>";
+
+/// Runs the subcommand named by the first argument, or returns `None` when
+/// the arguments are for the emulator itself.
+pub fn run(args: &[String]) -> Option<Result<(), String>> {
+    let rest = args.get(2..).unwrap_or_default();
+    match args.get(1)?.as_str() {
+        "asm" => Some(assemble(rest)),
+        "cfg" => Some(control_flow_graph(rest)),
+        "decompile" => Some(decompile(rest)),
+        "disasm" => Some(disasm(rest)),
+        "lint" => Some(lint(rest)),
+        "tracediff" => Some(trace_diff(rest)),
+        _ => None,
+    }
+}
+
+/// Reads the platform named by the argument after `--platform`.
+pub fn parse_platform<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    usage: &str,
+) -> Result<Platform, String> {
+    let name = args.next().ok_or(usage)?;
+    Platform::from_name(name).ok_or_else(|| format!("Unknown platform: {}", name))
+}
+
+fn assemble(args: &[String]) -> Result<(), String> {
+    let usage = &format!(
+        "Usage: rusty8 asm [--platform {}] <file.c8s> [-o <out.ch8>]",
+        PLATFORMS
+    );
+    let mut platform = Platform::Chip8;
+    let mut source_path = None;
+    let mut output_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => platform = parse_platform(&mut args, usage)?,
+            "-o" => output_path = Some(args.next().ok_or(usage)?.clone()),
+            _ if source_path.is_none() => source_path = Some(arg.clone()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    let source_path = source_path.ok_or(usage)?;
+    let output_path = output_path.unwrap_or_else(|| {
+        std::path::Path::new(&source_path)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned()
+    });
+    let rom = asm::assemble_file(&source_path, platform)?;
+    std::fs::write(&output_path, &rom).map_err(|e| format!("{}: {}", output_path, e))?;
+    println!("Wrote {} bytes to {}", rom.len(), output_path);
+    Ok(())
+}
+
+fn control_flow_graph(args: &[String]) -> Result<(), String> {
+    let usage = &format!(
+        "Usage: rusty8 cfg [--platform {}] <path_to_rom> [-o <out.dot>]",
+        PLATFORMS
+    );
+    let mut platform = Platform::Chip8;
+    let mut rom_path = None;
+    let mut output_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => platform = parse_platform(&mut args, usage)?,
+            "-o" => output_path = Some(args.next().ok_or(usage)?.clone()),
+            _ if rom_path.is_none() => rom_path = Some(arg.clone()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    let rom = cartridge::load_rom(&rom_path.ok_or(usage)?)?;
+    let dot = cfg::build(&rom, platform).to_dot(&rom, platform);
+    match output_path {
+        Some(path) => std::fs::write(&path, dot).map_err(|e| format!("{}: {}", path, e)),
+        None => {
+            print!("{}", dot);
+            Ok(())
+        }
+    }
+}
+
+fn decompile(args: &[String]) -> Result<(), String> {
+    let usage = &format!(
+        "Usage: rusty8 decompile [--platform {}] <path_to_rom> [-o <out.8o>]",
+        PLATFORMS
+    );
+    let mut platform = Platform::Chip8;
+    let mut rom_path = None;
+    let mut output_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => platform = parse_platform(&mut args, usage)?,
+            "-o" => output_path = Some(args.next().ok_or(usage)?.clone()),
+            _ if rom_path.is_none() => rom_path = Some(arg.clone()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    let rom = cartridge::load_rom(&rom_path.ok_or(usage)?)?;
+    let source = decompile::decompile(&rom, platform);
+    match output_path {
+        Some(path) => std::fs::write(&path, source).map_err(|e| format!("{}: {}", path, e)),
+        None => {
+            print!("{}", source);
+            Ok(())
+        }
+    }
+}
+
+fn disasm(args: &[String]) -> Result<(), String> {
+    let usage = &format!(
+        "Usage: rusty8 disasm [--platform {}] [--syntax <cowgod|octo>] [--symbols <file>] <path_to_rom>",
+        PLATFORMS
+    );
+    let mut platform = Platform::Chip8;
+    let mut syntax = Syntax::Cowgod;
+    let mut symbols = SymbolMap::new();
+    let mut rom_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => platform = parse_platform(&mut args, usage)?,
+            "--syntax" => {
+                let name = args.next().ok_or(usage)?;
+                syntax =
+                    Syntax::from_name(name).ok_or_else(|| format!("Unknown syntax: {}", name))?;
+            }
+            "--symbols" => symbols = SymbolMap::load(args.next().ok_or(usage)?)?,
+            _ if rom_path.is_none() => rom_path = Some(arg.clone()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    let rom = cartridge::load_rom(&rom_path.ok_or(usage)?)?;
+    print!(
+        "{}",
+        disasm::disassemble_with_symbols(&rom, platform, syntax, &symbols)
+    );
+    Ok(())
+}
+
+fn lint(args: &[String]) -> Result<(), String> {
+    let usage = &format!(
+        "Usage: rusty8 lint [--platform {}] <path_to_rom>",
+        PLATFORMS
+    );
+    let mut platform = Platform::Chip8;
+    let mut rom_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => platform = parse_platform(&mut args, usage)?,
+            _ if rom_path.is_none() => rom_path = Some(arg.clone()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    let rom = cartridge::load_rom(&rom_path.ok_or(usage)?)?;
+    let warnings = lint::lint(&rom, platform);
+    for warning in &warnings {
+        println!("{}", warning);
+    }
+    // A failing exit status lets build scripts stop on warnings.
+    match warnings.len() {
+        0 => Ok(()),
+        1 => Err("1 warning".to_string()),
+        count => Err(format!("{} warnings", count)),
+    }
+}
+
+fn trace_diff(args: &[String]) -> Result<(), String> {
+    let usage = &format!(
+        "Usage: rusty8 tracediff [--platform {}] [--ignore <V0-VF,I,SP,DT,ST,...>] <a.log> <b.log>",
+        PLATFORMS
+    );
+    let mut platform = Platform::Chip8;
+    let mut ignore = Vec::new();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => platform = parse_platform(&mut args, usage)?,
+            "--ignore" => {
+                for name in args.next().ok_or(usage)?.split(',') {
+                    let name = name.trim().to_ascii_uppercase();
+                    if !tracediff::FIELDS.contains(&name.as_str()) {
+                        return Err(format!("Unknown register: {}", name));
+                    }
+                    ignore.push(name);
+                }
+            }
+            _ if paths.len() < 2 => paths.push(arg.clone()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    let [a_path, b_path] = &paths[..] else {
+        return Err(usage.to_string());
+    };
+    let a = tracediff::read(a_path)?;
+    let b = tracediff::read(b_path)?;
+    match tracediff::diff(&a, &b, &ignore) {
+        Some(divergence) => {
+            let names = (a_path.as_str(), b_path.as_str());
+            print!(
+                "{}",
+                tracediff::report(&a, &b, names, &divergence, platform)
+            );
+            // A failing exit status lets scripts bisect on it.
+            Err("The traces diverge".to_string())
+        }
+        None => {
+            println!(
+                "The traces agree ({} and {} instructions)",
+                a.len(),
+                b.len()
+            );
+            Ok(())
+        }
+    }
+}
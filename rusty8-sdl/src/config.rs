@@ -0,0 +1,29 @@
+pub struct Config {
+    pub scale_factor: u32,
+    /// RGB colours for each combination of the two bitplanes.
+    pub palette: [(u8, u8, u8); 4],
+    /// RGB colours of the CHIP-8X foreground zones.
+    pub zone_palette: [(u8, u8, u8); 8],
+    /// RGB colours 02A0 cycles the CHIP-8X background through.
+    pub background_palette: [(u8, u8, u8); 4],
+}
+
+impl Config {
+    pub fn new(scale_factor: u32) -> Self {
+        Config {
+            scale_factor,
+            palette: [(0, 0, 0), (255, 255, 255), (170, 170, 170), (85, 85, 85)],
+            zone_palette: [
+                (0, 0, 0),
+                (255, 0, 0),
+                (0, 0, 255),
+                (255, 0, 255),
+                (0, 255, 0),
+                (255, 255, 0),
+                (0, 255, 255),
+                (255, 255, 255),
+            ],
+            background_palette: [(0, 0, 128), (0, 0, 0), (0, 128, 0), (128, 0, 0)],
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use sdl2::audio::{AudioCallback, AudioDevice};
+use std::f32::consts::PI;
+
+const AMPLITUDE: f32 = 0.25;
+const SAMPLE_RATE: i32 = 44100;
+const ATTACK_TIME: f32 = 0.01; // 10ms attack
+const DECAY_TIME: f32 = 0.05; // 50ms decay
+
+pub struct SineWave {
+    phase: f32,
+    volume: f32,
+    frequency: f32,
+    sample_count: usize,
+    duration: usize,
+    pattern: Option<[u8; 16]>,
+    pattern_rate: f32,
+    pattern_position: f32,
+    pcm: Vec<u8>,
+    pcm_rate: f32,
+    pcm_position: f32,
+    pcm_looping: bool,
+}
+
+impl SineWave {
+    pub fn new(frequency: f32) -> Self {
+        SineWave {
+            phase: 0.0,
+            volume: AMPLITUDE,
+            frequency,
+            sample_count: 0,
+            duration: 0,
+            pattern: None,
+            pattern_rate: 4000.0,
+            pattern_position: 0.0,
+            pcm: Vec::new(),
+            pcm_rate: 0.0,
+            pcm_position: 0.0,
+            pcm_looping: false,
+        }
+    }
+
+    /// Plays 8-bit unsigned PCM data at `rate` Hz, taking over from the tone
+    /// until it finishes or is replaced.
+    pub fn set_samples(&mut self, samples: Vec<u8>, rate: u16, looping: bool) {
+        self.pcm = samples;
+        self.pcm_rate = rate as f32;
+        self.pcm_position = 0.0;
+        self.pcm_looping = looping;
+    }
+
+    fn next_pcm_sample(&mut self) -> f32 {
+        let sample = (self.pcm[self.pcm_position as usize] as f32 - 128.0) / 128.0;
+        self.pcm_position += self.pcm_rate / SAMPLE_RATE as f32;
+        if self.pcm_position as usize >= self.pcm.len() {
+            if self.pcm_looping {
+                self.pcm_position = 0.0;
+            } else {
+                self.pcm.clear();
+            }
+        }
+        sample * self.volume
+    }
+
+    /// Plays the XO-CHIP 128-bit pattern instead of the sine tone. The pitch
+    /// register maps to a playback rate of 4000 * 2^((pitch - 64) / 48) bits
+    /// per second.
+    pub fn set_pattern(&mut self, pattern: Option<[u8; 16]>, pitch: u8) {
+        self.pattern = pattern;
+        self.pattern_rate = 4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0);
+    }
+
+    pub fn set_duration(&mut self, duration_ms: u32) {
+        self.duration = (duration_ms as f32 * SAMPLE_RATE as f32 / 1000.0) as usize;
+        self.sample_count = 0;
+    }
+}
+
+impl AudioCallback for SineWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            if !self.pcm.is_empty() {
+                *x = self.next_pcm_sample();
+                continue;
+            }
+
+            let t = self.sample_count as f32 / SAMPLE_RATE as f32;
+            let envelope = if t < ATTACK_TIME {
+                t / ATTACK_TIME
+            } else if t < ATTACK_TIME + DECAY_TIME {
+                1.0 - (t - ATTACK_TIME) / DECAY_TIME * 0.1
+            } else {
+                0.9
+            };
+
+            *x = match self.pattern {
+                Some(pattern) => {
+                    let bit = self.pattern_position as usize % 128;
+                    let level = if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                        1.0
+                    } else {
+                        -1.0
+                    };
+                    self.pattern_position =
+                        (self.pattern_position + self.pattern_rate / SAMPLE_RATE as f32) % 128.0;
+                    level * self.volume * envelope
+                }
+                None => {
+                    let sample = (self.phase * 2.0 * PI).sin() * self.volume * envelope;
+                    self.phase = (self.phase + self.frequency / SAMPLE_RATE as f32) % 1.0;
+                    sample
+                }
+            };
+
+            self.sample_count += 1;
+            if self.sample_count >= self.duration {
+                *x = 0.0;
+            }
+        }
+    }
+}
+
+pub fn play_beep(device: &mut AudioDevice<SineWave>, duration: u32) {
+    device.lock().set_duration(duration);
+    device.resume();
+}
+
+pub fn stop_beep(device: &mut AudioDevice<SineWave>) {
+    device.pause();
+}
+
+pub fn set_pattern(device: &mut AudioDevice<SineWave>, pattern: Option<[u8; 16]>, pitch: u8) {
+    device.lock().set_pattern(pattern, pitch);
+}
+
+pub fn play_samples(
+    device: &mut AudioDevice<SineWave>,
+    samples: Vec<u8>,
+    rate: u16,
+    looping: bool,
+) {
+    device.lock().set_samples(samples, rate, looping);
+    device.resume();
+}
+
+pub fn stop_samples(device: &mut AudioDevice<SineWave>) {
+    device.lock().set_samples(Vec::new(), 0, false);
+    device.pause();
+}
@@ -0,0 +1,82 @@
+use crate::config::Config;
+use rusty8::processor::{MegaRenderer, Renderer, MEGA_HEIGHT, MEGA_WIDTH};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+pub fn update_display(
+    canvas: &mut Canvas<Window>,
+    renderer: &Renderer,
+    config: &Config,
+) -> Result<(), String> {
+    let (r, g, b) = match renderer.zone_colors {
+        Some(_) => config.background_palette[renderer.background as usize & 0x3],
+        None => config.palette[0],
+    };
+    canvas.set_draw_color(Color::RGB(r, g, b));
+    canvas.clear();
+
+    // The scale factor is given for the 64-pixel-wide low resolution screen.
+    let scale = config.scale_factor * 64 / renderer.width() as u32;
+    for (y, row) in renderer.buffer.iter().take(renderer.height()).enumerate() {
+        for (x, &pixel) in row.iter().take(renderer.width()).enumerate() {
+            if pixel != 0 {
+                let (r, g, b) = match renderer.zone_colors {
+                    Some(zones) => config.zone_palette[zones[y % 32][x / 8 % 8] as usize],
+                    None => config.palette[pixel as usize & 0x3],
+                };
+                canvas.set_draw_color(Color::RGB(r, g, b));
+                let rect = Rect::new(
+                    x as i32 * scale as i32,
+                    y as i32 * scale as i32,
+                    scale,
+                    scale,
+                );
+                canvas.fill_rect(rect)?;
+            }
+        }
+    }
+
+    canvas.present();
+    Ok(())
+}
+
+pub fn update_mega_display(
+    canvas: &mut Canvas<Window>,
+    renderer: &MegaRenderer,
+    config: &Config,
+) -> Result<(), String> {
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(
+            PixelFormatEnum::RGB24,
+            MEGA_WIDTH as u32,
+            MEGA_HEIGHT as u32,
+        )
+        .map_err(|e| e.to_string())?;
+
+    // The screen alpha fades the whole frame towards black.
+    let alpha = renderer.alpha as u32;
+    texture.with_lock(None, |bytes: &mut [u8], pitch: usize| {
+        for (y, row) in renderer.pixels.iter().enumerate() {
+            for (x, &argb) in row.iter().enumerate() {
+                let offset = y * pitch + x * 3;
+                bytes[offset] = (((argb >> 16) & 0xFF) * alpha / 0xFF) as u8;
+                bytes[offset + 1] = (((argb >> 8) & 0xFF) * alpha / 0xFF) as u8;
+                bytes[offset + 2] = ((argb & 0xFF) * alpha / 0xFF) as u8;
+            }
+        }
+    })?;
+
+    // The MegaChip screen is four times the width of the low resolution one.
+    let scale = config.scale_factor / 4;
+    canvas.clear();
+    canvas.copy(
+        &texture,
+        None,
+        Rect::new(0, 0, MEGA_WIDTH as u32 * scale, MEGA_HEIGHT as u32 * scale),
+    )?;
+    canvas.present();
+    Ok(())
+}
@@ -1,4 +1,3 @@
 pub mod audio_driver;
-pub mod cartridge_driver;
 pub mod display_driver;
 pub mod input_driver;
@@ -0,0 +1,468 @@
+use drivers::input_driver::InputDriver;
+use sdl2::audio::{AudioDevice, AudioSpecDesired};
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::env;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+mod commands;
+mod config;
+mod drivers;
+mod monitor;
+
+use config::Config;
+use drivers::{audio_driver, display_driver};
+use monitor::{DapConnection, DebugConsole, GdbConnection, Monitor};
+use rusty8::cartridge;
+use rusty8::debugger::Debugger;
+use rusty8::fault::CpuFault;
+use rusty8::font::{Font, DEFAULT_FONT_ADDRESS};
+use rusty8::octo;
+use rusty8::platform::Platform;
+use rusty8::processor::{CPU, MEGA_HEIGHT};
+use rusty8::quirks::Quirks;
+use rusty8::random::{BuiltinRandom, RandomSource};
+use rusty8::symbols::SymbolMap;
+use rusty8::trace::{Filter, TraceFormat, Tracer};
+
+const CHIP8_WIDTH: u32 = 64;
+const CHIP8_HEIGHT: u32 = 32;
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().collect();
+    // Tool subcommands work on files and never open a window.
+    if let Some(result) = commands::run(&args) {
+        return result;
+    }
+
+    let sdl_context = sdl2::init().unwrap();
+    let event_pump = sdl_context.event_pump()?;
+    let mut input_driver = InputDriver::new(event_pump);
+    let options = parse_args(&args)?;
+    let platform = options.platform;
+
+    let sdl_context = sdl2::init().map_err(|e| e.to_string())?;
+    let video_subsystem = sdl_context.video()?;
+    // Taller variant screens get smaller pixels so the window stays on screen.
+    let (_, lores_height) = platform.resolution();
+    let scale_factor = 10 * 2 * CHIP8_HEIGHT / lores_height as u32;
+
+    // MegaChip's 256x192 screen is taller than the 2:1 CHIP-8 one.
+    let window_height = if platform == Platform::MegaChip {
+        MEGA_HEIGHT as u32 * scale_factor / 4
+    } else {
+        lores_height as u32 * scale_factor
+    };
+    let window = video_subsystem
+        .window("CHIP-8 Emulator", CHIP8_WIDTH * scale_factor, window_height)
+        .position_centered()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let audio_subsystem = sdl_context.audio().map_err(|e| e.to_string())?;
+
+    let audio_spec = AudioSpecDesired {
+        freq: Some(44100),
+        channels: Some(1),
+        samples: None,
+    };
+    let mut audio_device: AudioDevice<audio_driver::SineWave> = audio_subsystem
+        .open_playback(None, &audio_spec, |_spec| {
+            audio_driver::SineWave::new(800.0)
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut cpu = CPU::with_rng(platform, options.quirks, options.random);
+    cpu.install_font(&options.font, options.font_address)
+        .map_err(|e| e.to_string())?;
+    // Octo source is compiled on the way in, so there is no .ch8 to keep
+    // in step with it. Under --dap the editor's launch request names the
+    // program instead.
+    let mut symbols = SymbolMap::new();
+    if let Some(rom_path) = &options.rom_path {
+        let rom_data = if rom_path.ends_with(".8o") {
+            let source =
+                std::fs::read_to_string(rom_path).map_err(|e| format!("{}: {}", rom_path, e))?;
+            let (rom, found) = octo::compile_with_symbols(&source, rom_path, platform)
+                .map_err(|e| e.to_string())?;
+            symbols = found;
+            rom
+        } else {
+            cartridge::load_rom(rom_path)?
+        };
+        cpu.load_rom(&rom_data).map_err(|e| e.to_string())?;
+    }
+    if let Some(path) = &options.symbols_path {
+        symbols = SymbolMap::load(path)?;
+    }
+    let mut tracer = match &options.trace_path {
+        Some(path) => {
+            let mut tracer =
+                Tracer::create(path, platform, options.trace_format, options.trace_filter)?;
+            tracer.set_symbols(symbols.clone());
+            Some(tracer)
+        }
+        None => None,
+    };
+
+    let config = Config::new(scale_factor);
+
+    let mut last_sound_time = Instant::now();
+    let mut last_tick_time = Instant::now();
+    let mut beep_start_time: Option<Instant> = None;
+    // Once the program faults it stops running, but the window stays open
+    // so the last frame can still be looked at.
+    let mut halted = false;
+    // With --debug the program starts paused at the monitor prompt, and with
+    // --gdb or --dap it waits for the debugger to connect and starts paused.
+    let mut monitor = if let Some(port) = options.gdb_port {
+        Some(Monitor::Gdb(GdbConnection::accept(port)?))
+    } else if let Some(port) = options.dap_port {
+        Some(Monitor::Dap(DapConnection::accept(port)?))
+    } else if options.debug {
+        Some(Monitor::Console(DebugConsole::start(&cpu)))
+    } else {
+        None
+    };
+    if let Some(monitor) = &mut monitor {
+        monitor.debugger().symbols = symbols;
+    }
+
+    'running: loop {
+        if cpu.exited {
+            if let Some(monitor) = &mut monitor {
+                monitor.exited();
+            }
+            break 'running;
+        }
+
+        let keypad = match input_driver.poll() {
+            Ok(keypad) => keypad,
+            Err(_) => break 'running,
+        };
+
+        if let Some(monitor) = &mut monitor {
+            if !monitor.poll(&mut cpu, keypad) {
+                break 'running;
+            }
+            present(&mut canvas, &mut cpu, &config)?;
+        }
+
+        if !halted {
+            halted = run_step(
+                &mut canvas,
+                &mut cpu,
+                keypad,
+                &config,
+                &mut monitor,
+                tracer.as_mut(),
+            )?;
+        }
+
+        let now = Instant::now();
+        if !halted && now.duration_since(last_tick_time) >= Duration::from_micros(1000000 / 500) {
+            halted = run_step(
+                &mut canvas,
+                &mut cpu,
+                keypad,
+                &config,
+                &mut monitor,
+                tracer.as_mut(),
+            )?;
+            last_tick_time = now;
+        }
+
+        let paused = monitor.as_ref().is_some_and(Monitor::paused);
+        if !halted
+            && !paused
+            && now.duration_since(last_sound_time) >= Duration::from_millis(1000 / 60)
+        {
+            match &mut monitor {
+                Some(monitor) => monitor.debugger().tick_60hz(&mut cpu),
+                None => cpu.tick_60hz(),
+            }
+            last_sound_time = now;
+        }
+
+        if cpu.audio_updated {
+            audio_driver::set_pattern(&mut audio_device, cpu.audio_pattern, cpu.pitch);
+            match cpu.sample {
+                Some(sample) => {
+                    let end = (sample.address + sample.length).min(cpu.memory.len());
+                    let data = cpu.memory[sample.address.min(end)..end].to_vec();
+                    audio_driver::play_samples(
+                        &mut audio_device,
+                        data,
+                        sample.rate,
+                        sample.looping,
+                    );
+                }
+                None if platform == Platform::MegaChip => {
+                    audio_driver::stop_samples(&mut audio_device)
+                }
+                None => (),
+            }
+            cpu.audio_updated = false;
+        }
+
+        if cpu.sample.is_some() {
+            // A digitised sound owns the audio device until it is stopped.
+        } else if cpu.sound_timer > 0 {
+            if beep_start_time.is_none() {
+                audio_driver::play_beep(&mut audio_device, 100);
+                beep_start_time = Some(Instant::now());
+            }
+        } else if let Some(start_time) = beep_start_time {
+            if now.duration_since(start_time) >= Duration::from_millis(100) {
+                audio_driver::stop_beep(&mut audio_device);
+                beep_start_time = None;
+            }
+        }
+
+        let sleep_duration = Duration::from_micros(1000000 / 500);
+        ::std::thread::sleep(sleep_duration);
+    }
+
+    if let Some(tracer) = &mut tracer {
+        tracer.flush()?;
+    }
+    Ok(())
+}
+
+/// Runs one instruction, traces it and draws the result. A fault is
+/// reported on stderr and in the window title, and returned so the caller
+/// stops ticking.
+fn step<R: RandomSource>(
+    canvas: &mut Canvas<Window>,
+    cpu: &mut CPU<R>,
+    keypad: [bool; 16],
+    config: &Config,
+    debugger: Option<&mut Debugger<R>>,
+    mut tracer: Option<&mut Tracer>,
+) -> Result<Option<CpuFault>, String> {
+    if let Some(tracer) = &mut tracer {
+        tracer.begin(cpu);
+    }
+    let result = match debugger {
+        Some(debugger) => debugger.tick(cpu, keypad),
+        None => cpu.tick(keypad),
+    };
+    if let (Ok(_), Some(tracer)) = (&result, tracer) {
+        tracer.end(cpu)?;
+    }
+    if let Err(fault) = result {
+        let message = match cpu.fetch(cpu.program_counter) {
+            Ok(opcode) => format!(
+                "{} (PC {:#06X}, opcode {:#06X})",
+                fault, cpu.program_counter, opcode
+            ),
+            Err(_) => format!("{} (PC {:#06X})", fault, cpu.program_counter),
+        };
+        eprintln!("{}", message);
+        canvas
+            .window_mut()
+            .set_title(&format!("CHIP-8 Emulator - {}", message))
+            .map_err(|e| e.to_string())?;
+        return Ok(Some(fault));
+    }
+    present(canvas, cpu, config)?;
+    Ok(None)
+}
+
+/// Steps the CPU unless the debugger wants it paused. Under the debugger a
+/// fault pauses the program instead of halting it, so it can be inspected.
+fn run_step<R: RandomSource>(
+    canvas: &mut Canvas<Window>,
+    cpu: &mut CPU<R>,
+    keypad: [bool; 16],
+    config: &Config,
+    monitor: &mut Option<Monitor<R>>,
+    tracer: Option<&mut Tracer>,
+) -> Result<bool, String> {
+    if let Some(monitor) = monitor {
+        if monitor.should_pause(cpu) {
+            return Ok(false);
+        }
+    }
+    let debugger = monitor.as_mut().map(Monitor::debugger);
+    let fault = step(canvas, cpu, keypad, config, debugger, tracer)?;
+    match (monitor, fault) {
+        (Some(monitor), Some(fault)) => {
+            monitor.stop(cpu, fault);
+            Ok(false)
+        }
+        (_, fault) => Ok(fault.is_some()),
+    }
+}
+
+/// Draws whichever screen is active if the last instruction changed it. This
+/// runs after every tick so a MegaChip frame is shown before the next one
+/// starts being drawn.
+fn present(
+    canvas: &mut Canvas<Window>,
+    cpu: &mut CPU<impl RandomSource>,
+    config: &Config,
+) -> Result<(), String> {
+    if cpu.mega_mode {
+        if cpu.mega_renderer.redraw {
+            display_driver::update_mega_display(canvas, &cpu.mega_renderer, config)?;
+            cpu.mega_renderer.redraw = false;
+        }
+    } else if cpu.renderer.redraw {
+        display_driver::update_display(canvas, &cpu.renderer, config)?;
+        cpu.renderer.redraw = false;
+    }
+    Ok(())
+}
+
+struct Options {
+    rom_path: Option<String>,
+    platform: Platform,
+    quirks: Quirks,
+    font: Font,
+    font_address: u16,
+    random: BuiltinRandom,
+    debug: bool,
+    gdb_port: Option<u16>,
+    dap_port: Option<u16>,
+    symbols_path: Option<String>,
+    trace_path: Option<String>,
+    trace_format: TraceFormat,
+    trace_filter: Filter,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let usage = &format!(
+        "Usage: rusty8 [run] [--platform {}] \
+         [--quirks <vip|chip48|schip10|schip11|xochip>] \
+         [--font <vip|dream6800|eti660|fish|octo|path_to_font>] \
+         [--font-address <hex_address>] \
+         [--rng <seeded|vip>] [--seed <number>] [--debug] [--gdb <port>] \
+         [--dap <port>] [--symbols <file>] [--trace <file>] \
+         [--trace-format <text|binary>] [--trace-range <start-end>] \
+         [--trace-ops <0-F,...>] <path_to_rom|game.8o>",
+        commands::PLATFORMS
+    );
+    let mut platform = Platform::Chip8;
+    let mut quirks = None;
+    let mut font = None;
+    let mut font_address = DEFAULT_FONT_ADDRESS;
+    let mut rng = "seeded".to_string();
+    let mut seed = None;
+    let mut rom_path = None;
+    let mut debug = false;
+    let mut gdb_port = None;
+    let mut dap_port = None;
+    let mut symbols_path = None;
+    let mut trace_path = None;
+    let mut trace_format = TraceFormat::Text;
+    let mut trace_filter = Filter::default();
+
+    // `rusty8 run game.8o` reads better than a bare path for source files.
+    let skip = if args.get(1).map(String::as_str) == Some("run") {
+        2
+    } else {
+        1
+    };
+    let mut args = args.iter().skip(skip);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => platform = commands::parse_platform(&mut args, usage)?,
+            "--quirks" => {
+                let name = args.next().ok_or(usage)?;
+                quirks = Some(
+                    Quirks::from_name(name)
+                        .ok_or_else(|| format!("Unknown quirk profile: {}", name))?,
+                );
+            }
+            "--font" => {
+                let name = args.next().ok_or(usage)?;
+                font = Some(match Font::from_name(name) {
+                    Some(font) => font,
+                    None => {
+                        let bytes = std::fs::read(name).map_err(|e| e.to_string())?;
+                        Font::from_bytes(&bytes)?
+                    }
+                });
+            }
+            "--font-address" => {
+                let address = args.next().ok_or(usage)?;
+                font_address = u16::from_str_radix(address.trim_start_matches("0x"), 16)
+                    .map_err(|e| format!("Invalid font address {}: {}", address, e))?;
+            }
+            "--debug" => debug = true,
+            "--gdb" => {
+                let port = args.next().ok_or(usage)?;
+                gdb_port = Some(
+                    port.parse::<u16>()
+                        .map_err(|e| format!("Invalid gdb port {}: {}", port, e))?,
+                );
+            }
+            "--dap" => {
+                let port = args.next().ok_or(usage)?;
+                dap_port = Some(
+                    port.parse::<u16>()
+                        .map_err(|e| format!("Invalid DAP port {}: {}", port, e))?,
+                );
+            }
+            "--symbols" => symbols_path = Some(args.next().ok_or(usage)?.clone()),
+            "--trace" => trace_path = Some(args.next().ok_or(usage)?.clone()),
+            "--trace-format" => {
+                let name = args.next().ok_or(usage)?;
+                trace_format = TraceFormat::from_name(name)
+                    .ok_or_else(|| format!("Unknown trace format: {}", name))?;
+            }
+            "--trace-range" => {
+                trace_filter.range = Some(Filter::parse_range(args.next().ok_or(usage)?)?)
+            }
+            "--trace-ops" => {
+                trace_filter.classes = Some(Filter::parse_classes(args.next().ok_or(usage)?)?)
+            }
+            "--rng" => rng = args.next().ok_or(usage)?.clone(),
+            "--seed" => {
+                let value = args.next().ok_or(usage)?;
+                seed = Some(
+                    value
+                        .parse::<u64>()
+                        .map_err(|e| format!("Invalid seed {}: {}", value, e))?,
+                );
+            }
+            _ if rom_path.is_none() => rom_path = Some(arg.clone()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    // Without --seed every run is different; print the seed so a run worth
+    // reporting can be repeated.
+    let seed = seed.unwrap_or_else(|| {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        eprintln!("Random seed: {}", seed);
+        seed
+    });
+    let random = BuiltinRandom::from_name(&rng, seed)
+        .ok_or_else(|| format!("Unknown random number generator: {}", rng))?;
+
+    Ok(Options {
+        // The editor names the program when debugging over DAP.
+        rom_path: if dap_port.is_some() {
+            rom_path
+        } else {
+            Some(rom_path.ok_or(usage)?)
+        },
+        platform,
+        quirks: quirks.unwrap_or_else(|| platform.default_quirks()),
+        font: font.unwrap_or_else(|| platform.default_font()),
+        font_address,
+        random,
+        debug,
+        gdb_port,
+        dap_port,
+        symbols_path,
+        trace_path,
+        trace_format,
+        trace_filter,
+    })
+}
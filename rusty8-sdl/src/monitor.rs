@@ -0,0 +1,292 @@
+use std::io::{self, BufRead, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use rusty8::dap::{DapServer, SessionState};
+use rusty8::debugger::{self, Debugger, Resume};
+use rusty8::fault::{CpuFault, StepOutcome};
+use rusty8::gdb::{GdbStub, TargetState};
+use rusty8::processor::CPU;
+use rusty8::random::RandomSource;
+
+/// Whatever is debugging the program: the `--debug` console, a gdb
+/// connected with `--gdb` or an editor connected with `--dap`.
+pub enum Monitor<R: RandomSource> {
+    Console(DebugConsole<R>),
+    Gdb(GdbConnection<R>),
+    Dap(DapConnection<R>),
+}
+
+impl<R: RandomSource> Monitor<R> {
+    /// Handles input from the user. Returns `false` when they quit.
+    pub fn poll(&mut self, cpu: &mut CPU<R>, keypad: [bool; 16]) -> bool {
+        match self {
+            Monitor::Console(console) => console.poll(cpu, keypad),
+            Monitor::Gdb(gdb) => gdb.poll(cpu, keypad),
+            Monitor::Dap(dap) => dap.poll(cpu, keypad),
+        }
+    }
+
+    /// Whether the program should stay paused, stopping it at breakpoints.
+    pub fn should_pause(&mut self, cpu: &CPU<R>) -> bool {
+        match self {
+            Monitor::Console(console) => console.should_pause(cpu),
+            Monitor::Gdb(gdb) => gdb.should_pause(cpu),
+            Monitor::Dap(dap) => dap.should_pause(cpu),
+        }
+    }
+
+    /// Pauses the program on a fault so it can be inspected.
+    pub fn stop(&mut self, cpu: &CPU<R>, fault: CpuFault) {
+        match self {
+            Monitor::Console(console) => console.stop(cpu),
+            Monitor::Gdb(gdb) => gdb.stop(Err(fault)),
+            Monitor::Dap(dap) => dap.stop(Err(fault)),
+        }
+    }
+
+    /// Tells a remote debugger the program has exited.
+    pub fn exited(&mut self) {
+        match self {
+            Monitor::Console(_) => (),
+            Monitor::Gdb(gdb) => gdb.stop(Ok(StepOutcome::Exited)),
+            Monitor::Dap(dap) => dap.stop(Ok(StepOutcome::Exited)),
+        }
+    }
+
+    /// The debugger whose breakpoints and watchpoints apply.
+    pub fn debugger(&mut self) -> &mut Debugger<R> {
+        match self {
+            Monitor::Console(console) => &mut console.debugger,
+            Monitor::Gdb(gdb) => &mut gdb.stub.debugger,
+            Monitor::Dap(dap) => &mut dap.server.debugger,
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        match self {
+            Monitor::Console(console) => console.paused,
+            Monitor::Gdb(gdb) => gdb.paused(),
+            Monitor::Dap(dap) => dap.paused(),
+        }
+    }
+}
+
+/// The `--debug` monitor. Commands are read from stdin on another thread so
+/// the window keeps responding while the program is paused; typing a command
+/// while it runs pauses it.
+pub struct DebugConsole<R: RandomSource> {
+    debugger: Debugger<R>,
+    lines: Receiver<String>,
+    paused: bool,
+}
+
+impl<R: RandomSource> DebugConsole<R> {
+    pub fn start(cpu: &CPU<R>) -> Self {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut console = DebugConsole {
+            debugger: Debugger::new(),
+            lines,
+            paused: true,
+        };
+        println!("Debugger ready; type help for the commands.");
+        console.show(cpu);
+        console
+    }
+
+    /// Runs the commands typed since the last call. Returns `false` when the
+    /// user quits or closes stdin.
+    fn poll(&mut self, cpu: &mut CPU<R>, keypad: [bool; 16]) -> bool {
+        loop {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            };
+            self.paused = true;
+            match self.debugger.command(cpu, &line, keypad) {
+                Ok((output, Resume::Prompt)) => print!("{}", output),
+                Ok((output, Resume::Run)) => {
+                    print!("{}", output);
+                    self.paused = false;
+                }
+                Ok((_, Resume::Quit)) => return false,
+                Err(message) => println!("Error: {}", message),
+            }
+            if self.paused {
+                prompt();
+            }
+        }
+    }
+
+    fn should_pause(&mut self, cpu: &CPU<R>) -> bool {
+        if !self.paused && self.debugger.should_break(cpu) {
+            self.stop(cpu);
+        }
+        self.paused
+    }
+
+    fn stop(&mut self, cpu: &CPU<R>) {
+        self.paused = true;
+        self.show(cpu);
+    }
+
+    fn show(&mut self, cpu: &CPU<R>) {
+        if let Some(hit) = self.debugger.watch_hit.take() {
+            println!("{}", hit.message);
+        }
+        print!("{}", debugger::registers(cpu, &self.debugger.symbols));
+        print!("{}", self.debugger.disassemble(cpu, cpu.program_counter, 1));
+        prompt();
+    }
+}
+
+fn prompt() {
+    print!("(rusty8) ");
+    let _ = io::stdout().flush();
+}
+
+/// A gdb connected over TCP with `--gdb`. Like the console, the socket is
+/// read on another thread so the window keeps responding.
+pub struct GdbConnection<R: RandomSource> {
+    stub: GdbStub<R>,
+    stream: TcpStream,
+    input: Receiver<Vec<u8>>,
+}
+
+impl<R: RandomSource> GdbConnection<R> {
+    /// Waits for gdb to connect to `port` on localhost.
+    pub fn accept(port: u16) -> Result<Self, String> {
+        let (stream, input) = accept("gdb", port)?;
+        Ok(GdbConnection {
+            stub: GdbStub::new(),
+            stream,
+            input,
+        })
+    }
+
+    /// Handles the packets received since the last call. Returns `false`
+    /// when gdb kills the program or goes away without detaching.
+    fn poll(&mut self, cpu: &mut CPU<R>, keypad: [bool; 16]) -> bool {
+        loop {
+            let bytes = match self.input.try_recv() {
+                Ok(bytes) => bytes,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return self.stub.state == TargetState::Detached,
+            };
+            let reply = self.stub.receive(cpu, keypad, &bytes);
+            self.send(&reply);
+        }
+        self.stub.state != TargetState::Killed
+    }
+
+    fn should_pause(&mut self, cpu: &CPU<R>) -> bool {
+        if let Some(reply) = self.stub.should_break(cpu) {
+            self.send(&reply);
+        }
+        self.paused()
+    }
+
+    fn stop(&mut self, result: Result<StepOutcome, CpuFault>) {
+        let reply = self.stub.stop(result);
+        self.send(&reply);
+    }
+
+    fn paused(&self) -> bool {
+        matches!(self.stub.state, TargetState::Stopped | TargetState::Killed)
+    }
+
+    /// Writes to gdb. If it has gone away, the next poll finds out.
+    fn send(&mut self, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            let _ = self.stream.write_all(bytes);
+        }
+    }
+}
+
+/// An editor connected over TCP with `--dap`. The program to debug comes
+/// from its launch request.
+pub struct DapConnection<R: RandomSource> {
+    server: DapServer<R>,
+    stream: TcpStream,
+    input: Receiver<Vec<u8>>,
+}
+
+impl<R: RandomSource> DapConnection<R> {
+    /// Waits for an editor to connect to `port` on localhost.
+    pub fn accept(port: u16) -> Result<Self, String> {
+        let (stream, input) = accept("the editor", port)?;
+        Ok(DapConnection {
+            server: DapServer::new(),
+            stream,
+            input,
+        })
+    }
+
+    /// Handles the requests received since the last call. Returns `false`
+    /// once the session is over.
+    fn poll(&mut self, cpu: &mut CPU<R>, keypad: [bool; 16]) -> bool {
+        loop {
+            let bytes = match self.input.try_recv() {
+                Ok(bytes) => bytes,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return false,
+            };
+            let reply = self.server.receive(cpu, keypad, &bytes);
+            let _ = self.stream.write_all(&reply);
+        }
+        self.server.state != SessionState::Terminated
+    }
+
+    fn should_pause(&mut self, cpu: &CPU<R>) -> bool {
+        if let Some(reply) = self.server.should_break(cpu) {
+            let _ = self.stream.write_all(&reply);
+        }
+        self.paused()
+    }
+
+    fn stop(&mut self, result: Result<StepOutcome, CpuFault>) {
+        let reply = self.server.stop(result);
+        let _ = self.stream.write_all(&reply);
+    }
+
+    fn paused(&self) -> bool {
+        self.server.state != SessionState::Running
+    }
+}
+
+/// Waits for a debugger to connect to `port` on localhost, and reads from it
+/// on another thread.
+fn accept(client: &str, port: u16) -> Result<(TcpStream, Receiver<Vec<u8>>), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| e.to_string())?;
+    println!("Waiting for {} on 127.0.0.1:{}", client, port);
+    let (stream, address) = listener.accept().map_err(|e| e.to_string())?;
+    println!("Connected to {}", address);
+    let _ = stream.set_nodelay(true);
+
+    let mut reader = stream.try_clone().map_err(|e| e.to_string())?;
+    let (sender, input) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(length) => {
+                    if sender.send(buffer[..length].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    Ok((stream, input))
+}
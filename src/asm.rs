@@ -0,0 +1,537 @@
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// An assembly error, with the file and line it was found on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+/// Where a statement came from, for error messages.
+#[derive(Clone, Debug)]
+struct Location {
+    file: String,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Operand {
+    Register(u8),
+    /// `VX-VY`, as used by the XO-CHIP and CHIP-8E range loads and stores.
+    Range(u8, u8),
+    /// One of the special names such as `I`, `DT` or `K`.
+    Name(&'static str),
+    /// `[I]`
+    Memory,
+    /// `[I+]`
+    MemoryAdvance,
+    /// `LONG expression`, the 16-bit address of F000 NNNN.
+    Long(String),
+    Value(String),
+}
+
+const NAMES: [&str; 9] = ["I", "DT", "ST", "K", "F", "B", "HF", "R", "PITCH"];
+
+enum Statement {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+enum Symbol {
+    Label(usize),
+    Constant(String, Location),
+}
+
+struct Assembler<'a> {
+    platform: Platform,
+    read: &'a mut dyn FnMut(&str) -> Result<String, String>,
+    address: usize,
+    statements: Vec<(Statement, Location)>,
+    symbols: HashMap<String, Symbol>,
+}
+
+/// Assembles `source`, which was read from `file`. Included files are
+/// loaded through `read`, which is given the path exactly as written.
+pub fn assemble(
+    source: &str,
+    file: &str,
+    platform: Platform,
+    read: &mut dyn FnMut(&str) -> Result<String, String>,
+) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler {
+        platform,
+        read,
+        address: platform.load_address() as usize,
+        statements: Vec::new(),
+        symbols: HashMap::new(),
+    };
+    assembler.first_pass(source, file, 0)?;
+    assembler.second_pass()
+}
+
+/// Assembles the file at `path`, resolving includes relative to the file
+/// that includes them.
+pub fn assemble_file(path: &str, platform: Platform) -> Result<Vec<u8>, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let directory = Path::new(path)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+    let mut read = |include: &str| {
+        let path = directory.join(include);
+        std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+    assemble(&source, path, platform, &mut read).map_err(|e| e.to_string())
+}
+
+impl<'a> Assembler<'a> {
+    /// Collects statements and label addresses. Operands are only parsed
+    /// here; their values are worked out once every label is known.
+    fn first_pass(&mut self, source: &str, file: &str, depth: usize) -> Result<(), AsmError> {
+        for (number, line) in source.lines().enumerate() {
+            let location = Location {
+                file: file.to_string(),
+                line: number + 1,
+            };
+            let mut line = strip_comment(line).trim();
+
+            // Any number of labels may come before the statement.
+            while let Some((name, rest)) = line.split_once(':') {
+                let name = name.trim();
+                if !is_identifier(name) {
+                    break;
+                }
+                self.define(name, Symbol::Label(self.address), &location)?;
+                line = rest.trim();
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let (first, rest) = split_word(line);
+            let (second, value) = split_word(rest);
+            if second.eq_ignore_ascii_case("equ") || second == "=" {
+                self.define(
+                    first,
+                    Symbol::Constant(value.to_string(), location.clone()),
+                    &location,
+                )?;
+                continue;
+            }
+            if let Some((name, value)) = line.split_once('=') {
+                if is_identifier(name.trim()) {
+                    let constant = Symbol::Constant(value.trim().to_string(), location.clone());
+                    self.define(name.trim(), constant, &location)?;
+                    continue;
+                }
+            }
+
+            let mnemonic = first.to_ascii_uppercase();
+            let statement = match mnemonic.as_str() {
+                "INCLUDE" => {
+                    let path = rest.trim().trim_matches('"');
+                    if depth >= 16 {
+                        return Err(location.error("Includes are nested too deeply"));
+                    }
+                    let included = (self.read)(path).map_err(|e| location.error(e))?;
+                    self.first_pass(&included, path, depth + 1)?;
+                    continue;
+                }
+                "DB" => Statement::Bytes(split_operands(rest)),
+                "DW" => Statement::Words(split_operands(rest)),
+                _ => Statement::Instruction {
+                    mnemonic,
+                    operands: split_operands(rest)
+                        .iter()
+                        .map(|o| parse_operand(o))
+                        .collect(),
+                },
+            };
+
+            let size = match &statement {
+                Statement::Bytes(values) => values.len(),
+                Statement::Words(values) => values.len() * 2,
+                Statement::Instruction { mnemonic, operands } => {
+                    let long = mnemonic == "LDHI"
+                        || operands.iter().any(|o| matches!(o, Operand::Long(_)));
+                    if long {
+                        4
+                    } else {
+                        2
+                    }
+                }
+            };
+            let end = self.address + size;
+            if end > self.platform.memory_size() {
+                return Err(location.error("The program does not fit in memory"));
+            }
+            self.statements.push((statement, location));
+            self.address = end;
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol, location: &Location) -> Result<(), AsmError> {
+        if self.symbols.insert(name.to_string(), symbol).is_some() {
+            return Err(location.error(format!("{} is defined more than once", name)));
+        }
+        Ok(())
+    }
+
+    fn second_pass(&self) -> Result<Vec<u8>, AsmError> {
+        let mut output = Vec::new();
+        for (statement, location) in &self.statements {
+            match statement {
+                Statement::Bytes(values) => {
+                    for value in values {
+                        output.push(self.evaluate_sized(value, -0x80, 0xFF, location)? as u8);
+                    }
+                }
+                Statement::Words(values) => {
+                    for value in values {
+                        let word = self.evaluate_sized(value, -0x8000, 0xFFFF, location)? as u16;
+                        output.extend(word.to_be_bytes());
+                    }
+                }
+                Statement::Instruction { mnemonic, operands } => {
+                    let (instruction, operand) = self.instruction(mnemonic, operands, location)?;
+                    if Instruction::decode(instruction.encode(), self.platform) != Some(instruction)
+                    {
+                        return Err(location.error(format!(
+                            "{} is not available on {:?}",
+                            mnemonic, self.platform
+                        )));
+                    }
+                    output.extend(instruction.encode().to_be_bytes());
+                    output.extend(operand.map(u16::to_be_bytes).into_iter().flatten());
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    /// Builds the instruction for a statement, along with the address word
+    /// that follows F000 and 01NN.
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        operands: &[Operand],
+        location: &Location,
+    ) -> Result<(Instruction, Option<u16>), AsmError> {
+        use Instruction::*;
+        use Operand::{Long, Memory, MemoryAdvance, Name, Range, Register as V, Value};
+
+        let nnn = |value: &str| {
+            Ok::<u16, AsmError>(self.evaluate_sized(value, 0, 0xFFF, location)? as u16)
+        };
+        let nn = |value: &str| {
+            Ok::<u8, AsmError>(self.evaluate_sized(value, -0x80, 0xFF, location)? as u8)
+        };
+        let n =
+            |value: &str| Ok::<u8, AsmError>(self.evaluate_sized(value, 0, 0xF, location)? as u8);
+        let chip8x = self.platform.has_chip8x_opcodes();
+
+        let instruction = match (mnemonic, operands) {
+            ("CLS", []) => ClearScreen,
+            ("HCLS", []) => HiresClearScreen,
+            ("RET", []) => Return,
+            ("JP", [Value(a)]) => Jump(nnn(a)?),
+            ("JP", [V(0), Value(a)]) => JumpWithOffset(nnn(a)?),
+            ("CALL", [Value(a)]) => Call(nnn(a)?),
+            ("SE", [V(x), V(y)]) => SkipIfRegistersEqual { x: *x, y: *y },
+            ("SE", [V(x), Value(v)]) => SkipIfEqual { x: *x, nn: nn(v)? },
+            ("SNE", [V(x), V(y)]) => SkipIfRegistersNotEqual { x: *x, y: *y },
+            ("SNE", [V(x), Value(v)]) => SkipIfNotEqual { x: *x, nn: nn(v)? },
+            ("LD", [V(x), V(y)]) => Move { x: *x, y: *y },
+            ("LD", [V(x), Value(v)]) => SetRegister { x: *x, nn: nn(v)? },
+            ("LD", [Name("I"), Value(a)]) => SetIndex(nnn(a)?),
+            ("LD", [Name("I"), Long(a)]) => {
+                let word = self.evaluate_sized(a, 0, 0xFFFF, location)? as u16;
+                return Ok((LongIndex, Some(word)));
+            }
+            ("LD", [V(x), Name("DT")]) => GetDelay { x: *x },
+            ("LD", [V(x), Name("K")]) => WaitForKey { x: *x },
+            ("LD", [Name("DT"), V(x)]) => SetDelay { x: *x },
+            ("LD", [Name("ST"), V(x)]) => SetSound { x: *x },
+            ("LD", [Name("F"), V(x)]) => SmallCharacter { x: *x },
+            ("LD", [Name("HF"), V(x)]) => BigCharacter { x: *x },
+            ("LD", [Name("B"), V(x)]) => BinaryCodedDecimal { x: *x },
+            ("LD", [Memory, V(x)]) => Store { x: *x },
+            ("LD", [V(x), Memory]) => Load { x: *x },
+            ("LD", [Name("R"), V(x)]) => SaveFlags { x: *x },
+            ("LD", [V(x), Name("R")]) => LoadFlags { x: *x },
+            ("LD", [Name("PITCH"), V(x)]) => SetPitch { x: *x },
+            ("LD", [Memory, Range(x, y)]) => SaveRange { x: *x, y: *y },
+            ("LD", [Range(x, y), Memory]) => LoadRange { x: *x, y: *y },
+            ("LD", [MemoryAdvance, Range(x, y)]) => SaveRangeAndAdvance { x: *x, y: *y },
+            ("LD", [Range(x, y), MemoryAdvance]) => LoadRangeAndAdvance { x: *x, y: *y },
+            ("ADD", [V(x), V(y)]) => Add { x: *x, y: *y },
+            ("ADD", [V(x), Value(v)]) => AddImmediate { x: *x, nn: nn(v)? },
+            ("ADD", [Name("I"), V(x)]) => AddToIndex { x: *x },
+            ("OR", [V(x), V(y)]) => Or { x: *x, y: *y },
+            ("AND", [V(x), V(y)]) => And { x: *x, y: *y },
+            ("XOR", [V(x), V(y)]) => Xor { x: *x, y: *y },
+            ("SUB", [V(x), V(y)]) => Subtract { x: *x, y: *y },
+            ("SUBN", [V(x), V(y)]) => SubtractReversed { x: *x, y: *y },
+            ("SHR", [V(x)]) => ShiftRight { x: *x, y: *x },
+            ("SHR", [V(x), V(y)]) => ShiftRight { x: *x, y: *y },
+            ("SHL", [V(x)]) => ShiftLeft { x: *x, y: *x },
+            ("SHL", [V(x), V(y)]) => ShiftLeft { x: *x, y: *y },
+            ("RND", [V(x), Value(v)]) => Random { x: *x, nn: nn(v)? },
+            ("DRW", [V(x), V(y), Value(v)]) => Draw {
+                x: *x,
+                y: *y,
+                n: n(v)?,
+            },
+            ("SKP", [V(x)]) => SkipIfPressed { x: *x },
+            ("SKNP", [V(x)]) => SkipIfNotPressed { x: *x },
+
+            ("SCD", [Value(v)]) => ScrollDown(n(v)?),
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => LowResolution,
+            ("HIGH", []) => HighResolution,
+
+            ("SCU", [Value(v)]) => ScrollUp(n(v)?),
+            ("PLANE", [Value(v)]) => SelectPlanes(n(v)?),
+            ("AUDIO", []) => LoadAudioPattern,
+
+            ("MEGAOFF", []) => MegaOff,
+            ("MEGAON", []) => MegaOn,
+            ("LDHI", [Name("I"), Value(a)]) => {
+                let address = self.evaluate_sized(a, 0, 0xFF_FFFF, location)? as u32;
+                return Ok((LongIndex24((address >> 16) as u8), Some(address as u16)));
+            }
+            ("LDPAL", [Value(v)]) => LoadPalette(nn(v)?),
+            ("SPRW", [Value(v)]) => SpriteWidth(nn(v)?),
+            ("SPRH", [Value(v)]) => SpriteHeight(nn(v)?),
+            ("ALPHA", [Value(v)]) => ScreenAlpha(nn(v)?),
+            ("DIGISND", [Value(v)]) => PlaySample(n(v)?),
+            ("STOPSND", []) => StopSample,
+            ("BMODE", [Value(v)]) => BlendMode(n(v)?),
+            ("CCOL", [Value(v)]) => CollisionColor(nn(v)?),
+            ("SCRU", [Value(v)]) => MegaScrollUp(n(v)?),
+
+            ("BGCOL", []) => CycleBackground,
+            ("ADDN", [V(x), V(y)]) => AddNibbles { x: *x, y: *y },
+            ("COL", [V(x), V(y), Value(v)]) => ZoneColor {
+                x: *x,
+                y: *y,
+                n: n(v)?,
+            },
+            ("SKP2", [V(x)]) => SkipIfPressedKeypad2 { x: *x },
+            ("SKNP2", [V(x)]) => SkipIfNotPressedKeypad2 { x: *x },
+            ("OUT", [V(x)]) if chip8x => PortOutput { x: *x },
+            ("IN", [V(x)]) if chip8x => PortInput { x: *x },
+
+            ("STOP", []) => Stop,
+            ("NOP", []) => NoOperation,
+            ("WAIT", [Name("DT")]) => WaitForDelay,
+            ("SKIP", []) => SkipNext,
+            ("SKIP", [V(x)]) => SkipBytes { x: *x },
+            ("SGT", [V(x), V(y)]) => SkipIfGreater { x: *x, y: *y },
+            ("JB", [Value(v)]) => JumpBackward(nn(v)?),
+            ("JF", [Value(v)]) => JumpForward(nn(v)?),
+            ("OUT", [V(x)]) => Output { x: *x },
+            ("IN", [V(x)]) => Input { x: *x },
+            ("LDW", [Name("DT"), V(x)]) => SetDelayAndWait { x: *x },
+            ("INW", [V(x)]) => WaitForInput { x: *x },
+            _ => {
+                return Err(location.error(format!(
+                    "Unknown instruction or operands: {} {}",
+                    mnemonic,
+                    operands.iter().map(describe).collect::<Vec<_>>().join(", ")
+                )))
+            }
+        };
+        Ok((instruction, None))
+    }
+
+    fn evaluate_sized(
+        &self,
+        expression: &str,
+        min: i64,
+        max: i64,
+        location: &Location,
+    ) -> Result<i64, AsmError> {
+        let value = self.evaluate(expression, location, 0)?;
+        if value < min || value > max {
+            return Err(location.error(format!(
+                "{} ({:#X}) is out of range for this operand",
+                expression.trim(),
+                value
+            )));
+        }
+        Ok(value)
+    }
+
+    /// Evaluates a sum of numbers, labels and constants, such as
+    /// `sprites + 5 - 1`.
+    fn evaluate(
+        &self,
+        expression: &str,
+        location: &Location,
+        depth: usize,
+    ) -> Result<i64, AsmError> {
+        if depth > 32 {
+            return Err(location.error("Constants refer to each other in a loop"));
+        }
+        let expression = expression.trim();
+        if expression.is_empty() {
+            return Err(location.error("Missing value"));
+        }
+
+        let mut total: i64 = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+        for c in expression.chars().chain(std::iter::once('+')) {
+            if (c == '+' || c == '-') && !term.trim().is_empty() {
+                total = self
+                    .term(term.trim(), location, depth)?
+                    .checked_mul(sign)
+                    .and_then(|value| total.checked_add(value))
+                    .ok_or_else(|| location.error(format!("{} is too large", expression)))?;
+                term.clear();
+                sign = if c == '-' { -1 } else { 1 };
+            } else if c == '-' && term.trim().is_empty() {
+                sign = -sign;
+            } else if c != '+' {
+                term.push(c);
+            }
+        }
+        Ok(total)
+    }
+
+    fn term(&self, term: &str, location: &Location, depth: usize) -> Result<i64, AsmError> {
+        if let Some(value) = parse_number(term) {
+            return Ok(value);
+        }
+        match self.symbols.get(term) {
+            Some(Symbol::Label(address)) => Ok(*address as i64),
+            Some(Symbol::Constant(expression, defined_at)) => self
+                .evaluate(expression, defined_at, depth + 1)
+                .map_err(|e| location.error(format!("In {}: {}", term, e.message))),
+            None => Err(location.error(format!("Unknown name or number: {}", term))),
+        }
+    }
+}
+
+fn describe(operand: &Operand) -> String {
+    match operand {
+        Operand::Register(x) => format!("V{:X}", x),
+        Operand::Range(x, y) => format!("V{:X}-V{:X}", x, y),
+        Operand::Name(name) => name.to_string(),
+        Operand::Memory => "[I]".to_string(),
+        Operand::MemoryAdvance => "[I+]".to_string(),
+        Operand::Long(value) => format!("LONG {}", value),
+        Operand::Value(value) => value.clone(),
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim()),
+        None => (text, ""),
+    }
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    text.split(',')
+        .map(|operand| operand.trim().to_string())
+        .collect()
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('V').or_else(|| text.strip_prefix('v'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+fn parse_operand(text: &str) -> Operand {
+    let upper = text.to_ascii_uppercase();
+    if let Some(x) = register(text) {
+        return Operand::Register(x);
+    }
+    match upper.as_str() {
+        "[I]" => return Operand::Memory,
+        "[I+]" => return Operand::MemoryAdvance,
+        _ => (),
+    }
+    if let Some((x, y)) = text.split_once('-') {
+        if let (Some(x), Some(y)) = (register(x.trim()), register(y.trim())) {
+            return Operand::Range(x, y);
+        }
+    }
+    if let Some(value) = upper.strip_prefix("LONG ") {
+        return Operand::Long(text[text.len() - value.len()..].to_string());
+    }
+    if let Some(name) = NAMES.iter().find(|&&name| name == upper) {
+        return Operand::Name(name);
+    }
+    Operand::Value(text.to_string())
+}
+
+/// Reads a decimal, `0x`/`$`/`#` hexadecimal or `0b`/`%` binary number.
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    let (digits, radix) = if let Some(digits) = lower.strip_prefix("0x") {
+        (digits, 16)
+    } else if let Some(digits) = lower.strip_prefix('$').or_else(|| lower.strip_prefix('#')) {
+        (digits, 16)
+    } else if let Some(digits) = lower.strip_prefix("0b").or_else(|| lower.strip_prefix('%')) {
+        (digits, 2)
+    } else {
+        (lower.as_str(), 10)
+    };
+    i64::from_str_radix(digits, radix).ok()
+}
@@ -0,0 +1,91 @@
+#[cfg(test)]
+use crate::asm::{assemble, AsmError};
+#[cfg(test)]
+use crate::disasm::{disassemble, Syntax};
+#[cfg(test)]
+use crate::platform::Platform;
+
+#[cfg(test)]
+fn assemble_str(source: &str, platform: Platform) -> Result<Vec<u8>, AsmError> {
+    let mut read = |path: &str| match path {
+        "sprites.c8s" => Ok("sprite: db 0xF0, %10010000, $90\n".to_string()),
+        _ => Err(format!("{}: not found", path)),
+    };
+    assemble(source, "test.c8s", platform, &mut read)
+}
+
+#[test]
+fn test_assemble_labels_constants_and_data() {
+    let source = "\
+        ROWS equ 3          ; sprite height
+        X = 0x10
+        start:  CLS
+                LD I, sprite
+                ld v0, X + 2
+                DRW V0, V0, ROWS
+        loop:   JP loop
+                dw 0x1234
+        include \"sprites.c8s\"
+    ";
+    let rom = assemble_str(source, Platform::Chip8).unwrap();
+    assert_eq!(
+        rom,
+        [
+            0x00, 0xE0, 0xA2, 0x0C, 0x60, 0x12, 0xD0, 0x03, 0x12, 0x08, //
+            0x12, 0x34, 0xF0, 0x90, 0x90,
+        ]
+    );
+}
+
+#[test]
+fn test_assemble_extensions() {
+    let rom = assemble_str("LD I, LONG 0x1234\nLD [I], V2-V5\nSCD 4", Platform::XoChip);
+    assert_eq!(
+        rom.unwrap(),
+        [0xF0, 0x00, 0x12, 0x34, 0x52, 0x52, 0x00, 0xC4]
+    );
+}
+
+#[test]
+fn test_assemble_errors_have_line_numbers() {
+    let error = assemble_str("CLS\n\nJP nowhere", Platform::Chip8).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "test.c8s:3: Unknown name or number: nowhere"
+    );
+
+    let error = assemble_str("LD V0, 0x100", Platform::Chip8).unwrap_err();
+    assert_eq!(error.line, 1);
+
+    let error = assemble_str("x: CLS\nx: RET", Platform::Chip8).unwrap_err();
+    assert_eq!(error.line, 2);
+
+    let error = assemble_str("SCR", Platform::Chip8).unwrap_err();
+    assert!(error.message.contains("not available"));
+
+    let error = assemble_str("include \"missing.c8s\"", Platform::Chip8).unwrap_err();
+    assert_eq!(error.to_string(), "test.c8s:1: missing.c8s: not found");
+
+    let source = "CLS\nbig EQU 0x7FFFFFFFFFFFFFFF\nLD V0, big + big";
+    let error = assemble_str(source, Platform::Chip8).unwrap_err();
+    assert_eq!(error.to_string(), "test.c8s:3: big + big is too large");
+}
+
+#[test]
+fn test_disassembly_reassembles() {
+    let rom = [
+        0x00, 0xE0, 0xA2, 0x0E, 0x60, 0x05, 0xD0, 0x05, 0x22, 0x14, 0x80, 0x06, 0x12, 0x0A, 0xF0,
+        0x90, 0x90, 0x90, 0xFF, 0x00, 0x00, 0xEE, 0xFF, 0xFF,
+    ];
+    let listing: String = disassemble(&rom, Platform::SuperChip, Syntax::Cowgod)
+        .lines()
+        .map(|line| match line.get(18..) {
+            // Strip the address and bytes columns.
+            Some(statement) if line.get(4..6) == Some("  ") => {
+                format!("{}\n", statement)
+            }
+            _ => format!("{}\n", line),
+        })
+        .collect();
+    assert_eq!(assemble_str(&listing, Platform::SuperChip).unwrap(), rom);
+}
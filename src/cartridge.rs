@@ -1,6 +1,7 @@
 use std::fs::File;
 use std::io::Read;
 
+/// Reads a ROM image from disk so it can be passed to `CPU::load_rom`.
 pub fn load_rom(path: &str) -> Result<Vec<u8>, String> {
     let mut file = File::open(path).map_err(|e| e.to_string())?;
     let mut rom_data = Vec::new();
@@ -0,0 +1,440 @@
+use crate::disasm::{analyze, is_skip, mnemonic, skip_target, word_at, Syntax};
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// How control gets from one block to another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EdgeKind {
+    /// 1NNN, or one of the CHIP-8E relative jumps.
+    Jump,
+    /// 2NNN, to the subroutine.
+    Call,
+    /// 00EE, back to the instruction after a call that reaches it.
+    Return,
+    /// Into the next block, including when a skip is not taken.
+    FallThrough,
+    /// A taken skip.
+    Skip,
+}
+
+impl EdgeKind {
+    fn name(self) -> &'static str {
+        match self {
+            EdgeKind::Jump => "jump",
+            EdgeKind::Call => "call",
+            EdgeKind::Return => "return",
+            EdgeKind::FallThrough => "",
+            EdgeKind::Skip => "skip",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Edge {
+    pub from: u16,
+    pub to: u16,
+    pub kind: EdgeKind,
+}
+
+/// A run of instructions that is only entered at the top and only left at
+/// the bottom.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: u16,
+    /// The address after the last instruction.
+    pub end: u16,
+    pub instructions: Vec<(u16, Instruction)>,
+    /// The block ends in BNNN or another jump whose target depends on a
+    /// register, so its successors are unknown.
+    pub indirect: bool,
+    /// Stores in this block whose I, as far as it can be followed within the
+    /// block, points into code: the address of the store and of the write.
+    pub code_writes: Vec<(u16, u16)>,
+}
+
+/// The basic blocks reachable from the load address and the edges between
+/// them.
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<u16, BasicBlock>,
+    pub edges: BTreeSet<Edge>,
+}
+
+/// The addresses control may go to after `instruction`, which is at
+/// `address`, other than through a return.
+pub(crate) fn successors(
+    rom: &[u8],
+    base: u16,
+    platform: Platform,
+    address: u16,
+    instruction: &Instruction,
+) -> Vec<(u16, EdgeKind)> {
+    let next = address.wrapping_add(instruction.size());
+    match *instruction {
+        Instruction::Jump(target) => vec![(target, EdgeKind::Jump)],
+        Instruction::JumpBackward(nn) => vec![(address.wrapping_sub(nn as u16), EdgeKind::Jump)],
+        Instruction::JumpForward(nn) => vec![(address.wrapping_add(nn as u16), EdgeKind::Jump)],
+        Instruction::Call(target) => vec![(target, EdgeKind::Call)],
+        Instruction::SkipNext => vec![(skip_target(rom, base, next, platform), EdgeKind::Jump)],
+        Instruction::Return
+        | Instruction::Exit
+        | Instruction::Stop
+        | Instruction::JumpWithOffset(_)
+        | Instruction::SkipBytes { .. } => Vec::new(),
+        _ if is_skip(instruction) => vec![
+            (next, EdgeKind::FallThrough),
+            (skip_target(rom, base, next, platform), EdgeKind::Skip),
+        ],
+        _ => vec![(next, EdgeKind::FallThrough)],
+    }
+}
+
+/// Whether an instruction is the last in its block.
+fn ends_block(instruction: &Instruction) -> bool {
+    is_skip(instruction)
+        || matches!(
+            instruction,
+            Instruction::Jump(_)
+                | Instruction::JumpBackward(_)
+                | Instruction::JumpForward(_)
+                | Instruction::Call(_)
+                | Instruction::SkipNext
+                | Instruction::Return
+                | Instruction::Exit
+                | Instruction::Stop
+                | Instruction::JumpWithOffset(_)
+                | Instruction::SkipBytes { .. }
+        )
+}
+
+/// Builds the control-flow graph of a ROM loaded at the platform's load
+/// address.
+pub fn build(rom: &[u8], platform: Platform) -> ControlFlowGraph {
+    let base = platform.load_address();
+    let decode = |address: u16| {
+        word_at(rom, base, address).and_then(|opcode| Instruction::decode(opcode, platform))
+    };
+
+    // Find every reachable instruction, and the addresses that start a block.
+    let mut instructions = BTreeMap::new();
+    let mut leaders = BTreeSet::from([base]);
+    let mut pending = vec![base];
+    while let Some(address) = pending.pop() {
+        if instructions.contains_key(&address) {
+            continue;
+        }
+        let Some(instruction) = decode(address) else {
+            continue;
+        };
+        instructions.insert(address, instruction);
+        let next = address.wrapping_add(instruction.size());
+        for (target, kind) in successors(rom, base, platform, address, &instruction) {
+            if kind != EdgeKind::FallThrough || is_skip(&instruction) {
+                leaders.insert(target);
+            }
+            pending.push(target);
+        }
+        if let Instruction::Call(_) = instruction {
+            // Where the subroutine returns to.
+            leaders.insert(next);
+            pending.push(next);
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    let mut edges = BTreeSet::new();
+    for &start in leaders.iter().filter(|a| instructions.contains_key(a)) {
+        let mut block = BasicBlock {
+            start,
+            end: start,
+            instructions: Vec::new(),
+            indirect: false,
+            code_writes: Vec::new(),
+        };
+        let mut address = start;
+        while let Some(instruction) = instructions.get(&address) {
+            block.instructions.push((address, *instruction));
+            address = address.wrapping_add(instruction.size());
+            if ends_block(instruction) || leaders.contains(&address) {
+                break;
+            }
+        }
+        block.end = address;
+
+        let (last_address, last) = *block.instructions.last().unwrap();
+        block.indirect = matches!(
+            last,
+            Instruction::JumpWithOffset(_) | Instruction::SkipBytes { .. }
+        );
+        for (to, kind) in successors(rom, base, platform, last_address, &last) {
+            if instructions.contains_key(&to) {
+                edges.insert(Edge {
+                    from: start,
+                    to,
+                    kind,
+                });
+            }
+        }
+        blocks.insert(start, block);
+    }
+
+    let is_code = |target: u16| {
+        instructions
+            .range(..=target)
+            .next_back()
+            .is_some_and(|(&address, instruction)| {
+                (target as u32) < address as u32 + instruction.size() as u32
+            })
+    };
+    for block in blocks.values_mut() {
+        block.code_writes = memory_accesses(rom, base, platform, &block.instructions)
+            .into_iter()
+            .filter(|access| access.kind == AccessKind::Store)
+            .filter_map(|access| {
+                let end = access.target.saturating_add(access.length);
+                let written = (access.target..end).find(|&a| is_code(a))?;
+                Some((access.address, written))
+            })
+            .collect();
+    }
+
+    // A return goes back to every call whose subroutine reaches it.
+    let mut returns: HashMap<u16, Vec<u16>> = HashMap::new();
+    for block in blocks.values() {
+        let (address, instruction) = *block.instructions.last().unwrap();
+        let Instruction::Call(target) = instruction else {
+            continue;
+        };
+        let return_site = address.wrapping_add(2);
+        if !blocks.contains_key(&return_site) {
+            continue;
+        }
+        let sources = returns.entry(target).or_insert_with(|| {
+            subroutine_blocks(&blocks, &edges, target)
+                .into_iter()
+                .filter(|start| {
+                    matches!(
+                        blocks[start].instructions.last(),
+                        Some((_, Instruction::Return))
+                    )
+                })
+                .collect()
+        });
+        for &from in sources.iter() {
+            edges.insert(Edge {
+                from,
+                to: return_site,
+                kind: EdgeKind::Return,
+            });
+        }
+    }
+
+    ControlFlowGraph { blocks, edges }
+}
+
+/// The blocks a subroutine starting at `entry` can reach without returning
+/// first. Nested calls are assumed to come back.
+fn subroutine_blocks(
+    blocks: &BTreeMap<u16, BasicBlock>,
+    edges: &BTreeSet<Edge>,
+    entry: u16,
+) -> BTreeSet<u16> {
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![entry];
+    while let Some(start) = pending.pop() {
+        let Some(block) = blocks.get(&start) else {
+            continue;
+        };
+        if !seen.insert(start) {
+            continue;
+        }
+        if let Some((_, Instruction::Call(_))) = block.instructions.last() {
+            pending.push(block.end);
+        }
+        pending.extend(
+            edges
+                .iter()
+                .filter(|edge| {
+                    edge.from == start && !matches!(edge.kind, EdgeKind::Call | EdgeKind::Return)
+                })
+                .map(|edge| edge.to),
+        );
+    }
+    seen
+}
+
+/// What an instruction does with the memory I points at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    /// FX65 and the other loads into registers.
+    Load,
+    /// FX55, FX33 and the other stores.
+    Store,
+    /// DXYN reading sprite rows.
+    Sprite,
+    /// F002 reading an XO-CHIP audio pattern.
+    Audio,
+}
+
+/// A memory access through I whose address is known.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// The address of the instruction.
+    pub address: u16,
+    /// The first byte read or written.
+    pub target: u16,
+    pub length: u16,
+    pub kind: AccessKind,
+}
+
+/// How many bytes from I an instruction reads or writes, and what it does
+/// with them.
+pub fn access(instruction: &Instruction, platform: Platform) -> Option<(u16, AccessKind)> {
+    let access = match *instruction {
+        Instruction::Store { x } => (x as u16 + 1, AccessKind::Store),
+        Instruction::Load { x } => (x as u16 + 1, AccessKind::Load),
+        Instruction::BinaryCodedDecimal { .. } => (3, AccessKind::Store),
+        Instruction::SaveRange { x, y } | Instruction::SaveRangeAndAdvance { x, y } => {
+            (x.abs_diff(y) as u16 + 1, AccessKind::Store)
+        }
+        Instruction::LoadRange { x, y } | Instruction::LoadRangeAndAdvance { x, y } => {
+            (x.abs_diff(y) as u16 + 1, AccessKind::Load)
+        }
+        Instruction::Draw { n: 0, .. } if platform.has_superchip_opcodes() => {
+            (32, AccessKind::Sprite)
+        }
+        Instruction::Draw { n, .. } => (n as u16, AccessKind::Sprite),
+        Instruction::LoadAudioPattern => (16, AccessKind::Audio),
+        _ => return None,
+    };
+    Some(access)
+}
+
+/// Follows I through a block, which is only known after an `LD I` in the
+/// same block, and lists the accesses made through it.
+pub fn memory_accesses(
+    rom: &[u8],
+    base: u16,
+    platform: Platform,
+    instructions: &[(u16, Instruction)],
+) -> Vec<MemoryAccess> {
+    let mut accesses = Vec::new();
+    let mut index = None;
+    for &(address, instruction) in instructions {
+        match instruction {
+            Instruction::SetIndex(nnn) => {
+                index = Some(nnn);
+                continue;
+            }
+            Instruction::LongIndex => {
+                index = word_at(rom, base, address.wrapping_add(2));
+                continue;
+            }
+            Instruction::AddToIndex { .. }
+            | Instruction::SmallCharacter { .. }
+            | Instruction::BigCharacter { .. }
+            | Instruction::LongIndex24(_) => {
+                index = None;
+                continue;
+            }
+            _ => (),
+        }
+        let Some((length, kind)) = access(&instruction, platform) else {
+            continue;
+        };
+        if let Some(target) = index {
+            accesses.push(MemoryAccess {
+                address,
+                target,
+                length,
+                kind,
+            });
+        }
+        // Depending on the quirks these move I past the registers.
+        if matches!(
+            instruction,
+            Instruction::Store { .. }
+                | Instruction::Load { .. }
+                | Instruction::SaveRangeAndAdvance { .. }
+                | Instruction::LoadRangeAndAdvance { .. }
+        ) {
+            index = None;
+        }
+    }
+    accesses
+}
+
+impl ControlFlowGraph {
+    /// The blocks of the subroutine starting at `entry`, or of the main
+    /// program when given the load address.
+    pub fn subroutine(&self, entry: u16) -> BTreeSet<u16> {
+        subroutine_blocks(&self.blocks, &self.edges, entry)
+    }
+
+    /// Whether `address` is part of a reachable instruction.
+    pub fn is_code(&self, address: u16) -> bool {
+        self.blocks
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(_, block)| address < block.end)
+    }
+
+    /// Writes the graph in Graphviz DOT, with each block's disassembly in its
+    /// node.
+    pub fn to_dot(&self, rom: &[u8], platform: Platform) -> String {
+        let map = analyze(rom, platform);
+        let mut output = String::from("digraph rom {\n");
+        output.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            if let Some(name) = map.label(block.start) {
+                label.push_str(&format!("{}:\\l", name));
+            }
+            for &(address, instruction) in &block.instructions {
+                let operand = word_at(rom, map.base, address.wrapping_add(2));
+                let text = mnemonic(&instruction, operand, Syntax::Cowgod, &map);
+                label.push_str(&format!("{:04X}  {}\\l", address, escape(&text)));
+            }
+            if block.indirect {
+                label.push_str("; indirect jump\\l");
+            }
+            for (address, written) in &block.code_writes {
+                label.push_str(&format!(
+                    "; {:04X} writes code at {:04X}\\l",
+                    address, written
+                ));
+            }
+            let style = if block.indirect {
+                ", color=red"
+            } else if !block.code_writes.is_empty() {
+                ", color=orange"
+            } else {
+                ""
+            };
+            output.push_str(&format!(
+                "    block_{:03X} [label=\"{}\"{}];\n",
+                block.start, label, style
+            ));
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Call => ", style=dashed",
+                EdgeKind::Return => ", style=dotted",
+                _ => "",
+            };
+            output.push_str(&format!(
+                "    block_{:03X} -> block_{:03X} [label=\"{}\"{}];\n",
+                edge.from,
+                edge.to,
+                edge.kind.name(),
+                style
+            ));
+        }
+        output.push_str("}\n");
+        output
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+use crate::cfg::{build, Edge, EdgeKind};
+#[cfg(test)]
+use crate::platform::Platform;
+
+#[cfg(test)]
+const ROM: [u8; 14] = [
+    0x22, 0x08, // call 0x208
+    0x30, 0x01, // skip if v0 == 1
+    0x12, 0x00, // jump 0x200
+    0xB2, 0x00, // jump 0x200 + v0
+    0xA2, 0x04, // i := 0x204
+    0xF0, 0x55, // save v0, over the jump
+    0x00, 0xEE, // return
+];
+
+#[test]
+fn test_build_blocks_and_edges() {
+    let graph = build(&ROM, Platform::Chip8);
+    assert_eq!(
+        graph.blocks.keys().copied().collect::<Vec<_>>(),
+        [0x200, 0x202, 0x204, 0x206, 0x208]
+    );
+    assert_eq!(graph.blocks[&0x208].end, 0x20E);
+    assert!(graph.blocks[&0x206].indirect);
+    assert_eq!(graph.blocks[&0x208].code_writes, [(0x20A, 0x204)]);
+
+    let edge = |from, to, kind| Edge { from, to, kind };
+    assert_eq!(
+        graph.edges.iter().copied().collect::<Vec<_>>(),
+        [
+            edge(0x200, 0x208, EdgeKind::Call),
+            edge(0x202, 0x204, EdgeKind::FallThrough),
+            edge(0x202, 0x206, EdgeKind::Skip),
+            edge(0x204, 0x200, EdgeKind::Jump),
+            edge(0x208, 0x202, EdgeKind::Return),
+        ]
+    );
+}
+
+#[test]
+fn test_to_dot() {
+    let dot = build(&ROM, Platform::Chip8).to_dot(&ROM, Platform::Chip8);
+    assert!(dot.starts_with("digraph rom {\n"));
+    assert!(dot.contains(
+        "    block_208 [label=\"sub_208:\\l0208  LD I, 0x204\\l020A  LD [I], V0\\l020C  RET\\l\
+         ; 020A writes code at 0204\\l\", color=orange];\n"
+    ));
+    assert!(dot.contains(
+        "    block_206 [label=\"0206  JP V0, label_200\\l; indirect jump\\l\", color=red];\n"
+    ));
+    assert!(dot.contains("    block_208 -> block_202 [label=\"return\", style=dotted];\n"));
+}
+
+#[test]
+fn test_code_running_to_the_end_of_memory() {
+    let mut rom: Vec<u8> = [0x60, 0x00].repeat((0x10000 - 0x200) / 2);
+    let end = rom.len();
+    rom[end - 4..].copy_from_slice(&[0x30, 0x00, 0x12, 0x00]);
+    // i := long 0xFFFE, bcd v0, writing over the last instruction.
+    rom[..6].copy_from_slice(&[0xF0, 0x00, 0xFF, 0xFE, 0xF0, 0x33]);
+    let graph = build(&rom, Platform::XoChip);
+    assert!(graph.blocks.keys().any(|&address| address == 0xFFFE));
+    assert_eq!(graph.blocks[&0x200].code_writes, [(0x204, 0xFFFE)]);
+}
@@ -0,0 +1,894 @@
+use crate::cartridge;
+use crate::debugger::{Debugger, Watch};
+use crate::disasm::{mnemonic, RomMap, Syntax};
+use crate::expr::Expression;
+use crate::fault::{CpuFault, StepOutcome};
+use crate::history::History;
+use crate::instruction::Instruction;
+use crate::json::Json;
+use crate::octo;
+use crate::processor::CPU;
+use crate::random::RandomSource;
+use crate::symbols::SymbolMap;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The variables references of the scopes every stack frame has.
+const REGISTERS: i64 = 1;
+const TIMERS: i64 = 2;
+const STACK: i64 = 3;
+const MEMORY: i64 = 4;
+
+/// How many bytes from I the Memory scope shows.
+const MEMORY_WINDOW: usize = 0x40;
+
+/// How many instructions a source-level step runs looking for the next line
+/// before it gives up and stops anyway.
+const MAX_LINE_STEPS: usize = 10_000;
+
+/// The most instructions one disassemble request lists, which is one for
+/// every word of the largest memory.
+const MAX_DISASSEMBLY: i64 = 0x8000;
+
+/// Where the debug session is up to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionState {
+    /// Waiting for the editor, either before launch or at a stop.
+    Stopped,
+    /// Running until a breakpoint, a fault or a pause request.
+    Running,
+    /// The editor has ended the session or the program has exited.
+    Terminated,
+}
+
+/// A Debug Adapter Protocol server. Like `GdbStub`, bytes from the editor go
+/// in through `receive` and the bytes to send back come out, so the frontend
+/// owns the connection.
+///
+/// `launch` loads the program named in its arguments into the CPU. Octo
+/// sources are compiled with a symbol map, so breakpoints can be set on
+/// source lines and steps go a line at a time. A ROM can be given the same
+/// with a symbol file in the `symbols` argument.
+#[derive(Clone, Debug)]
+pub struct DapServer<R: RandomSource> {
+    pub debugger: Debugger<R>,
+    pub state: SessionState,
+    /// Breakpoints by address, with their conditions.
+    source_breakpoints: BTreeMap<u16, Option<Expression>>,
+    instruction_breakpoints: BTreeMap<u16, Option<Expression>>,
+    launched: bool,
+    stop_on_entry: bool,
+    /// A message that hasn't finished arriving.
+    input: Vec<u8>,
+    seq: i64,
+}
+
+impl<R: RandomSource> Default for DapServer<R> {
+    fn default() -> Self {
+        DapServer::new()
+    }
+}
+
+impl<R: RandomSource> DapServer<R> {
+    pub fn new() -> Self {
+        DapServer {
+            debugger: Debugger::new(),
+            state: SessionState::Stopped,
+            source_breakpoints: BTreeMap::new(),
+            instruction_breakpoints: BTreeMap::new(),
+            launched: false,
+            stop_on_entry: false,
+            input: Vec::new(),
+            seq: 0,
+        }
+    }
+
+    /// Handles bytes from the editor, returning the bytes to send back.
+    pub fn receive(&mut self, cpu: &mut CPU<R>, keypad: [bool; 16], bytes: &[u8]) -> Vec<u8> {
+        self.input.extend_from_slice(bytes);
+        let mut output = Vec::new();
+        while let Some(message) = self.next_message() {
+            match Json::parse(&message) {
+                Ok(request) => self.request(cpu, keypad, &request, &mut output),
+                Err(message) => self.event(&mut output, "output", output_body(&message)),
+            }
+        }
+        output
+    }
+
+    /// Checks for a breakpoint before the frontend runs the instruction at
+    /// PC, returning the stopped event to send if there is one.
+    pub fn should_break(&mut self, cpu: &CPU<R>) -> Option<Vec<u8>> {
+        if self.state != SessionState::Running || !self.debugger.should_break(cpu) {
+            return None;
+        }
+        let reason = if self.debugger.breakpoints.contains(&cpu.program_counter) {
+            "breakpoint"
+        } else {
+            "step"
+        };
+        self.state = SessionState::Stopped;
+        let body = self.stopped_body(reason);
+        let mut output = Vec::new();
+        self.event(&mut output, "stopped", body);
+        Some(output)
+    }
+
+    /// Stops the program after the frontend ran into a fault or an exit,
+    /// returning the events to send.
+    pub fn stop(&mut self, result: Result<StepOutcome, CpuFault>) -> Vec<u8> {
+        let mut output = Vec::new();
+        for (event, body) in self.outcome(result) {
+            self.event(&mut output, event, body);
+        }
+        output
+    }
+
+    /// Takes the next complete message off the input, if there is one.
+    fn next_message(&mut self) -> Option<String> {
+        loop {
+            let header_end = self.input.windows(4).position(|w| w == b"\r\n\r\n")?;
+            let body = header_end + 4;
+            let header = String::from_utf8_lossy(&self.input[..header_end]).to_string();
+            let length = header
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("Content-Length"))
+                .and_then(|(_, value)| value.trim().parse::<usize>().ok());
+            // Without a length, or with one too long to ever arrive, the
+            // message can't be found, so skip the header.
+            let Some(end) = length.and_then(|length| body.checked_add(length)) else {
+                self.input.drain(..body);
+                continue;
+            };
+            if self.input.len() < end {
+                return None;
+            }
+            let message = String::from_utf8_lossy(&self.input[body..end]).to_string();
+            self.input.drain(..end);
+            return Some(message);
+        }
+    }
+
+    fn request(
+        &mut self,
+        cpu: &mut CPU<R>,
+        keypad: [bool; 16],
+        request: &Json,
+        output: &mut Vec<u8>,
+    ) {
+        let command = request.get("command").as_str().unwrap_or_default();
+        let args = request.get("arguments");
+        // Events that follow the response, such as a step's stop.
+        let mut events = Vec::new();
+        let result = self.command(cpu, keypad, command, args, &mut events);
+        let mut response = vec![
+            ("seq".to_string(), Json::Null),
+            ("type".to_string(), "response".into()),
+            ("request_seq".to_string(), request.get("seq").clone()),
+            ("success".to_string(), result.is_ok().into()),
+            ("command".to_string(), command.into()),
+        ];
+        match result {
+            Ok(body) => response.push(("body".to_string(), body)),
+            Err(message) => response.push(("message".to_string(), message.into())),
+        }
+        self.send(output, Json::Object(response));
+        for (event, body) in events {
+            self.event(output, event, body);
+        }
+    }
+
+    fn command(
+        &mut self,
+        cpu: &mut CPU<R>,
+        keypad: [bool; 16],
+        command: &str,
+        args: &Json,
+        events: &mut Vec<(&'static str, Json)>,
+    ) -> Result<Json, String> {
+        let body = match command {
+            "initialize" => Json::object([
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsInstructionBreakpoints", true.into()),
+                ("supportsConditionalBreakpoints", true.into()),
+                ("supportsDataBreakpoints", true.into()),
+                ("supportsEvaluateForHovers", true.into()),
+                ("supportsStepBack", true.into()),
+                ("supportsSteppingGranularity", true.into()),
+                ("supportsReadMemoryRequest", true.into()),
+                ("supportsDisassembleRequest", true.into()),
+                ("supportsTerminateRequest", true.into()),
+            ]),
+            "launch" => {
+                let program = args
+                    .get("program")
+                    .as_str()
+                    .ok_or("launch needs a program")?;
+                let (rom, mut symbols) = if program.ends_with(".8o") {
+                    let source = std::fs::read_to_string(program)
+                        .map_err(|e| format!("{}: {}", program, e))?;
+                    octo::compile_with_symbols(&source, program, cpu.platform)
+                        .map_err(|e| e.to_string())?
+                } else {
+                    (cartridge::load_rom(program)?, SymbolMap::new())
+                };
+                if let Some(path) = args.get("symbols").as_str() {
+                    symbols = SymbolMap::load(path)?;
+                }
+                cpu.load_rom(&rom).map_err(|e| e.to_string())?;
+                self.debugger.history = History::new();
+                self.debugger.symbols = symbols;
+                self.stop_on_entry = args.get("stopOnEntry").as_bool().unwrap_or(false);
+                self.launched = true;
+                // Breakpoints can only be placed once the program is loaded,
+                // so configuration waits until now.
+                events.push(("initialized", Json::Null));
+                Json::Null
+            }
+            "configurationDone" => {
+                if self.launched {
+                    if self.stop_on_entry {
+                        events.push(("stopped", stopped_body("entry", None)));
+                    } else {
+                        self.debugger.resume(cpu);
+                        self.state = SessionState::Running;
+                    }
+                }
+                Json::Null
+            }
+            "setBreakpoints" => {
+                let path = args.get("source").get("path").as_str().unwrap_or_default();
+                let ours = self
+                    .debugger
+                    .symbols
+                    .file
+                    .as_deref()
+                    .is_some_and(|file| same_file(file, path));
+                // The editor sends every file's breakpoints separately, and
+                // only the program's own file has code in it.
+                if ours {
+                    self.source_breakpoints.clear();
+                }
+                let mut breakpoints = Vec::new();
+                for breakpoint in args.get("breakpoints").as_array() {
+                    let line = breakpoint.get("line").as_i64().unwrap_or(0);
+                    let found = self
+                        .debugger
+                        .symbols
+                        .address_of_line(line.max(0) as usize)
+                        .filter(|_| ours);
+                    let condition = condition(breakpoint);
+                    breakpoints.push(match (found, condition) {
+                        (_, Err(message)) => Json::object([
+                            ("verified", false.into()),
+                            ("line", line.into()),
+                            ("message", message.into()),
+                        ]),
+                        (Some((address, line)), Ok(condition)) => {
+                            self.source_breakpoints.insert(address, condition);
+                            Json::object([
+                                ("verified", true.into()),
+                                ("line", line.into()),
+                                ("instructionReference", reference(address).into()),
+                            ])
+                        }
+                        (None, _) => Json::object([
+                            ("verified", false.into()),
+                            ("line", line.into()),
+                            (
+                                "message",
+                                if ours {
+                                    "No code on this line"
+                                } else {
+                                    "Not part of the program"
+                                }
+                                .into(),
+                            ),
+                        ]),
+                    });
+                }
+                self.update_breakpoints();
+                Json::object([("breakpoints", breakpoints.into())])
+            }
+            "setInstructionBreakpoints" => {
+                self.instruction_breakpoints.clear();
+                let mut breakpoints = Vec::new();
+                for breakpoint in args.get("breakpoints").as_array() {
+                    let address = parse_reference(breakpoint.get("instructionReference")).and_then(
+                        |address| {
+                            address.checked_add(breakpoint.get("offset").as_i64().unwrap_or(0))
+                        },
+                    );
+                    breakpoints.push(match (address, condition(breakpoint)) {
+                        (_, Err(message)) => {
+                            Json::object([("verified", false.into()), ("message", message.into())])
+                        }
+                        (Some(address @ 0..=0xFFFF), Ok(condition)) => {
+                            self.instruction_breakpoints
+                                .insert(address as u16, condition);
+                            Json::object([
+                                ("verified", true.into()),
+                                ("instructionReference", reference(address as u16).into()),
+                            ])
+                        }
+                        _ => Json::object([
+                            ("verified", false.into()),
+                            ("message", "Not an address".into()),
+                        ]),
+                    });
+                }
+                self.update_breakpoints();
+                Json::object([("breakpoints", breakpoints.into())])
+            }
+            "dataBreakpointInfo" => {
+                let name = args.get("name").as_str().unwrap_or_default();
+                let scope = args.get("variablesReference").as_i64();
+                // A data ID is an expression to watch the value of, or a
+                // memory range written as `address/length`.
+                let (id, access_types): (Option<String>, &[&str]) = match scope {
+                    Some(REGISTERS | TIMERS) if Expression::parse(name).is_ok() => {
+                        (Some(name.to_ascii_lowercase()), &["write"])
+                    }
+                    Some(MEMORY) => (
+                        parse_reference(&name.into()).map(|address| format!("{:#06X}/8", address)),
+                        &["read", "write", "readWrite"],
+                    ),
+                    _ => (None, &[]),
+                };
+                let description = match &id {
+                    Some(_) => format!("{} changes", name),
+                    None => format!("{} can't be watched", name),
+                };
+                let access_types: Vec<Json> = access_types.iter().map(|&t| t.into()).collect();
+                Json::object([
+                    ("dataId", id.map_or(Json::Null, Json::from)),
+                    ("description", description.into()),
+                    ("accessTypes", access_types.into()),
+                ])
+            }
+            "setDataBreakpoints" => {
+                self.debugger.watchpoints.clear();
+                let mut breakpoints = Vec::new();
+                for breakpoint in args.get("breakpoints").as_array() {
+                    let id = breakpoint.get("dataId").as_str().unwrap_or_default();
+                    let access = breakpoint.get("accessType").as_str().unwrap_or("write");
+                    let watch = match id.split_once('/') {
+                        Some((start, length)) => parse_reference(&start.into())
+                            .zip(length.parse::<u16>().ok())
+                            .map(|(start, length)| Watch::Memory {
+                                start: start as u16,
+                                length,
+                                reads: access != "write",
+                                writes: access != "read",
+                            })
+                            .ok_or_else(|| format!("{} is not a memory range", id)),
+                        None => Expression::parse(id).map(|expression| Watch::Value {
+                            expression,
+                            value: 0,
+                        }),
+                    };
+                    breakpoints.push(match watch {
+                        Ok(watch) => {
+                            self.debugger.watch(cpu, watch);
+                            Json::object([("verified", true.into())])
+                        }
+                        Err(message) => {
+                            Json::object([("verified", false.into()), ("message", message.into())])
+                        }
+                    });
+                }
+                Json::object([("breakpoints", breakpoints.into())])
+            }
+            "threads" => Json::object([(
+                "threads",
+                vec![Json::object([
+                    ("id", 1i64.into()),
+                    ("name", "CHIP-8".into()),
+                ])]
+                .into(),
+            )]),
+            "stackTrace" => {
+                // The frames are PC and then each call on the stack, innermost
+                // first.
+                let stack = &cpu.stack[..cpu.stack_pointer as usize];
+                let addresses = std::iter::once(cpu.program_counter)
+                    .chain(stack.iter().rev().map(|&ret| ret.wrapping_sub(2)));
+                let frames: Vec<Json> = addresses
+                    .enumerate()
+                    .map(|(id, address)| self.frame(id, address))
+                    .collect();
+                let total = frames.len();
+                Json::object([
+                    ("stackFrames", frames.into()),
+                    ("totalFrames", total.into()),
+                ])
+            }
+            "scopes" => {
+                let scope = |name: &str, reference: i64| {
+                    Json::object([
+                        ("name", name.into()),
+                        ("variablesReference", reference.into()),
+                        ("expensive", false.into()),
+                    ])
+                };
+                Json::object([(
+                    "scopes",
+                    vec![
+                        scope("Registers", REGISTERS),
+                        scope("Timers", TIMERS),
+                        scope("Stack", STACK),
+                        scope("Memory", MEMORY),
+                    ]
+                    .into(),
+                )])
+            }
+            "variables" => {
+                let reference = args.get("variablesReference").as_i64().unwrap_or(0);
+                Json::object([("variables", variables(cpu, reference).into())])
+            }
+            "evaluate" => {
+                let text = args.get("expression").as_str().unwrap_or_default();
+                let value = Expression::parse(text)?.evaluate(cpu);
+                Json::object([
+                    ("result", format!("{:#X} ({})", value, value).into()),
+                    ("variablesReference", 0i64.into()),
+                ])
+            }
+            "continue" => {
+                self.debugger.resume(cpu);
+                self.state = SessionState::Running;
+                Json::object([("allThreadsContinued", true.into())])
+            }
+            "next" | "stepIn" => {
+                let by_line = args.get("granularity").as_str() != Some("instruction");
+                self.step(cpu, keypad, command == "next", by_line, events);
+                Json::Null
+            }
+            "stepBack" => {
+                let by_line = args.get("granularity").as_str() != Some("instruction");
+                let by_line = by_line && !self.debugger.symbols.lines.is_empty();
+                let start_line = self.debugger.symbols.line_at(cpu.program_counter);
+                let mut moved = false;
+                // Going back a line stops on the last instruction of the line
+                // before, which is usually the only one.
+                for _ in 0..MAX_LINE_STEPS {
+                    if !self.debugger.step_back(cpu) {
+                        break;
+                    }
+                    moved = true;
+                    let pc = cpu.program_counter;
+                    let line = self.debugger.symbols.line_at(pc);
+                    if !by_line || line.is_some_and(|line| Some(line) != start_line) {
+                        break;
+                    }
+                }
+                events.push(("stopped", self.history_stop(moved, "step")));
+                Json::Null
+            }
+            "reverseContinue" => {
+                let found = self.debugger.reverse_continue(cpu);
+                events.push(("stopped", self.history_stop(found, "breakpoint")));
+                Json::Null
+            }
+            "stepOut" => {
+                match cpu.stack_pointer.checked_sub(1) {
+                    Some(top) => {
+                        self.debugger.run_to = Some(cpu.stack[top as usize]);
+                        self.debugger.resume(cpu);
+                        self.state = SessionState::Running;
+                    }
+                    None => self.step(cpu, keypad, false, true, events),
+                }
+                Json::Null
+            }
+            "pause" => {
+                self.state = SessionState::Stopped;
+                events.push(("stopped", stopped_body("pause", None)));
+                Json::Null
+            }
+            "readMemory" => {
+                let address = parse_reference(args.get("memoryReference"))
+                    .ok_or("Not a memory reference")?
+                    .checked_add(args.get("offset").as_i64().unwrap_or(0))
+                    .ok_or("The offset is out of range")?;
+                let count = args.get("count").as_i64().unwrap_or(0).max(0) as usize;
+                let size = cpu.platform.memory_size();
+                let start = (address.max(0) as usize).min(size);
+                let end = start.saturating_add(count).min(size);
+                Json::object([
+                    ("address", format!("{:#06x}", start).into()),
+                    ("data", base64(&cpu.memory[start..end]).into()),
+                    ("unreadableBytes", (count - (end - start)).into()),
+                ])
+            }
+            "disassemble" => {
+                let address =
+                    parse_reference(args.get("memoryReference")).ok_or("Not a memory reference")?;
+                let offset = args.get("offset").as_i64().unwrap_or(0);
+                let instruction_offset = args.get("instructionOffset").as_i64().unwrap_or(0);
+                let address = instruction_offset
+                    .checked_mul(2)
+                    .and_then(|bytes| bytes.checked_add(offset))
+                    .and_then(|offset| address.checked_add(offset))
+                    .ok_or("The offset is out of range")?;
+                let count = args.get("instructionCount").as_i64().unwrap_or(0);
+                if count > MAX_DISASSEMBLY {
+                    return Err(format!(
+                        "Can't disassemble more than {} instructions",
+                        MAX_DISASSEMBLY
+                    ));
+                }
+                let count = count.max(0);
+                let instructions = self.disassemble(cpu, address, count as usize);
+                Json::object([("instructions", instructions.into())])
+            }
+            "disconnect" | "terminate" => {
+                self.state = SessionState::Terminated;
+                events.push(("terminated", Json::Null));
+                Json::Null
+            }
+            _ => return Err(format!("{} is not supported", command)),
+        };
+        Ok(body)
+    }
+
+    /// Runs one instruction, or with `by_line` carries on to the start of
+    /// the next source line. With `over`, a call runs through to its return.
+    fn step(
+        &mut self,
+        cpu: &mut CPU<R>,
+        keypad: [bool; 16],
+        over: bool,
+        by_line: bool,
+        events: &mut Vec<(&'static str, Json)>,
+    ) {
+        let by_line = by_line && !self.debugger.symbols.lines.is_empty();
+        let start_line = self.debugger.symbols.line_at(cpu.program_counter);
+        for _ in 0..MAX_LINE_STEPS {
+            let pc = cpu.program_counter;
+            if over && matches!(cpu.fetch(pc), Ok(0x2000..=0x2FFF)) {
+                self.debugger.run_to = Some(pc.wrapping_add(2));
+                self.debugger.resume(cpu);
+                self.state = SessionState::Running;
+                return;
+            }
+            match self.debugger.tick(cpu, keypad) {
+                Ok(StepOutcome::Executed) => (),
+                result => {
+                    events.extend(self.outcome(result));
+                    return;
+                }
+            }
+            let pc = cpu.program_counter;
+            let new_line = self
+                .debugger
+                .symbols
+                .line_at(pc)
+                .is_some_and(|line| Some(line) != start_line);
+            let watched = self.debugger.watch_hit.is_some();
+            if !by_line || new_line || watched || self.debugger.breakpoints.contains(&pc) {
+                break;
+            }
+        }
+        events.push(("stopped", self.stopped_body("step")));
+    }
+
+    /// The body of the stopped event after going backwards, which says so if
+    /// the start of the history got in the way.
+    fn history_stop(&mut self, moved: bool, reason: &str) -> Json {
+        if moved {
+            return self.stopped_body(reason);
+        }
+        let text = "Reached the start of the recorded history".to_string();
+        stopped_body("step", Some(text))
+    }
+
+    /// The body of a stopped event, which says which watchpoint went off if
+    /// one did.
+    fn stopped_body(&mut self, reason: &str) -> Json {
+        match self.debugger.watch_hit.take() {
+            Some(hit) => stopped_body("data breakpoint", Some(hit.message)),
+            None => stopped_body(reason, None),
+        }
+    }
+
+    /// Stops or ends the session for the result of running an instruction,
+    /// returning the events that say so.
+    fn outcome(&mut self, result: Result<StepOutcome, CpuFault>) -> Vec<(&'static str, Json)> {
+        match result {
+            Ok(StepOutcome::Exited) => {
+                self.state = SessionState::Terminated;
+                vec![
+                    ("exited", Json::object([("exitCode", 0i64.into())])),
+                    ("terminated", Json::Null),
+                ]
+            }
+            Ok(_) => {
+                self.state = SessionState::Stopped;
+                vec![("stopped", stopped_body("step", None))]
+            }
+            Err(fault) => {
+                self.state = SessionState::Stopped;
+                let body = stopped_body("exception", Some(fault.to_string()));
+                vec![("stopped", body)]
+            }
+        }
+    }
+
+    fn update_breakpoints(&mut self) {
+        self.debugger.breakpoints.clear();
+        self.debugger.conditions.clear();
+        let all = self
+            .source_breakpoints
+            .iter()
+            .chain(&self.instruction_breakpoints);
+        for (&address, condition) in all {
+            let first = self.debugger.breakpoints.insert(address);
+            // A breakpoint set both ways stops when either condition holds,
+            // and every time unless both have a condition.
+            let condition = match (first, condition) {
+                (true, Some(condition)) => Some(condition.clone()),
+                (false, Some(condition)) => {
+                    self.debugger.conditions.get(&address).and_then(|other| {
+                        Expression::parse(&format!("({}) || ({})", other, condition)).ok()
+                    })
+                }
+                (_, None) => None,
+            };
+            match condition {
+                Some(condition) => self.debugger.conditions.insert(address, condition),
+                None => self.debugger.conditions.remove(&address),
+            };
+        }
+    }
+
+    fn frame(&self, id: usize, address: u16) -> Json {
+        let mut frame = vec![
+            ("id".to_string(), id.into()),
+            (
+                "name".to_string(),
+                self.debugger.symbols.describe(address).into(),
+            ),
+            ("column".to_string(), 1i64.into()),
+            (
+                "instructionPointerReference".to_string(),
+                reference(address).into(),
+            ),
+        ];
+        match (
+            self.debugger.symbols.line_at(address),
+            &self.debugger.symbols.file,
+        ) {
+            (Some(line), Some(file)) => {
+                frame.push(("line".to_string(), line.into()));
+                frame.push(("source".to_string(), source(file)));
+            }
+            _ => frame.push(("line".to_string(), 0i64.into())),
+        }
+        Json::Object(frame)
+    }
+
+    fn disassemble(&self, cpu: &CPU<R>, address: i64, count: usize) -> Vec<Json> {
+        let map = RomMap {
+            base: 0,
+            kinds: Vec::new(),
+            labels: BTreeMap::new(),
+            names: self.debugger.symbols.labels.clone(),
+        };
+        let mut instructions = Vec::new();
+        let mut address = address;
+        for _ in 0..count {
+            let opcode = u16::try_from(address).ok().and_then(|a| cpu.fetch(a).ok());
+            let Some(opcode) = opcode else {
+                // Editors ask for instructions either side of the one they
+                // show, which may be outside memory.
+                instructions.push(Json::object([
+                    ("address", format!("{:#06x}", address.max(0)).into()),
+                    ("instruction", "??".into()),
+                    ("presentationHint", "invalid".into()),
+                ]));
+                address = address.saturating_add(2);
+                continue;
+            };
+            let at = address as u16;
+            let decoded = Instruction::decode(opcode, cpu.platform);
+            let text = match &decoded {
+                Some(instruction) => {
+                    let operand = cpu.fetch(at.wrapping_add(2)).ok();
+                    mnemonic(instruction, operand, Syntax::Cowgod, &map)
+                }
+                None => "???".to_string(),
+            };
+            let size = decoded.map_or(2, |instruction| instruction.size());
+            let bytes: Vec<String> = (0..size)
+                .map(|i| {
+                    format!(
+                        "{:02X}",
+                        cpu.memory[at.wrapping_add(i) as usize % cpu.memory.len()]
+                    )
+                })
+                .collect();
+            let mut instruction = vec![
+                ("address".to_string(), reference(at).into()),
+                ("instructionBytes".to_string(), bytes.join(" ").into()),
+                ("instruction".to_string(), text.into()),
+            ];
+            if let Some(label) = self.debugger.symbols.labels.get(&at) {
+                instruction.push(("symbol".to_string(), label.as_str().into()));
+            }
+            if let (Some(line), Some(file)) = (
+                self.debugger.symbols.line_at(at),
+                &self.debugger.symbols.file,
+            ) {
+                instruction.push(("line".to_string(), line.into()));
+                instruction.push(("location".to_string(), source(file)));
+            }
+            instructions.push(Json::Object(instruction));
+            address = address.saturating_add(size as i64);
+        }
+        instructions
+    }
+
+    fn event(&mut self, output: &mut Vec<u8>, event: &str, body: Json) {
+        let mut message = vec![
+            ("seq".to_string(), Json::Null),
+            ("type".to_string(), "event".into()),
+            ("event".to_string(), event.into()),
+        ];
+        if body != Json::Null {
+            message.push(("body".to_string(), body));
+        }
+        self.send(output, Json::Object(message));
+    }
+
+    /// Numbers and frames a message.
+    fn send(&mut self, output: &mut Vec<u8>, mut message: Json) {
+        self.seq += 1;
+        if let Json::Object(members) = &mut message {
+            members[0].1 = self.seq.into();
+        }
+        let text = message.to_string();
+        output.extend(format!("Content-Length: {}\r\n\r\n{}", text.len(), text).bytes());
+    }
+}
+
+fn stopped_body(reason: &str, text: Option<String>) -> Json {
+    let mut body = vec![
+        ("reason".to_string(), reason.into()),
+        ("threadId".to_string(), 1i64.into()),
+        ("allThreadsStopped".to_string(), true.into()),
+    ];
+    if let Some(text) = text {
+        body.push(("text".to_string(), text.into()));
+    }
+    Json::Object(body)
+}
+
+/// The condition on a source or instruction breakpoint, if it has one.
+fn condition(breakpoint: &Json) -> Result<Option<Expression>, String> {
+    match breakpoint.get("condition").as_str() {
+        Some(text) if !text.trim().is_empty() => Expression::parse(text).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn output_body(message: &str) -> Json {
+    Json::object([
+        ("category", "stderr".into()),
+        ("output", format!("{}\n", message).into()),
+    ])
+}
+
+/// The variables in one of the scopes.
+fn variables(cpu: &CPU<impl RandomSource>, scope: i64) -> Vec<Json> {
+    let variable = |name: String, value: String, memory: Option<u16>| {
+        let mut variable = vec![
+            ("name".to_string(), name.into()),
+            ("value".to_string(), value.into()),
+            ("variablesReference".to_string(), 0i64.into()),
+        ];
+        if let Some(address) = memory {
+            variable.push(("memoryReference".to_string(), reference(address).into()));
+        }
+        Json::Object(variable)
+    };
+    match scope {
+        REGISTERS => {
+            let mut variables: Vec<Json> = cpu
+                .registers
+                .iter()
+                .enumerate()
+                .map(|(i, value)| variable(format!("V{:X}", i), format!("{:#04X}", value), None))
+                .collect();
+            variables.push(variable(
+                "I".to_string(),
+                format!("{:#06X}", cpu.index),
+                Some(cpu.index),
+            ));
+            variables.push(variable(
+                "PC".to_string(),
+                format!("{:#06X}", cpu.program_counter),
+                Some(cpu.program_counter),
+            ));
+            variables.push(variable(
+                "SP".to_string(),
+                cpu.stack_pointer.to_string(),
+                None,
+            ));
+            variables
+        }
+        TIMERS => vec![
+            variable("DT".to_string(), cpu.delay_timer.to_string(), None),
+            variable("ST".to_string(), cpu.sound_timer.to_string(), None),
+        ],
+        STACK => cpu.stack[..cpu.stack_pointer as usize]
+            .iter()
+            .enumerate()
+            .map(|(i, &address)| {
+                variable(i.to_string(), format!("{:#06X}", address), Some(address))
+            })
+            .collect(),
+        MEMORY => {
+            let start = cpu.index as usize;
+            let end = (start + MEMORY_WINDOW).min(cpu.platform.memory_size());
+            (start..end)
+                .step_by(8)
+                .map(|row| {
+                    let bytes: Vec<String> = cpu.memory[row..(row + 8).min(end)]
+                        .iter()
+                        .map(|byte| format!("{:02X}", byte))
+                        .collect();
+                    variable(format!("{:#06X}", row), bytes.join(" "), Some(row as u16))
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn source(path: &str) -> Json {
+    let name = Path::new(path)
+        .file_name()
+        .map_or(path.to_string(), |name| name.to_string_lossy().to_string());
+    Json::object([("name", name.into()), ("path", path.into())])
+}
+
+/// Whether two paths name the same file, however they are written.
+fn same_file(a: &str, b: &str) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Addresses are passed to the editor as memory and instruction references.
+fn reference(address: u16) -> String {
+    format!("{:#06x}", address)
+}
+
+fn parse_reference(reference: &Json) -> Option<i64> {
+    let text = reference.as_str()?;
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (i, &byte)| {
+            value | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(value >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
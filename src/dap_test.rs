@@ -0,0 +1,365 @@
+#[cfg(test)]
+use crate::dap::{DapServer, SessionState};
+#[cfg(test)]
+use crate::json::Json;
+#[cfg(test)]
+use crate::platform::Platform;
+#[cfg(test)]
+use crate::processor::CPU;
+#[cfg(test)]
+use crate::quirks::Quirks;
+#[cfg(test)]
+use crate::random::SeededRandom;
+
+#[cfg(test)]
+const SOURCE: &str = "\
+: main
+  v0 := 5
+  add-one
+  loop
+    i := main
+  again
+
+: add-one
+  v0 += 1 v1 := 2
+;
+";
+
+#[cfg(test)]
+fn new_cpu() -> CPU<SeededRandom> {
+    CPU::with_rng(Platform::Chip8, Quirks::default(), SeededRandom::new(1))
+}
+
+/// Sends a request and returns the messages that come back.
+#[cfg(test)]
+fn send(
+    server: &mut DapServer<SeededRandom>,
+    cpu: &mut CPU<SeededRandom>,
+    command: &str,
+    arguments: &str,
+) -> Vec<Json> {
+    let request = format!(
+        "{{\"seq\":1,\"type\":\"request\",\"command\":\"{}\",\"arguments\":{}}}",
+        command, arguments
+    );
+    let bytes = format!("Content-Length: {}\r\n\r\n{}", request.len(), request);
+    messages(&server.receive(cpu, [false; 16], bytes.as_bytes()))
+}
+
+#[cfg(test)]
+fn messages(output: &[u8]) -> Vec<Json> {
+    let mut text = std::str::from_utf8(output).unwrap();
+    let mut messages = Vec::new();
+    while let Some(rest) = text.strip_prefix("Content-Length: ") {
+        let (length, rest) = rest.split_once("\r\n\r\n").unwrap();
+        let length: usize = length.parse().unwrap();
+        messages.push(Json::parse(&rest[..length]).unwrap());
+        text = &rest[length..];
+    }
+    assert!(text.is_empty());
+    messages
+}
+
+/// Launches `SOURCE` from a file.
+#[cfg(test)]
+fn launch(cpu: &mut CPU<SeededRandom>, name: &str) -> (DapServer<SeededRandom>, String) {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, SOURCE).unwrap();
+    let path = path.to_string_lossy().to_string();
+    let mut server = DapServer::new();
+    send(&mut server, cpu, "initialize", "{}");
+    let arguments = format!("{{\"program\":{}}}", Json::from(path.as_str()));
+    let replies = send(&mut server, cpu, "launch", &arguments);
+    assert_eq!(replies[0].get("success"), &Json::Bool(true));
+    assert_eq!(replies[1].get("event").as_str(), Some("initialized"));
+    (server, path)
+}
+
+#[test]
+fn test_source_breakpoints_stop_at_their_line() {
+    let mut cpu = new_cpu();
+    let (mut server, path) = launch(&mut cpu, "rusty8_dap_breakpoints.8o");
+    let arguments = format!(
+        "{{\"source\":{{\"path\":{}}},\"breakpoints\":[{{\"line\":7}},{{\"line\":20}}]}}",
+        Json::from(path.as_str())
+    );
+    let replies = send(&mut server, &mut cpu, "setBreakpoints", &arguments);
+    let breakpoints = replies[0].get("body").get("breakpoints").as_array();
+    // Line 7 is blank, so the breakpoint moves to the first instruction after.
+    assert_eq!(breakpoints[0].get("verified"), &Json::Bool(true));
+    assert_eq!(breakpoints[0].get("line").as_i64(), Some(9));
+    assert_eq!(breakpoints[1].get("verified"), &Json::Bool(false));
+
+    // Breakpoints in another file leave the program's alone.
+    let arguments = "{\"source\":{\"path\":\"other.8o\"},\"breakpoints\":[{\"line\":9}]}";
+    let replies = send(&mut server, &mut cpu, "setBreakpoints", arguments);
+    let breakpoints = replies[0].get("body").get("breakpoints").as_array();
+    assert_eq!(breakpoints[0].get("verified"), &Json::Bool(false));
+    assert_eq!(server.debugger.breakpoints.len(), 1);
+
+    send(&mut server, &mut cpu, "configurationDone", "{}");
+    assert_eq!(server.state, SessionState::Running);
+    let stopped = loop {
+        if let Some(output) = server.should_break(&cpu) {
+            break messages(&output);
+        }
+        cpu.tick([false; 16]).unwrap();
+    };
+    assert_eq!(
+        stopped[0].get("body").get("reason").as_str(),
+        Some("breakpoint")
+    );
+
+    let replies = send(&mut server, &mut cpu, "stackTrace", "{\"threadId\":1}");
+    let frames = replies[0].get("body").get("stackFrames").as_array();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].get("name").as_str(), Some("add-one"));
+    assert_eq!(frames[0].get("line").as_i64(), Some(9));
+    assert_eq!(
+        frames[0].get("source").get("path").as_str(),
+        Some(path.as_str())
+    );
+    assert_eq!(frames[1].get("name").as_str(), Some("main+2"));
+    assert_eq!(frames[1].get("line").as_i64(), Some(3));
+}
+
+#[test]
+fn test_steps_go_a_line_at_a_time() {
+    let mut cpu = new_cpu();
+    let (mut server, _) = launch(&mut cpu, "rusty8_dap_steps.8o");
+    send(&mut server, &mut cpu, "configurationDone", "{}");
+    send(&mut server, &mut cpu, "pause", "{\"threadId\":1}");
+    assert_eq!(server.state, SessionState::Stopped);
+
+    let replies = send(&mut server, &mut cpu, "stepIn", "{\"threadId\":1}");
+    assert_eq!(replies[1].get("body").get("reason").as_str(), Some("step"));
+    assert_eq!(cpu.program_counter, 0x202);
+    send(&mut server, &mut cpu, "stepIn", "{\"threadId\":1}");
+    assert_eq!(cpu.program_counter, 0x208);
+    // Both instructions on line 9 run in one step.
+    send(&mut server, &mut cpu, "stepIn", "{\"threadId\":1}");
+    assert_eq!(cpu.program_counter, 0x20C);
+    assert_eq!(cpu.registers[..2], [6, 2]);
+    let arguments = "{\"threadId\":1,\"granularity\":\"instruction\"}";
+    send(&mut server, &mut cpu, "stepIn", arguments);
+    assert_eq!(cpu.program_counter, 0x204);
+
+    // Stepping over the call runs it through to the return.
+    cpu.program_counter = 0x202;
+    send(&mut server, &mut cpu, "next", "{\"threadId\":1}");
+    assert_eq!(server.state, SessionState::Running);
+    while server.should_break(&cpu).is_none() {
+        cpu.tick([false; 16]).unwrap();
+    }
+    assert_eq!(cpu.program_counter, 0x204);
+    assert_eq!(cpu.registers[0], 7);
+}
+
+#[test]
+fn test_variables_and_memory() {
+    let mut cpu = new_cpu();
+    let (mut server, _) = launch(&mut cpu, "rusty8_dap_variables.8o");
+    cpu.delay_timer = 30;
+    let replies = send(
+        &mut server,
+        &mut cpu,
+        "variables",
+        "{\"variablesReference\":1}",
+    );
+    let registers = replies[0].get("body").get("variables").as_array();
+    assert_eq!(registers.len(), 19);
+    assert_eq!(registers[17].get("name").as_str(), Some("PC"));
+    assert_eq!(registers[17].get("value").as_str(), Some("0x0200"));
+    assert_eq!(
+        registers[17].get("memoryReference").as_str(),
+        Some("0x0200")
+    );
+    let replies = send(
+        &mut server,
+        &mut cpu,
+        "variables",
+        "{\"variablesReference\":2}",
+    );
+    let timers = replies[0].get("body").get("variables").as_array();
+    assert_eq!(timers[0].get("value").as_str(), Some("30"));
+
+    let arguments = "{\"memoryReference\":\"0x0200\",\"offset\":0,\"count\":4}";
+    let replies = send(&mut server, &mut cpu, "readMemory", arguments);
+    // 60 05 22 08
+    assert_eq!(
+        replies[0].get("body").get("data").as_str(),
+        Some("YAUiCA==")
+    );
+
+    let arguments = "{\"memoryReference\":\"0x0208\",\"instructionCount\":2}";
+    let replies = send(&mut server, &mut cpu, "disassemble", arguments);
+    let instructions = replies[0].get("body").get("instructions").as_array();
+    assert_eq!(
+        instructions[0].get("instruction").as_str(),
+        Some("ADD V0, 0x01")
+    );
+    assert_eq!(instructions[0].get("symbol").as_str(), Some("add-one"));
+    assert_eq!(instructions[1].get("line").as_i64(), Some(9));
+
+    // Offsets and counts from the editor can't overflow.
+    let arguments = "{\"memoryReference\":\"0x0200\",\"instructionOffset\":5e18}";
+    let replies = send(&mut server, &mut cpu, "disassemble", arguments);
+    assert_eq!(replies[0].get("success"), &Json::Bool(false));
+    let arguments = "{\"memoryReference\":\"0x0200\",\"instructionCount\":1e9}";
+    let replies = send(&mut server, &mut cpu, "disassemble", arguments);
+    assert_eq!(replies[0].get("success"), &Json::Bool(false));
+    let arguments = "{\"memoryReference\":\"0x7fffffffffffffff\",\"offset\":1}";
+    let replies = send(&mut server, &mut cpu, "readMemory", arguments);
+    assert_eq!(replies[0].get("success"), &Json::Bool(false));
+
+    let replies = send(&mut server, &mut cpu, "evaluate", "{}");
+    assert_eq!(replies[0].get("success"), &Json::Bool(false));
+    send(&mut server, &mut cpu, "disconnect", "{}");
+    assert_eq!(server.state, SessionState::Terminated);
+}
+
+#[test]
+fn test_conditions_and_data_breakpoints() {
+    let mut cpu = new_cpu();
+    let (mut server, path) = launch(&mut cpu, "rusty8_dap_conditions.8o");
+    let arguments = format!(
+        "{{\"source\":{{\"path\":{}}},\"breakpoints\":[{}]}}",
+        Json::from(path.as_str()),
+        "{\"line\":5,\"condition\":\"v0 == 7\"},{\"line\":9,\"condition\":\"v0 ==\"}"
+    );
+    let replies = send(&mut server, &mut cpu, "setBreakpoints", &arguments);
+    let breakpoints = replies[0].get("body").get("breakpoints").as_array();
+    assert_eq!(breakpoints[0].get("verified"), &Json::Bool(true));
+    assert_eq!(breakpoints[1].get("verified"), &Json::Bool(false));
+
+    let arguments = "{\"variablesReference\":1,\"name\":\"V1\"}";
+    let replies = send(&mut server, &mut cpu, "dataBreakpointInfo", arguments);
+    assert_eq!(replies[0].get("body").get("dataId").as_str(), Some("v1"));
+    let arguments = "{\"breakpoints\":[{\"dataId\":\"v1\"},{\"dataId\":\"0x0300/4\"}]}";
+    send(&mut server, &mut cpu, "setDataBreakpoints", arguments);
+    assert_eq!(server.debugger.watchpoints.len(), 2);
+
+    send(&mut server, &mut cpu, "configurationDone", "{}");
+    let stopped = loop {
+        if let Some(output) = server.should_break(&cpu) {
+            break messages(&output);
+        }
+        server.debugger.tick(&mut cpu, [false; 16]).unwrap();
+    };
+    let body = stopped[0].get("body");
+    assert_eq!(body.get("reason").as_str(), Some("data breakpoint"));
+    assert_eq!(
+        body.get("text").as_str(),
+        Some("Watchpoint 1: v1 changed from 0x0 to 0x2")
+    );
+    let arguments = "{\"expression\":\"v0 + 1\"}";
+    let replies = send(&mut server, &mut cpu, "evaluate", arguments);
+    assert_eq!(
+        replies[0].get("body").get("result").as_str(),
+        Some("0x7 (7)")
+    );
+
+    // V0 is 6 in the loop, so the breakpoint on line 5 never stops it.
+    send(&mut server, &mut cpu, "continue", "{\"threadId\":1}");
+    for _ in 0..100 {
+        assert!(server.should_break(&cpu).is_none());
+        server.debugger.tick(&mut cpu, [false; 16]).unwrap();
+    }
+}
+
+#[test]
+fn test_breakpoints_set_both_ways() {
+    let mut cpu = new_cpu();
+    let (mut server, path) = launch(&mut cpu, "rusty8_dap_both_ways.8o");
+    let set_line =
+        |server: &mut DapServer<SeededRandom>, cpu: &mut CPU<SeededRandom>, condition: &str| {
+            let arguments = format!(
+                "{{\"source\":{{\"path\":{}}},\"breakpoints\":[{{\"line\":9{}}}]}}",
+                Json::from(path.as_str()),
+                condition
+            );
+            send(server, cpu, "setBreakpoints", &arguments);
+        };
+    let set_instruction =
+        |server: &mut DapServer<SeededRandom>, cpu: &mut CPU<SeededRandom>, condition: &str| {
+            let arguments = format!(
+                "{{\"breakpoints\":[{{\"instructionReference\":\"0x0208\"{}}}]}}",
+                condition
+            );
+            send(server, cpu, "setInstructionBreakpoints", &arguments);
+        };
+
+    // Both conditional: either one stops it.
+    set_line(&mut server, &mut cpu, ",\"condition\":\"v0 == 7\"");
+    set_instruction(&mut server, &mut cpu, ",\"condition\":\"v1 == 9\"");
+    assert_eq!(
+        server.debugger.conditions[&0x208].to_string(),
+        "(v0 == 7) || (v1 == 9)"
+    );
+
+    // One unconditional: it always stops.
+    set_instruction(&mut server, &mut cpu, "");
+    assert!(server.debugger.breakpoints.contains(&0x208));
+    assert!(!server.debugger.conditions.contains_key(&0x208));
+    set_line(&mut server, &mut cpu, "");
+    set_instruction(&mut server, &mut cpu, ",\"condition\":\"v1 == 9\"");
+    assert!(!server.debugger.conditions.contains_key(&0x208));
+}
+
+#[test]
+fn test_json_round_trips() {
+    let text = r#"{"a":[1,-2.5,true,null],"b":"say \"hi\"\n","c":{}}"#;
+    let value = Json::parse(text).unwrap();
+    assert_eq!(value.get("b").as_str(), Some("say \"hi\"\n"));
+    assert_eq!(value.get("a").as_array()[1], Json::Number(-2.5));
+    assert_eq!(value.to_string(), text);
+    assert!(Json::parse("{\"a\":}").is_err());
+    let deepest = format!("{}{}", "[".repeat(64), "]".repeat(64));
+    assert!(Json::parse(&deepest).is_ok());
+    let nested = "[".repeat(200_000);
+    assert_eq!(
+        Json::parse(&nested),
+        Err("Too deeply nested at 64".to_string())
+    );
+}
+
+#[test]
+fn test_the_end_of_memory() {
+    let mut cpu = CPU::with_rng(Platform::XoChip, Quirks::XO_CHIP, SeededRandom::new(1));
+    cpu.memory[0xFFFE..].copy_from_slice(&[0xF0, 0x00]);
+    let mut server = DapServer::new();
+    let arguments = "{\"memoryReference\":\"0xFFFE\",\"instructionCount\":1}";
+    let replies = send(&mut server, &mut cpu, "disassemble", arguments);
+    let instructions = replies[0].get("body").get("instructions").as_array();
+    assert_eq!(
+        instructions[0].get("instructionBytes").as_str(),
+        Some("F0 00 00 00")
+    );
+
+    let arguments = "{\"memoryReference\":\"9223372036854775807\",\"instructionCount\":2}";
+    let replies = send(&mut server, &mut cpu, "disassemble", arguments);
+    let instructions = replies[0].get("body").get("instructions").as_array();
+    assert_eq!(instructions.len(), 2);
+    assert_eq!(instructions[1].get("instruction").as_str(), Some("??"));
+
+    let arguments = "{\"memoryReference\":\"0x10000\",\"count\":1}";
+    let replies = send(&mut server, &mut cpu, "readMemory", arguments);
+    let body = replies[0].get("body");
+    assert_eq!(body.get("address").as_str(), Some("0x10000"));
+    assert_eq!(body.get("unreadableBytes"), &Json::Number(1.0));
+}
+
+#[test]
+fn test_bad_headers_are_skipped() {
+    let mut cpu = new_cpu();
+    let mut server = DapServer::new();
+    let request = "{\"seq\":1,\"type\":\"request\",\"command\":\"threads\"}";
+    let bytes = format!(
+        "Content-Length: 18446744073709551615\r\n\r\n{}Content-Length: {}\r\n\r\n{}",
+        "\r\n\r\n".repeat(10_000),
+        request.len(),
+        request
+    );
+    let replies = messages(&server.receive(&mut cpu, [false; 16], bytes.as_bytes()));
+    assert_eq!(replies[0].get("command").as_str(), Some("threads"));
+}
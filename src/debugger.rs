@@ -0,0 +1,733 @@
+use crate::cfg::{self, AccessKind};
+use crate::disasm::{mnemonic, RomMap, Syntax};
+use crate::expr::Expression;
+use crate::fault::{CpuFault, StepOutcome};
+use crate::history::History;
+use crate::instruction::Instruction;
+use crate::processor::CPU;
+use crate::random::RandomSource;
+use crate::symbols::SymbolMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// What the frontend should do once a command has run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+    /// Stay paused and read another command.
+    Prompt,
+    /// Run until a breakpoint is hit or the user interrupts.
+    Run,
+    /// Stop the emulator.
+    Quit,
+}
+
+pub const HELP: &str = "\
+Addresses and values are hexadecimal or labels from the symbol file, such
+as main+4, except in expressions, which are written like C over V0-VF, I,
+PC, SP, DT, ST and [addr] for a memory byte, with decimal numbers unless
+they start 0x.
+  r                       show the registers
+  bt                      show the call stack
+  m <addr> [len]          dump memory
+  d [addr] [count]        disassemble, by default around PC
+  s [count]               step one or more instructions
+  n                       step, running calls through to their return
+  u <addr>                run until PC reaches an address
+  c                       continue running
+  rs [count]              step back one or more instructions
+  rc                      run backwards to the last breakpoint or watchpoint
+  b [addr] [if <expr>]    set a breakpoint, or list them
+  bc <addr>               clear a breakpoint
+  w [expr]                stop when a value changes, or list watchpoints
+  wr|ww|wa <addr> [len]   stop on reads, writes or either of memory
+  wc <n>                  clear a watchpoint
+  p <expr>                print the value of an expression
+  poke <addr> <byte>...   write memory
+  set <reg> <value>       set V0-VF, I, PC, SP, DT or ST
+  load <file> <addr>      load a file into memory
+  save <file> <addr> <len>  save memory to a file
+  sym <file>              load a symbol file
+  q                       quit
+";
+
+/// What a watchpoint looks out for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Watch {
+    /// A change in the value of an expression. `value` is what it was after
+    /// the last tick.
+    Value { expression: Expression, value: i64 },
+    /// An instruction reading or writing memory in a range.
+    Memory {
+        start: u16,
+        length: u16,
+        reads: bool,
+        writes: bool,
+    },
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watch::Value { expression, value } => write!(f, "{} = {:#X}", expression, value),
+            Watch::Memory {
+                start,
+                length,
+                reads,
+                writes,
+            } => {
+                let verb = match (reads, writes) {
+                    (true, true) => "access",
+                    (true, false) => "read",
+                    _ => "write",
+                };
+                let end = start.saturating_add(length.saturating_sub(1));
+                write!(f, "{} {:04X}-{:04X}", verb, start, end)
+            }
+        }
+    }
+}
+
+/// How many bytes from I the instruction at the CPU's PC reads or writes.
+/// Unlike `cfg::access`, this knows how many XO-CHIP planes a sprite is
+/// drawn to and the size of MegaChip sprites, so a sprite can run past 64K.
+pub fn memory_access(cpu: &CPU<impl RandomSource>) -> Option<(u32, AccessKind)> {
+    let instruction = Instruction::decode(cpu.fetch(cpu.program_counter).ok()?, cpu.platform)?;
+    let (length, kind) = cfg::access(&instruction, cpu.platform)?;
+    if kind != AccessKind::Sprite {
+        return Some((length as u32, kind));
+    }
+    #[cfg(feature = "megachip")]
+    if cpu.mega_mode {
+        // A width or height of zero stands for 256.
+        let width = match cpu.mega_renderer.sprite_width {
+            0 => 256,
+            width => width as u32,
+        };
+        let height = match cpu.mega_renderer.sprite_height {
+            0 => 256,
+            height => height as u32,
+        };
+        return Some((width * height, kind));
+    }
+    // Each selected plane takes its own copy of the sprite.
+    Some((length as u32 * cpu.renderer.planes.count_ones(), kind))
+}
+
+/// A watchpoint that went off.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub id: usize,
+    /// For memory watchpoints, what the instruction did and the first byte
+    /// it touched in the range.
+    pub access: Option<(AccessKind, u16)>,
+    pub message: String,
+}
+
+/// A machine-language monitor. The frontend feeds it commands while the
+/// program is paused and asks it before every tick whether to pause.
+#[derive(Clone, Debug)]
+pub struct Debugger<R: RandomSource> {
+    pub breakpoints: BTreeSet<u16>,
+    /// Conditions on breakpoints, which only stop the program when theirs
+    /// is true.
+    pub conditions: BTreeMap<u16, Expression>,
+    /// Watchpoints by number.
+    pub watchpoints: BTreeMap<usize, Watch>,
+    /// The watchpoint the last `tick` set off, which stops the program before
+    /// the next instruction. Frontends take it to report why they stopped.
+    pub watch_hit: Option<WatchHit>,
+    /// Where `n` and `u` stop, on top of the breakpoints.
+    pub run_to: Option<u16>,
+    /// The run so far, for stepping backwards.
+    pub history: History<R>,
+    /// Labels to show addresses by, which commands also take as addresses.
+    pub symbols: SymbolMap,
+    /// A breakpoint to pass over once, so continuing from one doesn't stop
+    /// straight away.
+    resume_from: Option<u16>,
+    last_watchpoint: usize,
+}
+
+impl<R: RandomSource> Default for Debugger<R> {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl<R: RandomSource> Debugger<R> {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            conditions: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            watch_hit: None,
+            run_to: None,
+            history: History::new(),
+            symbols: SymbolMap::new(),
+            resume_from: None,
+            last_watchpoint: 0,
+        }
+    }
+
+    /// Whether to pause before running the instruction at PC.
+    pub fn should_break(&mut self, cpu: &CPU<R>) -> bool {
+        if self.watch_hit.is_some() {
+            return true;
+        }
+        let pc = cpu.program_counter;
+        if self.resume_from.take() == Some(pc) {
+            return false;
+        }
+        if self.run_to == Some(pc) {
+            self.run_to = None;
+            return true;
+        }
+        self.breakpoints.contains(&pc)
+            && self
+                .conditions
+                .get(&pc)
+                .is_none_or(|condition| condition.evaluate(cpu) != 0)
+    }
+
+    /// Runs one instruction and checks the watchpoints against what it did.
+    pub fn tick(&mut self, cpu: &mut CPU<R>, keypad: [bool; 16]) -> Result<StepOutcome, CpuFault> {
+        self.history.record_tick(cpu, keypad);
+        let pc = cpu.program_counter;
+        let access = memory_access(cpu);
+        let index = cpu.index;
+        let outcome = cpu.tick(keypad)?;
+
+        let mut hits = Vec::new();
+        for (&id, watch) in self.watchpoints.iter_mut() {
+            match watch {
+                Watch::Value { expression, value } => {
+                    // Values are compared after every tick, so changes the
+                    // timers make between ticks are caught too.
+                    let new = expression.evaluate(cpu);
+                    if new != *value {
+                        hits.push(value_hit(id, expression, *value, new));
+                        *value = new;
+                    }
+                }
+                Watch::Memory { .. } if outcome == StepOutcome::Executed => {
+                    hits.extend(memory_hit(id, watch, pc, index, access, &self.symbols));
+                }
+                Watch::Memory { .. } => (),
+            }
+        }
+        if self.watch_hit.is_none() {
+            self.watch_hit = hits.into_iter().next();
+        }
+        Ok(outcome)
+    }
+
+    /// Adds a watchpoint, returning its number. A value watchpoint starts
+    /// from the value the expression has now.
+    pub fn watch(&mut self, cpu: &CPU<R>, mut watch: Watch) -> usize {
+        if let Watch::Value { expression, value } = &mut watch {
+            *value = expression.evaluate(cpu);
+        }
+        self.last_watchpoint += 1;
+        self.watchpoints.insert(self.last_watchpoint, watch);
+        self.last_watchpoint
+    }
+
+    /// Runs one command line.
+    pub fn command(
+        &mut self,
+        cpu: &mut CPU<R>,
+        line: &str,
+        keypad: [bool; 16],
+    ) -> Result<(String, Resume), String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, args)) = words.split_first() else {
+            return Ok((String::new(), Resume::Prompt));
+        };
+        let arg = |i: usize| -> Result<u32, String> {
+            let text = args
+                .get(i)
+                .ok_or_else(|| format!("{} needs more arguments", name))?;
+            match self.symbols.resolve(text) {
+                Some(address) => Ok(address as u32),
+                None => parse_hex(text),
+            }
+        };
+
+        let output = match name {
+            "r" | "regs" => registers(cpu, &self.symbols),
+            "bt" | "backtrace" => {
+                // PC, then the call each return address on the stack
+                // belongs to, innermost first.
+                let stack = &cpu.stack[..cpu.stack_pointer as usize];
+                std::iter::once(cpu.program_counter)
+                    .chain(stack.iter().rev().map(|&ret| ret.wrapping_sub(2)))
+                    .enumerate()
+                    .map(|(i, address)| format!("#{} {}\n", i, self.symbols.annotate(address)))
+                    .collect()
+            }
+            "m" | "mem" => {
+                let length = if args.len() > 1 { arg(1)? } else { 0x40 };
+                hexdump(cpu, arg(0)?, length)
+            }
+            "d" | "dis" => {
+                let pc = cpu.program_counter as u32;
+                let address = if args.is_empty() {
+                    pc.saturating_sub(6)
+                } else {
+                    arg(0)?
+                };
+                let count = if args.len() > 1 { arg(1)? } else { 8 };
+                self.disassemble(cpu, address as u16, count as usize)
+            }
+            "s" | "step" => {
+                let count = if args.is_empty() { 1 } else { arg(0)? };
+                let mut output = String::new();
+                for _ in 0..count {
+                    match self.tick(cpu, keypad).map_err(|fault| fault.to_string())? {
+                        StepOutcome::Executed => (),
+                        StepOutcome::Waiting => {
+                            return Ok(("Waiting\n".to_string(), Resume::Prompt))
+                        }
+                        StepOutcome::Exited => return Ok(("Exited\n".to_string(), Resume::Prompt)),
+                    }
+                    if let Some(hit) = self.watch_hit.take() {
+                        output = format!("{}\n", hit.message);
+                        break;
+                    }
+                }
+                output + &self.disassemble(cpu, cpu.program_counter, 1)
+            }
+            "n" | "next" => {
+                let pc = cpu.program_counter;
+                if let Ok(0x2000..=0x2FFF) = cpu.fetch(pc) {
+                    self.run_to = Some(pc.wrapping_add(2));
+                    return Ok((String::new(), self.resume(cpu)));
+                }
+                return self.command(cpu, "s", keypad);
+            }
+            "u" | "until" => {
+                self.run_to = Some(arg(0)? as u16);
+                return Ok((String::new(), self.resume(cpu)));
+            }
+            "c" | "continue" => return Ok((String::new(), self.resume(cpu))),
+            "rs" | "rstep" => {
+                let count = if args.is_empty() { 1 } else { arg(0)? };
+                for _ in 0..count {
+                    if !self.step_back(cpu) {
+                        return Ok(("At the start of the history\n".to_string(), Resume::Prompt));
+                    }
+                }
+                self.disassemble(cpu, cpu.program_counter, 1)
+            }
+            "rc" | "rcontinue" => {
+                let output = if self.reverse_continue(cpu) {
+                    self.watch_hit
+                        .take()
+                        .map_or(String::new(), |hit| format!("{}\n", hit.message))
+                } else {
+                    "At the start of the history\n".to_string()
+                };
+                output + &self.disassemble(cpu, cpu.program_counter, 1)
+            }
+            "b" | "break" if args.is_empty() => self
+                .breakpoints
+                .iter()
+                .map(|&address| {
+                    let name = self.symbols.annotate(address);
+                    match self.conditions.get(&address) {
+                        Some(condition) => format!("{} if {}\n", name, condition),
+                        None => format!("{}\n", name),
+                    }
+                })
+                .collect(),
+            "b" | "break" => {
+                let address = arg(0)? as u16;
+                let condition = match args.get(1..) {
+                    Some(["if", condition @ ..]) if !condition.is_empty() => {
+                        Some(Expression::parse(&condition.join(" "))?)
+                    }
+                    Some([]) | None => None,
+                    _ => return Err("Usage: b <addr> [if <expr>]".to_string()),
+                };
+                self.breakpoints.insert(address);
+                match condition {
+                    Some(condition) => self.conditions.insert(address, condition),
+                    None => self.conditions.remove(&address),
+                };
+                String::new()
+            }
+            "bc" | "clear" => {
+                let address = arg(0)? as u16;
+                if !self.breakpoints.remove(&address) {
+                    return Err(format!("No breakpoint at {}", args[0]));
+                }
+                self.conditions.remove(&address);
+                String::new()
+            }
+            "w" | "watch" if args.is_empty() => self
+                .watchpoints
+                .iter()
+                .map(|(id, watch)| format!("{:X}: {}\n", id, watch))
+                .collect(),
+            "w" | "watch" => {
+                let expression = Expression::parse(&args.join(" "))?;
+                let watch = Watch::Value {
+                    expression,
+                    value: 0,
+                };
+                let id = self.watch(cpu, watch);
+                format!("Watchpoint {:X}: {}\n", id, self.watchpoints[&id])
+            }
+            "wr" | "ww" | "wa" => {
+                let start = arg(0)?;
+                let length = if args.len() > 1 { arg(1)? } else { 1 };
+                // The length has to fit in a u16 as well as the range in memory.
+                let end = start.checked_add(length);
+                if length == 0 || length > 0xFFFF || end.is_none_or(|end| end > 0x10000) {
+                    return Err(format!("Cannot watch {:X} bytes at {:04X}", length, start));
+                }
+                let watch = Watch::Memory {
+                    start: start as u16,
+                    length: length as u16,
+                    reads: name != "ww",
+                    writes: name != "wr",
+                };
+                let id = self.watch(cpu, watch);
+                format!("Watchpoint {:X}: {}\n", id, self.watchpoints[&id])
+            }
+            "wc" => {
+                if self.watchpoints.remove(&(arg(0)? as usize)).is_none() {
+                    return Err(format!("No watchpoint {}", args[0]));
+                }
+                String::new()
+            }
+            "p" | "print" => {
+                let value = Expression::parse(&args.join(" "))?.evaluate(cpu);
+                format!("{:#X} ({})\n", value, value)
+            }
+            "poke" => {
+                let address = arg(0)? as usize;
+                for i in 1..args.len().max(2) {
+                    let byte = arg(i)?;
+                    let target = address + i - 1;
+                    if byte > 0xFF || target >= cpu.platform.memory_size() {
+                        return Err(format!("Cannot write {:X} to {:04X}", byte, target));
+                    }
+                    cpu.memory[target] = byte as u8;
+                }
+                self.history.edited();
+                String::new()
+            }
+            "set" => {
+                let register = args.first().ok_or("set needs a register")?;
+                set_register(cpu, register, arg(1)?)?;
+                self.history.edited();
+                String::new()
+            }
+            "load" => {
+                let path = args.first().ok_or("load needs a file")?;
+                let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+                let address = arg(1)? as usize;
+                let end = address + data.len();
+                if end > cpu.platform.memory_size() {
+                    return Err(format!("{} does not fit at {:04X}", path, address));
+                }
+                cpu.memory[address..end].copy_from_slice(&data);
+                self.history.edited();
+                format!("Loaded {:X} bytes at {:04X}\n", data.len(), address)
+            }
+            "save" => {
+                let path = args.first().ok_or("save needs a file")?;
+                let address = arg(1)? as usize;
+                let end = address + arg(2)? as usize;
+                if end > cpu.platform.memory_size() {
+                    return Err(format!("{:04X}-{:04X} is outside memory", address, end));
+                }
+                std::fs::write(path, &cpu.memory[address..end])
+                    .map_err(|e| format!("{}: {}", path, e))?;
+                String::new()
+            }
+            "sym" | "symbols" => {
+                let path = args.first().ok_or("sym needs a file")?;
+                self.symbols = SymbolMap::load(path)?;
+                format!("Loaded {} labels\n", self.symbols.labels.len())
+            }
+            "q" | "quit" => return Ok((String::new(), Resume::Quit)),
+            "h" | "help" | "?" => HELP.to_string(),
+            _ => return Err(format!("Unknown command {}; try help", name)),
+        };
+        Ok((output, Resume::Prompt))
+    }
+
+    /// Runs the timers down, recording it for going backwards.
+    pub fn tick_60hz(&mut self, cpu: &mut CPU<R>) {
+        self.history.record_frame(cpu);
+        cpu.tick_60hz();
+    }
+
+    /// Goes back one instruction. Returns `false` at the start of the
+    /// history.
+    pub fn step_back(&mut self, cpu: &mut CPU<R>) -> bool {
+        let moved = self.history.step_back(cpu);
+        self.moved(cpu);
+        moved
+    }
+
+    /// Runs backwards to the last place the program would have stopped going
+    /// forwards: a breakpoint whose condition held, or just after an
+    /// instruction that set off a watchpoint, which is then in `watch_hit`.
+    /// Returns `false` if there was none, leaving the program at the start of
+    /// the history.
+    pub fn reverse_continue(&mut self, cpu: &mut CPU<R>) -> bool {
+        let now = self.history.ticks();
+        let (breakpoints, conditions) = (&self.breakpoints, &self.conditions);
+        let (watchpoints, symbols) = (&self.watchpoints, &self.symbols);
+        let mut found: Option<(u64, Option<WatchHit>)> = None;
+        let mut previous: Option<Vec<i64>> = None;
+        let visit = |ticks: u64, cpu: &CPU<R>| {
+            let values: Vec<i64> = watchpoints
+                .values()
+                .map(|watch| match watch {
+                    Watch::Value { expression, .. } => expression.evaluate(cpu),
+                    Watch::Memory { .. } => 0,
+                })
+                .collect();
+            for (i, (&id, watch)) in watchpoints.iter().enumerate() {
+                let old = previous.as_ref().map_or(values[i], |previous| previous[i]);
+                match watch {
+                    Watch::Value { expression, .. } if old != values[i] => {
+                        found = Some((ticks, Some(value_hit(id, expression, old, values[i]))));
+                    }
+                    Watch::Memory { .. } if ticks + 1 < now => {
+                        let (pc, index, access) =
+                            (cpu.program_counter, cpu.index, memory_access(cpu));
+                        if let Some(hit) = memory_hit(id, watch, pc, index, access, symbols) {
+                            found = Some((ticks + 1, Some(hit)));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            previous = Some(values);
+            let pc = cpu.program_counter;
+            if breakpoints.contains(&pc)
+                && conditions
+                    .get(&pc)
+                    .is_none_or(|condition| condition.evaluate(cpu) != 0)
+            {
+                found = Some((ticks, None));
+            }
+        };
+        self.history.scan(cpu, visit);
+        let target = match &found {
+            Some((ticks, _)) => *ticks,
+            None => self.history.range().map_or(now, |(first, _)| first),
+        };
+        self.history.seek(cpu, target);
+        self.moved(cpu);
+        match found {
+            Some((_, hit)) => {
+                self.watch_hit = hit;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lets the program run on from PC, even if there is a breakpoint there.
+    /// Value watchpoints start again from the current values, so changes
+    /// made while paused don't set them off.
+    pub fn resume(&mut self, cpu: &CPU<R>) -> Resume {
+        self.resume_from = Some(cpu.program_counter);
+        self.watch_hit = None;
+        self.refresh_watches(cpu);
+        Resume::Run
+    }
+
+    /// Forgets what the program was doing before it was moved backwards.
+    fn moved(&mut self, cpu: &CPU<R>) {
+        self.run_to = None;
+        self.resume_from = None;
+        self.watch_hit = None;
+        self.refresh_watches(cpu);
+    }
+
+    fn refresh_watches(&mut self, cpu: &CPU<R>) {
+        for watch in self.watchpoints.values_mut() {
+            if let Watch::Value { expression, value } = watch {
+                *value = expression.evaluate(cpu);
+            }
+        }
+    }
+
+    /// Lists `count` instructions from `address`, marking PC with `>` and
+    /// breakpoints with `*`.
+    pub fn disassemble(&self, cpu: &CPU<R>, address: u16, count: usize) -> String {
+        let map = RomMap {
+            base: 0,
+            kinds: Vec::new(),
+            labels: BTreeMap::new(),
+            names: self.symbols.labels.clone(),
+        };
+        let mut output = String::new();
+        let mut address = address;
+        for _ in 0..count {
+            let Ok(opcode) = cpu.fetch(address) else {
+                break;
+            };
+            let instruction = Instruction::decode(opcode, cpu.platform);
+            let text = match &instruction {
+                Some(instruction) => {
+                    let operand = cpu.fetch(address.wrapping_add(2)).ok();
+                    mnemonic(instruction, operand, Syntax::Cowgod, &map)
+                }
+                None => "???".to_string(),
+            };
+            let pc = if address == cpu.program_counter {
+                '>'
+            } else {
+                ' '
+            };
+            let breakpoint = if self.breakpoints.contains(&address) {
+                '*'
+            } else {
+                ' '
+            };
+            output.push_str(&format!(
+                "{}{}{}  {:04X}  {}\n",
+                pc,
+                breakpoint,
+                self.symbols.annotate(address),
+                opcode,
+                text
+            ));
+            address = address.wrapping_add(instruction.map_or(2, |i| i.size()));
+        }
+        output
+    }
+}
+
+/// Formats the registers, timers and stack, naming addresses by `symbols`.
+pub fn registers(cpu: &CPU<impl RandomSource>, symbols: &SymbolMap) -> String {
+    let mut output = String::new();
+    for (i, value) in cpu.registers.iter().enumerate() {
+        output.push_str(&format!("V{:X}={:02X}", i, value));
+        output.push(if i % 8 == 7 { '\n' } else { ' ' });
+    }
+    output.push_str(&format!(
+        "PC={} I={} SP={:X} DT={:02X} ST={:02X}\n",
+        symbols.annotate(cpu.program_counter),
+        symbols.annotate(cpu.index),
+        cpu.stack_pointer,
+        cpu.delay_timer,
+        cpu.sound_timer
+    ));
+    let stack: Vec<String> = cpu.stack[..cpu.stack_pointer as usize]
+        .iter()
+        .map(|&address| symbols.annotate(address))
+        .collect();
+    output.push_str(&format!("Stack: {}\n", stack.join(" ")));
+    output
+}
+
+/// Dumps memory 16 bytes to a line, with the printable characters alongside.
+pub fn hexdump(cpu: &CPU<impl RandomSource>, address: u32, length: u32) -> String {
+    let start = address as usize;
+    let end = (start + length as usize).min(cpu.platform.memory_size());
+    let mut output = String::new();
+    for line in (start..end).step_by(16) {
+        let bytes = &cpu.memory[line..(line + 16).min(end)];
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        output.push_str(&format!("{:04X}  {:<47}  {}\n", line, hex.join(" "), text));
+    }
+    output
+}
+
+fn set_register(cpu: &mut CPU<impl RandomSource>, name: &str, value: u32) -> Result<(), String> {
+    let too_big = || format!("{:X} is too big for {}", value, name);
+    let byte = || u8::try_from(value).map_err(|_| too_big());
+    let word = || u16::try_from(value).map_err(|_| too_big());
+    match name.to_ascii_uppercase().as_str() {
+        "I" => cpu.index = word()?,
+        "PC" => cpu.program_counter = word()?,
+        "DT" => cpu.delay_timer = byte()?,
+        "ST" => cpu.sound_timer = byte()?,
+        "SP" if value as usize <= cpu.stack.len() => cpu.stack_pointer = value as u8,
+        "SP" => return Err(too_big()),
+        register => {
+            let index = register
+                .strip_prefix('V')
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+                .ok_or_else(|| format!("Unknown register {}", name))?;
+            cpu.registers[index] = byte()?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_hex(text: &str) -> Result<u32, String> {
+    let digits = text.trim_start_matches("0x").trim_start_matches('$');
+    u32::from_str_radix(digits, 16).map_err(|_| format!("{} is not a hexadecimal number", text))
+}
+
+fn value_hit(id: usize, expression: &Expression, old: i64, new: i64) -> WatchHit {
+    WatchHit {
+        id,
+        access: None,
+        message: format!(
+            "Watchpoint {:X}: {} changed from {:#X} to {:#X}",
+            id, expression, old, new
+        ),
+    }
+}
+
+/// Checks a memory watchpoint against an instruction at `pc` making
+/// `access` from `index`.
+fn memory_hit(
+    id: usize,
+    watch: &Watch,
+    pc: u16,
+    index: u16,
+    access: Option<(u32, AccessKind)>,
+    symbols: &SymbolMap,
+) -> Option<WatchHit> {
+    let Watch::Memory {
+        start,
+        length,
+        reads,
+        writes,
+    } = *watch
+    else {
+        return None;
+    };
+    let (count, kind) = access?;
+    let writing = kind == AccessKind::Store;
+    let watched = if writing { writes } else { reads };
+    let first = (index as u32).max(start as u32);
+    let end = (index as u32 + count).min(start as u32 + length as u32);
+    if !watched || first >= end {
+        return None;
+    }
+    Some(WatchHit {
+        id,
+        access: Some((kind, first as u16)),
+        message: format!(
+            "Watchpoint {:X}: {} {} {}",
+            id,
+            symbols.annotate(pc),
+            if writing { "writes" } else { "reads" },
+            symbols.annotate(first as u16)
+        ),
+    })
+}
@@ -0,0 +1,282 @@
+#[cfg(test)]
+use crate::debugger::{Debugger, Resume};
+#[cfg(test)]
+use crate::platform::Platform;
+#[cfg(test)]
+use crate::processor::CPU;
+#[cfg(test)]
+use crate::quirks::Quirks;
+#[cfg(test)]
+use crate::random::SeededRandom;
+#[cfg(test)]
+use crate::symbols::SymbolMap;
+
+#[cfg(test)]
+fn cpu_with(rom: &[u8]) -> CPU<SeededRandom> {
+    let mut cpu = CPU::with_rng(Platform::Chip8, Quirks::default(), SeededRandom::new(1));
+    cpu.load_rom(rom).unwrap();
+    cpu
+}
+
+#[cfg(test)]
+fn run(
+    debugger: &mut Debugger<SeededRandom>,
+    cpu: &mut CPU<SeededRandom>,
+    line: &str,
+) -> (String, Resume) {
+    debugger.command(cpu, line, [false; 16]).unwrap()
+}
+
+#[cfg(test)]
+const ROM: [u8; 8] = [
+    0x60, 0x05, // v0 := 5
+    0x22, 0x06, // call 0x206
+    0x12, 0x04, // jump 0x204
+    0x00, 0xEE, // return
+];
+
+#[test]
+fn test_step_and_disassemble() {
+    let mut cpu = cpu_with(&ROM);
+    let mut debugger = Debugger::new();
+    let (output, resume) = run(&mut debugger, &mut cpu, "s");
+    assert_eq!(output, "> 0202  2206  CALL 0x206\n");
+    assert_eq!(resume, Resume::Prompt);
+    assert_eq!(cpu.registers[0], 5);
+
+    run(&mut debugger, &mut cpu, "b 204");
+    let (output, _) = run(&mut debugger, &mut cpu, "d 200 3");
+    assert_eq!(
+        output,
+        "  0200  6005  LD V0, 0x05\n> 0202  2206  CALL 0x206\n *0204  1204  JP 0x204\n"
+    );
+}
+
+#[test]
+fn test_step_over_and_breakpoints() {
+    let mut cpu = cpu_with(&ROM);
+    let mut debugger = Debugger::new();
+    run(&mut debugger, &mut cpu, "s");
+
+    // Stepping over the call runs until it returns.
+    assert_eq!(run(&mut debugger, &mut cpu, "n").1, Resume::Run);
+    assert_eq!(debugger.run_to, Some(0x204));
+    assert!(!debugger.should_break(&cpu));
+    cpu.tick([false; 16]).unwrap();
+    assert!(!debugger.should_break(&cpu));
+    cpu.tick([false; 16]).unwrap();
+    assert!(debugger.should_break(&cpu));
+
+    // Continuing from a breakpoint runs the instruction under it first.
+    run(&mut debugger, &mut cpu, "b 204");
+    assert!(debugger.should_break(&cpu));
+    run(&mut debugger, &mut cpu, "c");
+    assert!(!debugger.should_break(&cpu));
+    cpu.tick([false; 16]).unwrap();
+    assert!(debugger.should_break(&cpu));
+
+    run(&mut debugger, &mut cpu, "bc 204");
+    assert!(!debugger.should_break(&cpu));
+    assert!(debugger.command(&mut cpu, "bc 204", [false; 16]).is_err());
+
+    // A call in the last word of memory returns to the first.
+    let mut cpu = CPU::with_rng(Platform::XoChip, Quirks::default(), SeededRandom::new(1));
+    cpu.memory[0xFFFE..].copy_from_slice(&[0x22, 0x00]);
+    cpu.program_counter = 0xFFFE;
+    run(&mut debugger, &mut cpu, "n");
+    assert_eq!(debugger.run_to, Some(0));
+}
+
+#[test]
+fn test_poke_set_and_dump() {
+    let mut cpu = cpu_with(&ROM);
+    let mut debugger = Debugger::new();
+    run(&mut debugger, &mut cpu, "poke 300 41 42 ff");
+    run(&mut debugger, &mut cpu, "set vA 7f");
+    run(&mut debugger, &mut cpu, "set i 300");
+    assert_eq!(cpu.registers[0xA], 0x7F);
+    assert_eq!(cpu.index, 0x300);
+
+    let (output, _) = run(&mut debugger, &mut cpu, "m 300 4");
+    assert_eq!(output, format!("0300  {:<47}  AB..\n", "41 42 FF 00"));
+    let (output, _) = run(&mut debugger, &mut cpu, "r");
+    assert!(output.contains("V8=00 V9=00 VA=7F"));
+    assert!(output.contains("PC=0200 I=0300 SP=0 DT=00 ST=00\n"));
+
+    assert!(debugger
+        .command(&mut cpu, "set v0 100", [false; 16])
+        .is_err());
+    assert!(debugger
+        .command(&mut cpu, "poke ffff 1 2", [false; 16])
+        .is_err());
+    assert!(debugger.command(&mut cpu, "bogus", [false; 16]).is_err());
+}
+
+#[test]
+fn test_conditional_breakpoints() {
+    let mut cpu = cpu_with(&ROM);
+    let mut debugger = Debugger::new();
+    run(&mut debugger, &mut cpu, "b 204 if v0 == 0x06 && sp == 0");
+    let (output, _) = run(&mut debugger, &mut cpu, "b");
+    assert_eq!(output, "0204 if v0 == 0x06 && sp == 0\n");
+
+    cpu.program_counter = 0x204;
+    assert!(!debugger.should_break(&cpu));
+    run(&mut debugger, &mut cpu, "set v0 6");
+    assert!(debugger.should_break(&cpu));
+    // Setting it again without a condition makes it stop every time.
+    run(&mut debugger, &mut cpu, "b 204");
+    run(&mut debugger, &mut cpu, "set v0 0");
+    assert!(debugger.should_break(&cpu));
+
+    assert!(debugger
+        .command(&mut cpu, "b 204 if v0 ==", [false; 16])
+        .is_err());
+    assert!(debugger.command(&mut cpu, "b 204 v0", [false; 16]).is_err());
+    let (output, _) = run(&mut debugger, &mut cpu, "p [0x200] + v0 - 1");
+    assert_eq!(output, "0x5F (95)\n");
+}
+
+#[test]
+fn test_watchpoints() {
+    // v0 := 5, i := 0x300, save v1, delay := v0, load v0, jump 0x20A
+    let rom = [
+        0x60, 0x05, 0xA3, 0x00, 0xF1, 0x55, 0xF0, 0x15, 0xF0, 0x65, 0x12, 0x0A,
+    ];
+    let mut cpu = cpu_with(&rom);
+    let mut debugger = Debugger::new();
+    let (output, _) = run(&mut debugger, &mut cpu, "w v0");
+    assert_eq!(output, "Watchpoint 1: v0 = 0x0\n");
+    let (output, _) = run(&mut debugger, &mut cpu, "ww 301");
+    assert_eq!(output, "Watchpoint 2: write 0301-0301\n");
+    run(&mut debugger, &mut cpu, "wr 2ff 2");
+    run(&mut debugger, &mut cpu, "w dt == 0");
+
+    let (output, _) = run(&mut debugger, &mut cpu, "s 10");
+    assert_eq!(
+        output,
+        "Watchpoint 1: v0 changed from 0x0 to 0x5\n> 0202  A300  LD I, 0x300\n"
+    );
+    run(&mut debugger, &mut cpu, "c");
+    debugger.tick(&mut cpu, [false; 16]).unwrap();
+    assert!(!debugger.should_break(&cpu));
+    debugger.tick(&mut cpu, [false; 16]).unwrap();
+    assert!(debugger.should_break(&cpu));
+    let hit = debugger.watch_hit.take().unwrap();
+    assert_eq!(hit.message, "Watchpoint 2: 0204 writes 0301");
+
+    // A watched condition goes off when its truth changes.
+    run(&mut debugger, &mut cpu, "c");
+    debugger.tick(&mut cpu, [false; 16]).unwrap();
+    assert!(debugger.should_break(&cpu));
+    assert_eq!(
+        debugger.watch_hit.take().unwrap().message,
+        "Watchpoint 4: dt == 0 changed from 0x1 to 0x0"
+    );
+    run(&mut debugger, &mut cpu, "c");
+    debugger.tick(&mut cpu, [false; 16]).unwrap();
+    assert_eq!(
+        debugger.watch_hit.take().unwrap().message,
+        "Watchpoint 3: 0208 reads 0300"
+    );
+
+    run(&mut debugger, &mut cpu, "wc 3");
+    let (output, _) = run(&mut debugger, &mut cpu, "w");
+    assert_eq!(
+        output,
+        "1: v0 = 0x5\n2: write 0301-0301\n4: dt == 0 = 0x0\n"
+    );
+    assert!(debugger.command(&mut cpu, "wc 3", [false; 16]).is_err());
+    assert!(debugger
+        .command(&mut cpu, "wr FFFFFFFF 2", [false; 16])
+        .is_err());
+    assert!(debugger
+        .command(&mut cpu, "wr 0 10000", [false; 16])
+        .is_err());
+}
+
+#[test]
+fn test_watchpoints_on_wide_reads() {
+    // plane 3, i := 0x300, sprite v0 v0 1, audio
+    let rom = [0xF3, 0x01, 0xA3, 0x00, 0xD0, 0x01, 0xF0, 0x02];
+    let mut cpu = CPU::with_rng(Platform::XoChip, Quirks::default(), SeededRandom::new(1));
+    cpu.load_rom(&rom).unwrap();
+    let mut debugger = Debugger::new();
+    run(&mut debugger, &mut cpu, "wr 301");
+    run(&mut debugger, &mut cpu, "wr 30f");
+    // Both planes take a byte of the sprite.
+    let (output, _) = run(&mut debugger, &mut cpu, "s 10");
+    assert!(output.starts_with("Watchpoint 1: 0204 reads 0301\n"));
+    let (output, _) = run(&mut debugger, &mut cpu, "s 10");
+    assert!(output.starts_with("Watchpoint 1: 0206 reads 0301\n"));
+}
+
+#[cfg(feature = "megachip")]
+#[test]
+fn test_watchpoints_on_megachip_sprites() {
+    // megaon, sprite size 4x4, i := 0x300, sprite v0 v0 0
+    let rom = [0x00, 0x11, 0x03, 0x04, 0x04, 0x04, 0xA3, 0x00, 0xD0, 0x00];
+    let mut cpu = CPU::with_rng(Platform::MegaChip, Quirks::default(), SeededRandom::new(1));
+    cpu.load_rom(&rom).unwrap();
+    let mut debugger = Debugger::new();
+    run(&mut debugger, &mut cpu, "wr 30f");
+    let (output, _) = run(&mut debugger, &mut cpu, "s 10");
+    assert!(output.starts_with("Watchpoint 1: 0208 reads 030F\n"));
+}
+
+#[test]
+fn test_stepping_backwards() {
+    // v0 := 5, i := 0x300, save v1, delay := v0, load v0, jump 0x20A
+    let rom = [
+        0x60, 0x05, 0xA3, 0x00, 0xF1, 0x55, 0xF0, 0x15, 0xF0, 0x65, 0x12, 0x0A,
+    ];
+    let mut cpu = cpu_with(&rom);
+    let mut debugger = Debugger::new();
+    run(&mut debugger, &mut cpu, "s 4");
+    assert_eq!(cpu.delay_timer, 5);
+    run(&mut debugger, &mut cpu, "rs");
+    assert_eq!(cpu.program_counter, 0x206);
+    assert_eq!(cpu.delay_timer, 0);
+    let (output, _) = run(&mut debugger, &mut cpu, "rs 10");
+    assert_eq!(output, "At the start of the history\n");
+    assert_eq!((cpu.program_counter, cpu.registers[0]), (0x200, 0));
+
+    // Running backwards stops where running forwards would have.
+    run(&mut debugger, &mut cpu, "s 6");
+    run(&mut debugger, &mut cpu, "ww 301");
+    let (output, _) = run(&mut debugger, &mut cpu, "rc");
+    assert!(output.starts_with("Watchpoint 1: 0204 writes 0301\n"));
+    assert_eq!(cpu.program_counter, 0x206);
+    run(&mut debugger, &mut cpu, "b 202 if v0 == 5");
+    run(&mut debugger, &mut cpu, "rc");
+    assert_eq!(cpu.program_counter, 0x202);
+    let (output, _) = run(&mut debugger, &mut cpu, "rc");
+    assert!(output.starts_with("At the start of the history\n"));
+
+    // Changes made while paused are kept when stepping back over them.
+    run(&mut debugger, &mut cpu, "set v3 7");
+    run(&mut debugger, &mut cpu, "s 2");
+    run(&mut debugger, &mut cpu, "rs");
+    assert_eq!(cpu.registers[3], 7);
+}
+
+#[test]
+fn test_symbols() {
+    let mut cpu = cpu_with(&ROM);
+    let mut debugger = Debugger::new();
+    debugger.symbols = SymbolMap::parse("0200 main\n0206 helper\n").unwrap();
+    run(&mut debugger, &mut cpu, "b main+2");
+    run(&mut debugger, &mut cpu, "b helper");
+    let (output, _) = run(&mut debugger, &mut cpu, "b");
+    assert_eq!(output, "0202 <main+2>\n0206 <helper>\n");
+
+    run(&mut debugger, &mut cpu, "s 2");
+    assert_eq!(cpu.program_counter, 0x206);
+    let (output, _) = run(&mut debugger, &mut cpu, "bt");
+    assert_eq!(output, "#0 0206 <helper>\n#1 0202 <main+2>\n");
+    let (output, _) = run(&mut debugger, &mut cpu, "d helper 1");
+    assert_eq!(output, ">*0206 <helper>  00EE  RET\n");
+    assert!(debugger
+        .command(&mut cpu, "b nowhere", [false; 16])
+        .is_err());
+}
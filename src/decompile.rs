@@ -0,0 +1,369 @@
+use crate::disasm::{analyze, is_skip, mnemonic, word_at, ByteKind, RomMap, Syntax};
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// A control structure recovered from the jumps and skips around it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Structure {
+    /// A backward jump at `again` to `start`.
+    Loop { start: u16, again: u16 },
+    /// A skip at `skip` followed by a jump over the body to `end`. With an
+    /// else branch, the body ends in a jump at `else_jump` over the else
+    /// branch to `end`.
+    If {
+        skip: u16,
+        condition: String,
+        else_jump: Option<u16>,
+        end: u16,
+    },
+}
+
+impl Structure {
+    /// The addresses the structure covers, from its first byte to where the
+    /// code after it starts.
+    fn span(&self) -> (u16, u16) {
+        match *self {
+            Structure::Loop { start, again } => (start, again + 2),
+            Structure::If { skip, end, .. } => (skip, end),
+        }
+    }
+
+    /// The address ranges other structures may be nested in.
+    fn bodies(&self) -> Vec<(u16, u16)> {
+        match *self {
+            Structure::Loop { start, again } => vec![(start, again)],
+            Structure::If {
+                skip,
+                else_jump: None,
+                end,
+                ..
+            } => vec![(skip + 4, end)],
+            Structure::If {
+                skip,
+                else_jump: Some(else_jump),
+                end,
+                ..
+            } => vec![(skip + 4, else_jump), (else_jump + 2, end)],
+        }
+    }
+
+    /// Whether the two structures are disjoint or one sits wholly inside a
+    /// body of the other, so the Octo block keywords nest.
+    fn nests_with(&self, other: &Structure) -> bool {
+        let (start, end) = self.span();
+        let (other_start, other_end) = other.span();
+        let inside = |(start, end): (u16, u16), (outer_start, outer_end): (u16, u16)| {
+            outer_start <= start && end <= outer_end
+        };
+        end <= other_start
+            || other_end <= start
+            || other
+                .bodies()
+                .into_iter()
+                .any(|body| inside(self.span(), body))
+            || self
+                .bodies()
+                .into_iter()
+                .any(|body| inside(other.span(), body))
+    }
+}
+
+/// Lifts a ROM back to Octo source. Skips over forward jumps become
+/// `if ... begin ... else ... end` and backward jumps become
+/// `loop ... again`; everything else is written as by the Octo disassembler.
+/// The source compiles back to the same bytes.
+pub fn decompile(rom: &[u8], platform: Platform) -> String {
+    let map = analyze(rom, platform);
+    let base = map.base;
+    let rom_end = base as usize + rom.len();
+
+    // The instruction starting at each code address, in order.
+    let mut instructions = BTreeMap::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = base.wrapping_add(offset as u16);
+        let instruction = word_at(rom, base, address)
+            .and_then(|opcode| Instruction::decode(opcode, platform))
+            .filter(|_| map.kinds[offset] == ByteKind::Code);
+        match instruction {
+            Some(instruction) => {
+                instructions.insert(address, instruction);
+                offset += instruction.size() as usize;
+            }
+            None => offset += 1,
+        }
+    }
+
+    let mut references = references(rom, base, &instructions);
+    let structures = find_structures(rom_end, &map, &instructions, &references);
+    for structure in &structures {
+        // The block keywords replace the jumps, so their labels may no longer
+        // be needed.
+        match *structure {
+            Structure::Loop { start, .. } => release(&mut references, start),
+            Structure::If { end, else_jump, .. } => match else_jump {
+                Some(else_jump) => {
+                    release(&mut references, else_jump + 2);
+                    release(&mut references, end);
+                }
+                None => release(&mut references, end),
+            },
+        }
+    }
+
+    let mut output = String::from(": main\n");
+    let mut depth = 0;
+    let mut data = Vec::new();
+    let mut offset = 0;
+    while offset <= rom.len() {
+        let address = base.wrapping_add(offset as u16);
+        let closes: Vec<&Structure> = structures
+            .iter()
+            .rev()
+            .filter(|s| matches!(s, Structure::If { end, .. } if *end == address))
+            .collect();
+        let label = map
+            .label(address)
+            .filter(|_| references.get(&address).copied().unwrap_or(0) > 0);
+        let opens = structures
+            .iter()
+            .any(|s| matches!(s, Structure::Loop { start, .. } if *start == address));
+        let replaced = structures.iter().find(|s| match **s {
+            Structure::Loop { again, .. } => again == address,
+            Structure::If {
+                skip, else_jump, ..
+            } => skip == address || else_jump == Some(address),
+        });
+
+        let sprite = map.kinds.get(offset) == Some(&ByteKind::Data);
+        let interrupted = !closes.is_empty()
+            || label.is_some()
+            || opens
+            || replaced.is_some()
+            || instructions.contains_key(&address);
+        let full = data.len() == 8 || data.last().is_some_and(|&(_, last)| last != sprite);
+        if !data.is_empty() && (interrupted || full) {
+            write_bytes(&mut output, &data, depth);
+            data.clear();
+        }
+
+        for _ in closes {
+            depth -= 1;
+            line(&mut output, depth, "end");
+        }
+        if let Some(label) = label {
+            output.push_str(&format!(": {}\n", label));
+        }
+        for structure in &structures {
+            if let Structure::Loop { start, .. } = structure {
+                if *start == address {
+                    line(&mut output, depth, "loop");
+                    depth += 1;
+                }
+            }
+        }
+        if offset == rom.len() {
+            break;
+        }
+
+        match (replaced, instructions.get(&address)) {
+            (Some(Structure::Loop { .. }), _) => {
+                depth -= 1;
+                line(&mut output, depth, "again");
+                offset += 2;
+            }
+            (
+                Some(Structure::If {
+                    skip, condition, ..
+                }),
+                _,
+            ) if *skip == address => {
+                line(&mut output, depth, &format!("if {} begin", condition));
+                depth += 1;
+                offset += 4;
+            }
+            (Some(_), _) => {
+                line(&mut output, depth - 1, "else");
+                offset += 2;
+            }
+            (None, Some(instruction)) => {
+                let operand = word_at(rom, base, address.wrapping_add(2));
+                let text = mnemonic(instruction, operand, Syntax::Octo, &map);
+                line(&mut output, depth, &text);
+                offset += instruction.size() as usize;
+            }
+            (None, None) => {
+                data.push((rom[offset], sprite));
+                offset += 1;
+            }
+        }
+    }
+    if !data.is_empty() {
+        write_bytes(&mut output, &data, depth);
+    }
+    output
+}
+
+/// Counts how many instructions refer to each address.
+fn references(
+    rom: &[u8],
+    base: u16,
+    instructions: &BTreeMap<u16, Instruction>,
+) -> HashMap<u16, usize> {
+    let mut references = HashMap::new();
+    for (&address, instruction) in instructions {
+        let target = match *instruction {
+            Instruction::Jump(nnn)
+            | Instruction::Call(nnn)
+            | Instruction::SetIndex(nnn)
+            | Instruction::JumpWithOffset(nnn) => Some(nnn),
+            Instruction::LongIndex => word_at(rom, base, address.wrapping_add(2)),
+            _ => None,
+        };
+        if let Some(target) = target {
+            *references.entry(target).or_insert(0) += 1;
+        }
+    }
+    references
+}
+
+fn release(references: &mut HashMap<u16, usize>, address: u16) {
+    if let Some(count) = references.get_mut(&address) {
+        *count = count.saturating_sub(1);
+    }
+}
+
+/// Finds the loops and ifs that can be written as Octo blocks, keeping only
+/// ones that nest properly with those already found.
+fn find_structures(
+    rom_end: usize,
+    map: &RomMap,
+    instructions: &BTreeMap<u16, Instruction>,
+    references: &HashMap<u16, usize>,
+) -> Vec<Structure> {
+    // A block keyword after a skip would be skipped as a whole instruction,
+    // which reads as nonsense even though it compiles.
+    let after_skip: HashSet<u16> = instructions
+        .iter()
+        .filter(|(_, instruction)| is_skip(instruction))
+        .filter_map(|(&address, _)| address.checked_add(2))
+        .collect();
+    let is_code = |start: u16, end: u16| (start..end).all(|a| map.kind(a) == Some(ByteKind::Code));
+    let is_boundary =
+        |address: u16| instructions.contains_key(&address) || address as usize == rom_end;
+    let is_jump_to = |address: u16| match instructions.get(&address) {
+        Some(Instruction::Jump(target)) => Some(*target),
+        _ => None,
+    };
+
+    let mut candidates = Vec::new();
+    for (&address, instruction) in instructions {
+        // Nothing in the last words of memory has room to close a block.
+        let (Some(next), Some(after_next)) = (address.checked_add(2), address.checked_add(4))
+        else {
+            continue;
+        };
+        if after_skip.contains(&address) {
+            continue;
+        }
+        if let Instruction::Jump(start) = *instruction {
+            if start <= address && is_boundary(start) && is_code(start, next) {
+                candidates.push(Structure::Loop {
+                    start,
+                    again: address,
+                });
+            }
+        }
+
+        let Some(condition) = condition(instruction) else {
+            continue;
+        };
+        let Some(end) = is_jump_to(next) else {
+            continue;
+        };
+        if end <= after_next
+            || !is_boundary(end)
+            || !is_code(address, end)
+            || references.get(&next).is_some()
+        {
+            continue;
+        }
+        // An else branch shows up as the body ending in a forward jump.
+        let else_jump = end - 2;
+        let structure = match is_jump_to(else_jump) {
+            Some(else_end)
+                if else_jump >= after_next
+                    && else_end > end
+                    && !after_skip.contains(&else_jump)
+                    && is_boundary(else_end)
+                    && is_code(end, else_end) =>
+            {
+                Structure::If {
+                    skip: address,
+                    condition,
+                    else_jump: Some(else_jump),
+                    end: else_end,
+                }
+            }
+            _ => Structure::If {
+                skip: address,
+                condition,
+                else_jump: None,
+                end,
+            },
+        };
+        candidates.push(structure);
+    }
+
+    // Outer structures first, so they win over the ones inside them.
+    candidates.sort_by_key(|s| {
+        let (start, end) = s.span();
+        (start, std::cmp::Reverse(end))
+    });
+    let mut structures: Vec<Structure> = Vec::new();
+    for candidate in candidates {
+        if structures.iter().all(|s| s.nests_with(&candidate)) {
+            structures.push(candidate);
+        }
+    }
+    structures
+}
+
+/// The condition under which a skip instruction skips, in Octo syntax.
+fn condition(instruction: &Instruction) -> Option<String> {
+    match *instruction {
+        Instruction::SkipIfEqual { x, nn } => Some(format!("v{:x} == {:#04X}", x, nn)),
+        Instruction::SkipIfNotEqual { x, nn } => Some(format!("v{:x} != {:#04X}", x, nn)),
+        Instruction::SkipIfRegistersEqual { x, y } => Some(format!("v{:x} == v{:x}", x, y)),
+        Instruction::SkipIfRegistersNotEqual { x, y } => Some(format!("v{:x} != v{:x}", x, y)),
+        Instruction::SkipIfPressed { x } => Some(format!("v{:x} key", x)),
+        Instruction::SkipIfNotPressed { x } => Some(format!("v{:x} -key", x)),
+        _ => None,
+    }
+}
+
+fn line(output: &mut String, depth: usize, text: &str) {
+    output.push_str(&"  ".repeat(depth + 1));
+    output.push_str(text);
+    output.push('\n');
+}
+
+/// Writes bytes that aren't code. Sprite rows get a line each with a picture
+/// of the row; anything else is packed several to a line.
+fn write_bytes(output: &mut String, bytes: &[(u8, bool)], depth: usize) {
+    if bytes.iter().all(|&(_, sprite)| sprite) {
+        for &(byte, _) in bytes {
+            let row: String = (0..8)
+                .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                .collect();
+            line(output, depth, &format!("{:#04X} # {}", byte, row));
+        }
+    } else {
+        let text: Vec<String> = bytes
+            .iter()
+            .map(|(byte, _)| format!("{:#04X}", byte))
+            .collect();
+        line(output, depth, &text.join(" "));
+    }
+}
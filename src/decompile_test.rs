@@ -0,0 +1,78 @@
+#[cfg(test)]
+use crate::decompile::decompile;
+#[cfg(test)]
+use crate::octo::compile;
+#[cfg(test)]
+use crate::platform::Platform;
+
+#[cfg(test)]
+const ROM: [u8; 23] = [
+    0x60, 0x00, // v0 := 0
+    0x70, 0x01, // v0 += 1
+    0x30, 0x05, // skip if v0 == 5
+    0x12, 0x0C, // jump 0x20C
+    0x61, 0x01, // v1 := 1
+    0x12, 0x0E, // jump 0x20E
+    0x61, 0x02, // v1 := 2
+    0xA2, 0x14, // i := 0x214
+    0xD0, 0x13, // sprite v0 v1 3
+    0x12, 0x02, // jump 0x202
+    0xF0, 0x90, 0xF0, // sprite rows
+];
+
+#[test]
+fn test_decompile_recovers_blocks() {
+    let source = decompile(&ROM, Platform::Chip8);
+    assert_eq!(
+        source,
+        ": main
+  v0 := 0x00
+  loop
+    v0 += 0x01
+    if v0 == 0x05 begin
+      v1 := 0x01
+    else
+      v1 := 0x02
+    end
+    i := data_214
+    sprite v0 v1 3
+  again
+: data_214
+  0xF0 # ####....
+  0x90 # #..#....
+  0xF0 # ####....
+"
+    );
+}
+
+#[test]
+fn test_decompiled_source_compiles_to_the_same_rom() {
+    let roms: [&[u8]; 2] = [
+        &ROM,
+        // A backward jump into an if block, which can't also be a loop, and
+        // bytes nothing reaches.
+        &[
+            0x00, 0xE0, 0x60, 0x01, 0xE0, 0x9E, 0x12, 0x0A, 0x70, 0x01, 0x70, 0x02, 0x12, 0x08,
+            0xFF, 0x00,
+        ],
+    ];
+    for rom in roms {
+        let source = decompile(rom, Platform::Chip8);
+        assert_eq!(
+            compile(&source, "rom.8o", Platform::Chip8).unwrap(),
+            rom,
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_code_running_to_the_end_of_memory() {
+    // v0 := 0 all the way up, ending in a skip and a jump back.
+    let mut rom: Vec<u8> = [0x60, 0x00].repeat((0x10000 - 0x200) / 2);
+    let end = rom.len();
+    rom[end - 4..].copy_from_slice(&[0x30, 0x00, 0x12, 0x00]);
+    let source = decompile(&rom, Platform::XoChip);
+    assert!(source.ends_with("  if v0 != 0x00 then\n  jump label_200\n"));
+}
@@ -0,0 +1,562 @@
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use crate::symbols::SymbolMap;
+use std::collections::BTreeMap;
+
+/// The assembly dialect the disassembler writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// The mnemonics from Cowgod's CHIP-8 technical reference.
+    Cowgod,
+    /// Octo's assembly language.
+    Octo,
+}
+
+impl Syntax {
+    /// Looks up a syntax by the name accepted on the command line.
+    pub fn from_name(name: &str) -> Option<Syntax> {
+        match name {
+            "cowgod" => Some(Syntax::Cowgod),
+            "octo" => Some(Syntax::Octo),
+            _ => None,
+        }
+    }
+
+    fn comment(self) -> &'static str {
+        match self {
+            Syntax::Cowgod => ";",
+            Syntax::Octo => "#",
+        }
+    }
+}
+
+/// What a ROM byte was found to hold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteKind {
+    /// No path from the entry point reaches the byte.
+    Unreachable,
+    /// Part of an instruction, including the word after F000 and 01NN.
+    Code,
+    /// Read as sprite data by DXYN.
+    Data,
+}
+
+/// Why an address was given a label.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LabelKind {
+    /// The target of 2NNN.
+    Subroutine,
+    /// The target of a jump.
+    Jump,
+    /// Sprite data pointed at by I.
+    Data,
+}
+
+impl LabelKind {
+    pub fn name(self, address: u16) -> String {
+        match self {
+            LabelKind::Subroutine => format!("sub_{:03X}", address),
+            LabelKind::Jump => format!("label_{:03X}", address),
+            LabelKind::Data => format!("data_{:03X}", address),
+        }
+    }
+}
+
+/// The result of following every path through a ROM from its entry point.
+pub struct RomMap {
+    /// The address the first ROM byte is loaded at.
+    pub base: u16,
+    /// One entry per ROM byte.
+    pub kinds: Vec<ByteKind>,
+    pub labels: BTreeMap<u16, LabelKind>,
+    /// Names from a symbol file, used in place of the generated ones.
+    pub names: BTreeMap<u16, String>,
+}
+
+impl RomMap {
+    pub fn kind(&self, address: u16) -> Option<ByteKind> {
+        let offset = (address as usize).checked_sub(self.base as usize)?;
+        self.kinds.get(offset).copied()
+    }
+
+    pub fn label(&self, address: u16) -> Option<String> {
+        match self.names.get(&address) {
+            Some(name) => Some(name.clone()),
+            None => self.labels.get(&address).map(|kind| kind.name(address)),
+        }
+    }
+}
+
+/// Reads the big-endian word at `address`, if the ROM covers both bytes.
+pub fn word_at(rom: &[u8], base: u16, address: u16) -> Option<u16> {
+    let offset = (address as usize).checked_sub(base as usize)?;
+    let high = *rom.get(offset)?;
+    let low = *rom.get(offset + 1)?;
+    Some(((high as u16) << 8) | low as u16)
+}
+
+/// Follows the control flow of `rom` from the load address, marking which
+/// bytes are instructions and which are sprite data. Jumps through BNNN and
+/// FX1B cannot be followed, so code only reached that way shows up as
+/// unreachable.
+pub fn analyze(rom: &[u8], platform: Platform) -> RomMap {
+    let base = platform.load_address();
+    let mut map = RomMap {
+        base,
+        kinds: vec![ByteKind::Unreachable; rom.len()],
+        labels: BTreeMap::new(),
+        names: BTreeMap::new(),
+    };
+
+    // Each entry is an address to decode and the value of I on the way in,
+    // when it is known.
+    let mut pending = vec![(base, None)];
+    while let Some((address, mut index)) = pending.pop() {
+        let mut address = address;
+        loop {
+            if map.kind(address) == Some(ByteKind::Code) {
+                break;
+            }
+            let Some(opcode) = word_at(rom, base, address) else {
+                break;
+            };
+            let Some(instruction) = Instruction::decode(opcode, platform) else {
+                break;
+            };
+            let size = instruction.size();
+            for offset in 0..size {
+                mark(&mut map, address.wrapping_add(offset), ByteKind::Code);
+            }
+            let next = address.wrapping_add(size);
+
+            match instruction {
+                Instruction::Jump(target) => {
+                    label(&mut map, target, LabelKind::Jump);
+                    pending.push((target, index));
+                    break;
+                }
+                Instruction::Call(target) => {
+                    map.labels.insert(target, LabelKind::Subroutine);
+                    pending.push((target, index));
+                    // The subroutine may change I, so it is unknown after.
+                    index = None;
+                }
+                Instruction::JumpBackward(nn) | Instruction::JumpForward(nn) => {
+                    let target = match instruction {
+                        Instruction::JumpBackward(_) => address.wrapping_sub(nn as u16),
+                        _ => address.wrapping_add(nn as u16),
+                    };
+                    label(&mut map, target, LabelKind::Jump);
+                    pending.push((target, index));
+                    break;
+                }
+                Instruction::Return
+                | Instruction::Exit
+                | Instruction::Stop
+                | Instruction::JumpWithOffset(_)
+                | Instruction::SkipBytes { .. } => break,
+                Instruction::SkipNext => {
+                    address = skip_target(rom, base, next, platform);
+                    continue;
+                }
+                _ if is_skip(&instruction) => {
+                    pending.push((skip_target(rom, base, next, platform), index));
+                }
+                Instruction::SetIndex(nnn) => index = Some(nnn),
+                Instruction::LongIndex => index = word_at(rom, base, address.wrapping_add(2)),
+                Instruction::Draw { n, .. } => {
+                    if let Some(sprite) = index {
+                        let length = if n == 0 && platform.has_superchip_opcodes() {
+                            32
+                        } else {
+                            n as u16
+                        };
+                        if length > 0 {
+                            label(&mut map, sprite, LabelKind::Data);
+                        }
+                        for offset in 0..length {
+                            mark(&mut map, sprite.wrapping_add(offset), ByteKind::Data);
+                        }
+                    }
+                }
+                _ if writes_index(&instruction) => index = None,
+                _ => (),
+            }
+            address = next;
+        }
+    }
+
+    // Labels only make sense where the disassembly has a line to put them.
+    let labels = std::mem::take(&mut map.labels);
+    map.labels = labels
+        .into_iter()
+        .filter(|&(address, _)| map.kind(address).is_some())
+        .collect();
+    map
+}
+
+fn mark(map: &mut RomMap, address: u16, kind: ByteKind) {
+    let Some(offset) = (address as usize).checked_sub(map.base as usize) else {
+        return;
+    };
+    if let Some(existing) = map.kinds.get_mut(offset) {
+        // Code wins over data, since it was actually executed.
+        if *existing != ByteKind::Code {
+            *existing = kind;
+        }
+    }
+}
+
+fn label(map: &mut RomMap, address: u16, kind: LabelKind) {
+    map.labels.entry(address).or_insert(kind);
+}
+
+/// Where a taken skip lands: past the next instruction, which may be the
+/// four-byte F000 NNNN.
+pub fn skip_target(rom: &[u8], base: u16, next: u16, platform: Platform) -> u16 {
+    let size = word_at(rom, base, next)
+        .and_then(|opcode| Instruction::decode(opcode, platform))
+        .map_or(2, |instruction| instruction.size());
+    next.wrapping_add(size)
+}
+
+/// Whether the instruction conditionally skips the one after it.
+pub fn is_skip(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::SkipIfEqual { .. }
+            | Instruction::SkipIfNotEqual { .. }
+            | Instruction::SkipIfRegistersEqual { .. }
+            | Instruction::SkipIfRegistersNotEqual { .. }
+            | Instruction::SkipIfPressed { .. }
+            | Instruction::SkipIfNotPressed { .. }
+            | Instruction::SkipIfPressedKeypad2 { .. }
+            | Instruction::SkipIfNotPressedKeypad2 { .. }
+            | Instruction::SkipIfGreater { .. }
+    )
+}
+
+/// Whether the instruction changes I in a way the analysis can't follow.
+fn writes_index(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::AddToIndex { .. }
+            | Instruction::SmallCharacter { .. }
+            | Instruction::BigCharacter { .. }
+            | Instruction::Store { .. }
+            | Instruction::Load { .. }
+            | Instruction::SaveRangeAndAdvance { .. }
+            | Instruction::LoadRangeAndAdvance { .. }
+            | Instruction::LongIndex24(_)
+    )
+}
+
+/// Disassembles a whole ROM, one line per instruction or data byte.
+pub fn disassemble(rom: &[u8], platform: Platform, syntax: Syntax) -> String {
+    disassemble_with_symbols(rom, platform, syntax, &SymbolMap::new())
+}
+
+/// Disassembles a whole ROM, naming addresses from a symbol file where it
+/// has a name for them.
+pub fn disassemble_with_symbols(
+    rom: &[u8],
+    platform: Platform,
+    syntax: Syntax,
+    symbols: &SymbolMap,
+) -> String {
+    let mut map = analyze(rom, platform);
+    map.names = symbols.labels.clone();
+    let base = map.base;
+    let comment = syntax.comment();
+    let mut output = String::new();
+
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = base.wrapping_add(offset as u16);
+        if let Some(name) = map.label(address) {
+            match syntax {
+                Syntax::Cowgod => output.push_str(&format!("{}:\n", name)),
+                Syntax::Octo => output.push_str(&format!(": {}\n", name)),
+            }
+        }
+
+        let instruction = word_at(rom, base, address)
+            .and_then(|opcode| Instruction::decode(opcode, platform))
+            .filter(|_| map.kinds[offset] == ByteKind::Code);
+        let line = match instruction {
+            Some(instruction) => {
+                let size = instruction.size() as usize;
+                let bytes = &rom[offset..(offset + size).min(rom.len())];
+                let operand = word_at(rom, base, address.wrapping_add(2));
+                let text = mnemonic(&instruction, operand, syntax, &map);
+                offset += size;
+                format!("{:04X}  {:<11} {}", address, hex_bytes(bytes), text)
+            }
+            None => {
+                let byte = rom[offset];
+                offset += 1;
+                let text = match syntax {
+                    Syntax::Cowgod => format!("DB {:#04X}", byte),
+                    Syntax::Octo => format!("{:#04X}", byte),
+                };
+                let note = match map.kinds[offset - 1] {
+                    ByteKind::Data => bitmap(byte),
+                    _ => "unreachable".to_string(),
+                };
+                format!(
+                    "{:04X}  {:<11} {:<20} {} {}",
+                    address,
+                    hex_bytes(&[byte]),
+                    text,
+                    comment,
+                    note
+                )
+            }
+        };
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Draws a sprite row, so data regions can be recognised at a glance.
+fn bitmap(byte: u8) -> String {
+    (0..8)
+        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+        .collect()
+}
+
+/// Formats one instruction. `operand` is the word after it, used by F000 and
+/// 01NN; addresses that have a label in `map` are written as the label.
+pub fn mnemonic(
+    instruction: &Instruction,
+    operand: Option<u16>,
+    syntax: Syntax,
+    map: &RomMap,
+) -> String {
+    let address = |nnn: u16| match map.label(nnn) {
+        Some(name) => name,
+        None => format!("{:#05X}", nnn),
+    };
+    let long = match operand {
+        Some(word) => format!("{:#06X}", word),
+        None => "?".to_string(),
+    };
+    match syntax {
+        Syntax::Cowgod => cowgod(instruction, &address, operand),
+        Syntax::Octo => octo(instruction, &address, &long)
+            .unwrap_or_else(|| unsupported_in_octo(instruction, operand, &address)),
+    }
+}
+
+fn cowgod(
+    instruction: &Instruction,
+    address: &dyn Fn(u16) -> String,
+    operand: Option<u16>,
+) -> String {
+    use Instruction::*;
+
+    let long = |high: u8, digits: usize| match operand {
+        Some(word) => format!(
+            "{:#0width$X}",
+            ((high as u32) << 16) | word as u32,
+            width = digits + 2
+        ),
+        None => "?".to_string(),
+    };
+
+    match *instruction {
+        ClearScreen => "CLS".to_string(),
+        Return => "RET".to_string(),
+        Jump(nnn) => format!("JP {}", address(nnn)),
+        Call(nnn) => format!("CALL {}", address(nnn)),
+        SkipIfEqual { x, nn } => format!("SE V{:X}, {:#04X}", x, nn),
+        SkipIfNotEqual { x, nn } => format!("SNE V{:X}, {:#04X}", x, nn),
+        SkipIfRegistersEqual { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        SetRegister { x, nn } => format!("LD V{:X}, {:#04X}", x, nn),
+        AddImmediate { x, nn } => format!("ADD V{:X}, {:#04X}", x, nn),
+        Move { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        Subtract { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        SubtractReversed { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        SkipIfRegistersNotEqual { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        SetIndex(nnn) => format!("LD I, {}", address(nnn)),
+        JumpWithOffset(nnn) => format!("JP V0, {}", address(nnn)),
+        Random { x, nn } => format!("RND V{:X}, {:#04X}", x, nn),
+        Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        SkipIfPressed { x } => format!("SKP V{:X}", x),
+        SkipIfNotPressed { x } => format!("SKNP V{:X}", x),
+        GetDelay { x } => format!("LD V{:X}, DT", x),
+        WaitForKey { x } => format!("LD V{:X}, K", x),
+        SetDelay { x } => format!("LD DT, V{:X}", x),
+        SetSound { x } => format!("LD ST, V{:X}", x),
+        AddToIndex { x } => format!("ADD I, V{:X}", x),
+        SmallCharacter { x } => format!("LD F, V{:X}", x),
+        BinaryCodedDecimal { x } => format!("LD B, V{:X}", x),
+        Store { x } => format!("LD [I], V{:X}", x),
+        Load { x } => format!("LD V{:X}, [I]", x),
+
+        ScrollDown(n) => format!("SCD {}", n),
+        ScrollRight => "SCR".to_string(),
+        ScrollLeft => "SCL".to_string(),
+        Exit => "EXIT".to_string(),
+        LowResolution => "LOW".to_string(),
+        HighResolution => "HIGH".to_string(),
+        BigCharacter { x } => format!("LD HF, V{:X}", x),
+        SaveFlags { x } => format!("LD R, V{:X}", x),
+        LoadFlags { x } => format!("LD V{:X}, R", x),
+
+        ScrollUp(n) => format!("SCU {}", n),
+        SaveRange { x, y } => format!("LD [I], V{:X}-V{:X}", x, y),
+        LoadRange { x, y } => format!("LD V{:X}-V{:X}, [I]", x, y),
+        LongIndex => format!("LD I, LONG {}", long(0, 4)),
+        SelectPlanes(n) => format!("PLANE {}", n),
+        LoadAudioPattern => "AUDIO".to_string(),
+        SetPitch { x } => format!("LD PITCH, V{:X}", x),
+
+        MegaOff => "MEGAOFF".to_string(),
+        MegaOn => "MEGAON".to_string(),
+        LongIndex24(nn) => format!("LDHI I, {}", long(nn, 6)),
+        LoadPalette(nn) => format!("LDPAL {}", nn),
+        SpriteWidth(nn) => format!("SPRW {}", nn),
+        SpriteHeight(nn) => format!("SPRH {}", nn),
+        ScreenAlpha(nn) => format!("ALPHA {:#04X}", nn),
+        PlaySample(n) => format!("DIGISND {}", n),
+        StopSample => "STOPSND".to_string(),
+        BlendMode(n) => format!("BMODE {}", n),
+        CollisionColor(nn) => format!("CCOL {:#04X}", nn),
+        MegaScrollUp(n) => format!("SCRU {}", n),
+
+        HiresClearScreen => "HCLS".to_string(),
+
+        CycleBackground => "BGCOL".to_string(),
+        AddNibbles { x, y } => format!("ADDN V{:X}, V{:X}", x, y),
+        ZoneColor { x, y, n } => format!("COL V{:X}, V{:X}, {}", x, y, n),
+        SkipIfPressedKeypad2 { x } => format!("SKP2 V{:X}", x),
+        SkipIfNotPressedKeypad2 { x } => format!("SKNP2 V{:X}", x),
+        PortOutput { x } | Output { x } => format!("OUT V{:X}", x),
+        PortInput { x } | Input { x } => format!("IN V{:X}", x),
+
+        Stop => "STOP".to_string(),
+        NoOperation => "NOP".to_string(),
+        WaitForDelay => "WAIT DT".to_string(),
+        SkipNext => "SKIP".to_string(),
+        SkipIfGreater { x, y } => format!("SGT V{:X}, V{:X}", x, y),
+        SaveRangeAndAdvance { x, y } => format!("LD [I+], V{:X}-V{:X}", x, y),
+        LoadRangeAndAdvance { x, y } => format!("LD V{:X}-V{:X}, [I+]", x, y),
+        JumpBackward(nn) => format!("JB {}", nn),
+        JumpForward(nn) => format!("JF {}", nn),
+        SkipBytes { x } => format!("SKIP V{:X}", x),
+        SetDelayAndWait { x } => format!("LDW DT, V{:X}", x),
+        WaitForInput { x } => format!("INW V{:X}", x),
+    }
+}
+
+/// Octo only knows the CHIP-8, SUPER-CHIP and XO-CHIP instruction sets, so
+/// anything else comes back as `None`.
+fn octo(instruction: &Instruction, address: &dyn Fn(u16) -> String, long: &str) -> Option<String> {
+    use Instruction::*;
+
+    // Octo writes skips as the condition under which the next instruction
+    // does run.
+    let text = match *instruction {
+        ClearScreen => "clear".to_string(),
+        Return => "return".to_string(),
+        Jump(nnn) => format!("jump {}", address(nnn)),
+        Call(nnn) => {
+            let target = address(nnn);
+            if target.starts_with("0x") {
+                format!(":call {}", target)
+            } else {
+                target
+            }
+        }
+        SkipIfEqual { x, nn } => format!("if v{:x} != {:#04X} then", x, nn),
+        SkipIfNotEqual { x, nn } => format!("if v{:x} == {:#04X} then", x, nn),
+        SkipIfRegistersEqual { x, y } => format!("if v{:x} != v{:x} then", x, y),
+        SetRegister { x, nn } => format!("v{:x} := {:#04X}", x, nn),
+        AddImmediate { x, nn } => format!("v{:x} += {:#04X}", x, nn),
+        Move { x, y } => format!("v{:x} := v{:x}", x, y),
+        Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+        And { x, y } => format!("v{:x} &= v{:x}", x, y),
+        Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+        Add { x, y } => format!("v{:x} += v{:x}", x, y),
+        Subtract { x, y } => format!("v{:x} -= v{:x}", x, y),
+        ShiftRight { x, y } => format!("v{:x} >>= v{:x}", x, y),
+        SubtractReversed { x, y } => format!("v{:x} =- v{:x}", x, y),
+        ShiftLeft { x, y } => format!("v{:x} <<= v{:x}", x, y),
+        SkipIfRegistersNotEqual { x, y } => format!("if v{:x} == v{:x} then", x, y),
+        SetIndex(nnn) => format!("i := {}", address(nnn)),
+        JumpWithOffset(nnn) => format!("jump0 {}", address(nnn)),
+        Random { x, nn } => format!("v{:x} := random {:#04X}", x, nn),
+        Draw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+        SkipIfPressed { x } => format!("if v{:x} -key then", x),
+        SkipIfNotPressed { x } => format!("if v{:x} key then", x),
+        GetDelay { x } => format!("v{:x} := delay", x),
+        WaitForKey { x } => format!("v{:x} := key", x),
+        SetDelay { x } => format!("delay := v{:x}", x),
+        SetSound { x } => format!("buzzer := v{:x}", x),
+        AddToIndex { x } => format!("i += v{:x}", x),
+        SmallCharacter { x } => format!("i := hex v{:x}", x),
+        BinaryCodedDecimal { x } => format!("bcd v{:x}", x),
+        Store { x } => format!("save v{:x}", x),
+        Load { x } => format!("load v{:x}", x),
+
+        ScrollDown(n) => format!("scroll-down {}", n),
+        ScrollRight => "scroll-right".to_string(),
+        ScrollLeft => "scroll-left".to_string(),
+        Exit => "exit".to_string(),
+        LowResolution => "lores".to_string(),
+        HighResolution => "hires".to_string(),
+        BigCharacter { x } => format!("i := bighex v{:x}", x),
+        SaveFlags { x } => format!("saveflags v{:x}", x),
+        LoadFlags { x } => format!("loadflags v{:x}", x),
+
+        ScrollUp(n) => format!("scroll-up {}", n),
+        SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+        LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+        LongIndex => format!("i := long {}", long),
+        SelectPlanes(n) => format!("plane {}", n),
+        LoadAudioPattern => "audio".to_string(),
+        SetPitch { x } => format!("pitch := v{:x}", x),
+
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// Writes an instruction Octo has no syntax for as raw bytes, with the
+/// Cowgod-style mnemonic in a comment.
+fn unsupported_in_octo(
+    instruction: &Instruction,
+    operand: Option<u16>,
+    address: &dyn Fn(u16) -> String,
+) -> String {
+    let mut words = vec![instruction.encode()];
+    if instruction.size() == 4 {
+        words.extend(operand);
+    }
+    let bytes: Vec<String> = words
+        .iter()
+        .flat_map(|word| [word >> 8, word & 0xFF])
+        .map(|byte| format!("{:#04X}", byte))
+        .collect();
+    format!(
+        "{} # {}",
+        bytes.join(" "),
+        cowgod(instruction, address, operand)
+    )
+}
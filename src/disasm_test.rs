@@ -0,0 +1,56 @@
+#[cfg(test)]
+use crate::disasm::{analyze, disassemble, ByteKind, LabelKind, Syntax};
+#[cfg(test)]
+use crate::platform::Platform;
+
+#[cfg(test)]
+const ROM: [u8; 20] = [
+    0x00, 0xE0, // clear
+    0xA2, 0x0C, // i := 0x20C
+    0x60, 0x05, // v0 := 5
+    0xD0, 0x05, // sprite v0 v0 5
+    0x22, 0x10, // call 0x210
+    0x12, 0x0A, // jump 0x20A
+    0xF0, 0x90, 0x90, 0x90, // sprite rows
+    0x00, 0xEE, // return
+    0xFF, 0xFF, // never reached
+];
+
+#[test]
+fn test_analyze_finds_code_data_and_labels() {
+    let map = analyze(&ROM, Platform::Chip8);
+    assert_eq!(map.kind(0x200), Some(ByteKind::Code));
+    assert_eq!(map.kind(0x20C), Some(ByteKind::Data));
+    // The sprite is five rows tall, but the fifth byte is executed as code.
+    assert_eq!(map.kind(0x210), Some(ByteKind::Code));
+    assert_eq!(map.kind(0x212), Some(ByteKind::Unreachable));
+    assert_eq!(map.labels.get(&0x210), Some(&LabelKind::Subroutine));
+    assert_eq!(map.labels.get(&0x20A), Some(&LabelKind::Jump));
+    assert_eq!(map.labels.get(&0x20C), Some(&LabelKind::Data));
+}
+
+#[test]
+fn test_disassemble_syntaxes() {
+    let cowgod = disassemble(&ROM, Platform::Chip8, Syntax::Cowgod);
+    assert!(cowgod.contains("0202  A2 0C       LD I, data_20C\n"));
+    assert!(cowgod.contains("sub_210:\n0210  00 EE       RET\n"));
+    assert!(cowgod.contains("0212  FF          DB 0xFF              ; unreachable\n"));
+
+    let octo = disassemble(&ROM, Platform::Chip8, Syntax::Octo);
+    assert!(octo.contains("0206  D0 05       sprite v0 v0 5\n"));
+    assert!(octo.contains("0208  22 10       sub_210\n"));
+    assert!(octo.contains("020C  F0          0xF0                 # ####....\n"));
+}
+
+#[test]
+fn test_skip_over_long_instruction() {
+    let rom = [
+        0x30, 0x00, // if v0 != 0 then
+        0xF0, 0x00, 0x03, 0x00, // i := long 0x300
+        0x00, 0xFD, // exit
+    ];
+    let map = analyze(&rom, Platform::XoChip);
+    assert!(map.kinds.iter().all(|&kind| kind == ByteKind::Code));
+    let octo = disassemble(&rom, Platform::XoChip, Syntax::Octo);
+    assert!(octo.contains("0202  F0 00 03 00 i := long 0x0300\n"));
+}
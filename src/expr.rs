@@ -0,0 +1,235 @@
+use crate::processor::CPU;
+use crate::random::RandomSource;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Register {
+    V(u8),
+    I,
+    PC,
+    SP,
+    DT,
+    ST,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Register(Register),
+    /// `[address]`, the byte of memory there.
+    Memory(Box<Node>),
+    Not(Box<Node>),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+}
+
+/// The operators from loosest to tightest binding.
+const PRECEDENCE: [&[(&str, Operator)]; 7] = [
+    &[("||", Operator::Or)],
+    &[("&&", Operator::And)],
+    &[("|", Operator::BitOr)],
+    &[("^", Operator::BitXor)],
+    &[("&", Operator::BitAnd)],
+    &[
+        ("==", Operator::Equal),
+        ("!=", Operator::NotEqual),
+        ("<=", Operator::LessOrEqual),
+        (">=", Operator::GreaterOrEqual),
+        ("<", Operator::Less),
+        (">", Operator::Greater),
+    ],
+    &[("+", Operator::Add), ("-", Operator::Subtract)],
+];
+
+/// A condition or value over the CPU state, such as
+/// `v3 == 0x10 && i > 0x300` or `[i] + 1`. Names are V0-VF, I, PC, SP, DT and
+/// ST, `[address]` is a byte of memory, numbers are decimal unless written
+/// with `0x`, and the operators are C's. Comparisons and logic give 1 or 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expression {
+    text: String,
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let tokens = tokenize(text)?;
+        let mut position = 0;
+        let root = parse_binary(&tokens, &mut position, 0)?;
+        if let Some(token) = tokens.get(position) {
+            return Err(format!("Unexpected {} in {}", token, text));
+        }
+        Ok(Expression {
+            text: text.trim().to_string(),
+            root,
+        })
+    }
+
+    pub fn evaluate(&self, cpu: &CPU<impl RandomSource>) -> i64 {
+        evaluate(&self.root, cpu)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(text[start..end].to_ascii_lowercase());
+        } else {
+            chars.next();
+            let pair = chars.peek().map(|&(_, next)| format!("{}{}", c, next));
+            let two = ["||", "&&", "==", "!=", "<=", ">="];
+            match pair {
+                Some(pair) if two.contains(&pair.as_str()) => {
+                    chars.next();
+                    tokens.push(pair);
+                }
+                _ if "|&^<>+-![]()".contains(c) => tokens.push(c.to_string()),
+                _ => return Err(format!("Unexpected {} in {}", c, text)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_binary(tokens: &[String], position: &mut usize, level: usize) -> Result<Node, String> {
+    let Some(operators) = PRECEDENCE.get(level) else {
+        return parse_unary(tokens, position);
+    };
+    let mut left = parse_binary(tokens, position, level + 1)?;
+    while let Some(&(_, operator)) = tokens
+        .get(*position)
+        .and_then(|token| operators.iter().find(|(text, _)| text == token))
+    {
+        *position += 1;
+        let right = parse_binary(tokens, position, level + 1)?;
+        left = Node::Binary(operator, Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_unary(tokens: &[String], position: &mut usize) -> Result<Node, String> {
+    let token = tokens
+        .get(*position)
+        .ok_or("The expression ends too soon")?;
+    *position += 1;
+    let mut inner = |close: Option<&str>| -> Result<Box<Node>, String> {
+        let node = match close {
+            Some(_) => parse_binary(tokens, position, 0)?,
+            None => parse_unary(tokens, position)?,
+        };
+        if let Some(close) = close {
+            if tokens.get(*position).map(String::as_str) != Some(close) {
+                return Err(format!("Expected {}", close));
+            }
+            *position += 1;
+        }
+        Ok(Box::new(node))
+    };
+    match token.as_str() {
+        "!" => Ok(Node::Not(inner(None)?)),
+        "-" => Ok(Node::Negate(inner(None)?)),
+        "(" => Ok(*inner(Some(")"))?),
+        "[" => Ok(Node::Memory(inner(Some("]"))?)),
+        "i" => Ok(Node::Register(Register::I)),
+        "pc" => Ok(Node::Register(Register::PC)),
+        "sp" => Ok(Node::Register(Register::SP)),
+        "dt" => Ok(Node::Register(Register::DT)),
+        "st" => Ok(Node::Register(Register::ST)),
+        name if name.len() == 2 && name.starts_with('v') => u8::from_str_radix(&name[1..], 16)
+            .map(|x| Node::Register(Register::V(x)))
+            .map_err(|_| format!("Unknown register {}", name)),
+        number => {
+            let value = if let Some(hex) = number.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(binary) = number.strip_prefix("0b") {
+                i64::from_str_radix(binary, 2)
+            } else {
+                number.parse()
+            };
+            value
+                .map(Node::Number)
+                .map_err(|_| format!("Expected a value, found {}", number))
+        }
+    }
+}
+
+fn evaluate(node: &Node, cpu: &CPU<impl RandomSource>) -> i64 {
+    match node {
+        Node::Number(value) => *value,
+        Node::Register(register) => match *register {
+            Register::V(x) => cpu.registers[x as usize] as i64,
+            Register::I => cpu.index as i64,
+            Register::PC => cpu.program_counter as i64,
+            Register::SP => cpu.stack_pointer as i64,
+            Register::DT => cpu.delay_timer as i64,
+            Register::ST => cpu.sound_timer as i64,
+        },
+        // Outside memory reads as 0 rather than failing, so a condition on
+        // [i] can't stop the program by itself.
+        Node::Memory(address) => usize::try_from(evaluate(address, cpu))
+            .ok()
+            .filter(|&address| address < cpu.platform.memory_size())
+            .map_or(0, |address| cpu.memory[address] as i64),
+        Node::Not(inner) => (evaluate(inner, cpu) == 0) as i64,
+        Node::Negate(inner) => evaluate(inner, cpu).wrapping_neg(),
+        Node::Binary(operator, left, right) => {
+            let left = evaluate(left, cpu);
+            // && and || short-circuit as in C.
+            match operator {
+                Operator::Or if left != 0 => return 1,
+                Operator::And if left == 0 => return 0,
+                _ => (),
+            }
+            let right = evaluate(right, cpu);
+            match operator {
+                Operator::Or | Operator::And => (right != 0) as i64,
+                Operator::BitOr => left | right,
+                Operator::BitXor => left ^ right,
+                Operator::BitAnd => left & right,
+                Operator::Equal => (left == right) as i64,
+                Operator::NotEqual => (left != right) as i64,
+                Operator::Less => (left < right) as i64,
+                Operator::LessOrEqual => (left <= right) as i64,
+                Operator::Greater => (left > right) as i64,
+                Operator::GreaterOrEqual => (left >= right) as i64,
+                Operator::Add => left.wrapping_add(right),
+                Operator::Subtract => left.wrapping_sub(right),
+            }
+        }
+    }
+}
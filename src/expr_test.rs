@@ -0,0 +1,40 @@
+#[cfg(test)]
+use crate::expr::Expression;
+#[cfg(test)]
+use crate::platform::Platform;
+#[cfg(test)]
+use crate::processor::CPU;
+#[cfg(test)]
+use crate::quirks::Quirks;
+#[cfg(test)]
+use crate::random::SeededRandom;
+
+#[cfg(test)]
+fn evaluate(text: &str) -> i64 {
+    let mut cpu = CPU::with_rng(Platform::Chip8, Quirks::default(), SeededRandom::new(1));
+    cpu.registers[3] = 0x10;
+    cpu.index = 0x300;
+    cpu.memory[0x300] = 0xAB;
+    cpu.delay_timer = 60;
+    Expression::parse(text).unwrap().evaluate(&cpu)
+}
+
+#[test]
+fn test_expressions() {
+    assert_eq!(evaluate("v3 == 0x10 && i > 0x300"), 0);
+    assert_eq!(evaluate("V3 == 0x10 && I >= 0x300"), 1);
+    assert_eq!(evaluate("[i] + [i + 1] - 1"), 0xAA);
+    assert_eq!(evaluate("[0x1000000]"), 0);
+    assert_eq!(evaluate("1 + 2 == 3 || [0] == 1"), 1);
+    assert_eq!(evaluate("dt & 0b1100 | 1 ^ 3"), 14);
+    assert_eq!(evaluate("!(dt < 60) && -v3 == 0 - 16"), 1);
+}
+
+#[test]
+fn test_expression_errors() {
+    for text in ["", "v3 ==", "vg", "(v0", "[i", "v0 = 1", "v0 v1", "3 * 4"] {
+        assert!(Expression::parse(text).is_err(), "{}", text);
+    }
+    let expression = Expression::parse(" v0 != 1 ").unwrap();
+    assert_eq!(expression.to_string(), "v0 != 1");
+}
@@ -0,0 +1,46 @@
+use core::fmt;
+
+/// What happened when the CPU was stepped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction was executed.
+    Executed,
+    /// Execution is blocked until a key is pressed or the delay timer runs out.
+    Waiting,
+    /// The program has stopped itself and will not run any further.
+    Exited,
+}
+
+/// An error that stops the program from running. When `CPU::tick` returns a
+/// fault, the program counter is left on the instruction that caused it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuFault {
+    /// 2NNN was executed with all sixteen stack entries in use.
+    StackOverflow,
+    /// 00EE was executed with an empty stack.
+    StackUnderflow,
+    /// An instruction fetch or I-relative access fell outside memory.
+    MemoryOutOfBounds { address: usize },
+    /// The opcode is not part of the selected platform's instruction set.
+    UnknownOpcode(u16),
+    /// The ROM does not fit between the load address and the end of memory.
+    RomTooLarge { size: usize, capacity: usize },
+}
+
+impl fmt::Display for CpuFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuFault::StackOverflow => write!(f, "Stack overflow"),
+            CpuFault::StackUnderflow => write!(f, "Stack underflow"),
+            CpuFault::MemoryOutOfBounds { address } => {
+                write!(f, "Memory access out of bounds at {:#06X}", address)
+            }
+            CpuFault::UnknownOpcode(opcode) => write!(f, "Unknown opcode {:#06X}", opcode),
+            CpuFault::RomTooLarge { size, capacity } => write!(
+                f,
+                "ROM is {} bytes but only {} bytes are available",
+                size, capacity
+            ),
+        }
+    }
+}
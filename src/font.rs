@@ -0,0 +1,172 @@
+/// Where the small font is installed unless another address is chosen. The
+/// big font follows straight after it.
+pub const DEFAULT_FONT_ADDRESS: u16 = 0x050;
+
+pub const SMALL_FONT_SIZE: usize = 16 * 5;
+pub const BIG_FONT_SIZE: usize = 16 * 10;
+
+/// The 4x5 hex digits read through FX29 and the 8x10 digits read through FX30.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Font {
+    pub small: [u8; SMALL_FONT_SIZE],
+    pub big: [u8; BIG_FONT_SIZE],
+}
+
+impl Font {
+    pub const COSMAC_VIP: Font = Font {
+        small: [
+            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+            0x60, 0x20, 0x20, 0x20, 0x70, // 1
+            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+            0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+            0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+            0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+            0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+        ],
+        big: SCHIP_BIG_FONT,
+    };
+
+    pub const DREAM_6800: Font = Font {
+        small: [
+            0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+            0x40, 0x40, 0x40, 0x40, 0x40, // 1
+            0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+            0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+            0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+            0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+            0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+            0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+            0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+            0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+            0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+            0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+            0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+            0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+            0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+            0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+        ],
+        big: SCHIP_BIG_FONT,
+    };
+
+    pub const ETI_660: Font = Font {
+        small: [
+            0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+            0x20, 0x20, 0x20, 0x20, 0x20, // 1
+            0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+            0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+            0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+            0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+            0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+            0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+            0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+            0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+            0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+            0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+            0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+            0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+            0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+            0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+        ],
+        big: SCHIP_BIG_FONT,
+    };
+
+    pub const FISH_N_CHIPS: Font = Font {
+        small: [
+            0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+            0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+            0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+            0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+            0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+            0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+            0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+            0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+            0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+            0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+            0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+            0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+            0x60, 0x80, 0x80, 0x80, 0x60, // C
+            0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+            0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+            0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+        ],
+        big: SCHIP_BIG_FONT,
+    };
+
+    pub const OCTO: Font = Font {
+        small: [
+            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+            0x20, 0x60, 0x20, 0x20, 0x70, // 1
+            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+        ],
+        big: SCHIP_BIG_FONT,
+    };
+
+    /// Looks up a built-in font by the name accepted on the command line.
+    pub fn from_name(name: &str) -> Option<Font> {
+        match name {
+            "vip" | "cosmac-vip" => Some(Font::COSMAC_VIP),
+            "dream6800" | "dream-6800" => Some(Font::DREAM_6800),
+            "eti660" | "eti-660" => Some(Font::ETI_660),
+            "fish" | "fish-n-chips" => Some(Font::FISH_N_CHIPS),
+            "octo" => Some(Font::OCTO),
+            _ => None,
+        }
+    }
+
+    /// Builds a font from the contents of a font file: the 80 bytes of the
+    /// small font, optionally followed by the 160 bytes of the big font.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Font, &'static str> {
+        let mut font = Font::OCTO;
+        match bytes.len() {
+            SMALL_FONT_SIZE => font.small.copy_from_slice(bytes),
+            len if len == SMALL_FONT_SIZE + BIG_FONT_SIZE => {
+                font.small.copy_from_slice(&bytes[..SMALL_FONT_SIZE]);
+                font.big.copy_from_slice(&bytes[SMALL_FONT_SIZE..]);
+            }
+            _ => return Err("Font files must hold 80 bytes, or 240 with the big font"),
+        }
+        Ok(font)
+    }
+}
+
+/// The SUPER-CHIP 1.1 big digits, with Octo's letters filling in A-F.
+const SCHIP_BIG_FONT: [u8; BIG_FONT_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
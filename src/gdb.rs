@@ -0,0 +1,491 @@
+use crate::debugger::{Debugger, Watch};
+use crate::fault::{CpuFault, StepOutcome};
+use crate::processor::CPU;
+use crate::random::RandomSource;
+
+/// V0-VF, then I, PC, SP, DT and ST, numbered as in `target_xml`.
+const REGISTER_COUNT: usize = 21;
+const INDEX: usize = 16;
+const PROGRAM_COUNTER: usize = 17;
+const STACK_POINTER: usize = 18;
+const DELAY_TIMER: usize = 19;
+const SOUND_TIMER: usize = 20;
+
+/// Signal numbers for stop replies.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Where the program stands as far as gdb is concerned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetState {
+    /// Waiting for gdb to say what to do next.
+    Stopped,
+    /// Running until a breakpoint, a fault or an interrupt from gdb.
+    Running,
+    /// gdb has detached; the program runs on without it.
+    Detached,
+    /// gdb has killed the program.
+    Killed,
+}
+
+/// A GDB Remote Serial Protocol stub. Bytes read from the connection go in
+/// through `receive` and the bytes to send back come out, so the frontend
+/// owns the socket and the stub can be driven directly in tests.
+#[derive(Clone, Debug)]
+pub struct GdbStub<R: RandomSource> {
+    /// Software breakpoints, set with `Z0`.
+    pub debugger: Debugger<R>,
+    pub state: TargetState,
+    /// A packet that hasn't finished arriving.
+    input: Vec<u8>,
+    /// The last packet sent, for when gdb asks for it again.
+    last_packet: Vec<u8>,
+    /// Set by `QStartNoAckMode`, after which packets aren't acknowledged.
+    no_ack: bool,
+}
+
+impl<R: RandomSource> Default for GdbStub<R> {
+    fn default() -> Self {
+        GdbStub::new()
+    }
+}
+
+impl<R: RandomSource> GdbStub<R> {
+    /// A stub with the program stopped, as gdb expects when it attaches.
+    pub fn new() -> Self {
+        GdbStub {
+            debugger: Debugger::new(),
+            state: TargetState::Stopped,
+            input: Vec::new(),
+            last_packet: Vec::new(),
+            no_ack: false,
+        }
+    }
+
+    /// Handles bytes from gdb, returning the bytes to send back. Single steps
+    /// run here; continuing only sets `state` and leaves the frontend to run
+    /// the program.
+    pub fn receive(&mut self, cpu: &mut CPU<R>, keypad: [bool; 16], bytes: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        for &byte in bytes {
+            if self.input.is_empty() {
+                match byte {
+                    b'$' => self.input.push(byte),
+                    b'-' => output.extend_from_slice(&self.last_packet),
+                    // Ctrl-C in gdb.
+                    0x03 if self.state == TargetState::Running => {
+                        self.state = TargetState::Stopped;
+                        output.extend(self.packet(&format!("S{:02x}", SIGINT)));
+                    }
+                    // Acknowledgements, and anything between packets.
+                    _ => (),
+                }
+                continue;
+            }
+
+            self.input.push(byte);
+            let length = self.input.len();
+            if length < 4 || self.input[length - 3] != b'#' {
+                continue;
+            }
+            let packet = std::mem::take(&mut self.input);
+            let data = &packet[1..length - 3];
+            let checksum = std::str::from_utf8(&packet[length - 2..])
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+            if checksum != Some(checksum_of(data)) {
+                if !self.no_ack {
+                    output.push(b'-');
+                }
+                continue;
+            }
+            if !self.no_ack {
+                output.push(b'+');
+            }
+            let command = String::from_utf8_lossy(data);
+            if let Some(reply) = self.command(cpu, keypad, &command) {
+                output.extend(self.packet(&reply));
+            }
+        }
+        output
+    }
+
+    /// Checks for a breakpoint before the frontend runs the instruction at
+    /// PC, returning the stop reply to send if there is one.
+    pub fn should_break(&mut self, cpu: &CPU<R>) -> Option<Vec<u8>> {
+        if self.state != TargetState::Running || !self.debugger.should_break(cpu) {
+            return None;
+        }
+        self.state = TargetState::Stopped;
+        let reply = self.trap_reply();
+        Some(self.packet(&reply))
+    }
+
+    /// Stops the program after the frontend ran into a fault or an exit,
+    /// returning the stop reply to send.
+    pub fn stop(&mut self, result: Result<StepOutcome, CpuFault>) -> Vec<u8> {
+        self.state = TargetState::Stopped;
+        self.packet(&stop_reply(result))
+    }
+
+    /// Runs one packet, returning its reply. Packets the stub doesn't know
+    /// get an empty reply, which tells gdb they aren't supported.
+    fn command(&mut self, cpu: &mut CPU<R>, keypad: [bool; 16], command: &str) -> Option<String> {
+        let error = || Some("E01".to_string());
+        let ok = || Some("OK".to_string());
+        let first = command.chars().next().map_or(0, char::len_utf8);
+        let (kind, args) = command.split_at(first);
+
+        if let Some(features) = command.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = parse_pair(features, ',') else {
+                return error();
+            };
+            let xml = target_xml();
+            let start = (offset as usize).min(xml.len());
+            let end = (start + length as usize).min(xml.len());
+            let more = if end < xml.len() { 'm' } else { 'l' };
+            return Some(format!("{}{}", more, &xml[start..end]));
+        }
+        match command {
+            "?" => return Some(format!("S{:02x}", SIGTRAP)),
+            "qAttached" => return Some("1".to_string()),
+            "qC" => return Some("QC1".to_string()),
+            "qfThreadInfo" => return Some("m1".to_string()),
+            "qsThreadInfo" => return Some("l".to_string()),
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                return ok();
+            }
+            _ if command.starts_with("qSupported") => {
+                return Some(
+                    "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+;ReverseStep+;ReverseContinue+"
+                        .to_string(),
+                );
+            }
+            "bs" => {
+                if !self.debugger.step_back(cpu) {
+                    return Some(format!("T{:02x}replaylog:begin;", SIGTRAP));
+                }
+                return Some(format!("S{:02x}", SIGTRAP));
+            }
+            "bc" => {
+                if !self.debugger.reverse_continue(cpu) {
+                    return Some(format!("T{:02x}replaylog:begin;", SIGTRAP));
+                }
+                return Some(self.trap_reply());
+            }
+            _ => (),
+        }
+
+        match kind {
+            "g" => {
+                let values = (0..REGISTER_COUNT).map(|n| encode_register(cpu, n));
+                Some(values.collect())
+            }
+            "G" => {
+                let mut rest = args;
+                for n in 0..REGISTER_COUNT {
+                    let digits = register_size(n) * 2;
+                    let Some(value) = rest.get(..digits).and_then(decode_le) else {
+                        return error();
+                    };
+                    rest = &rest[digits..];
+                    if !write_register(cpu, n, value) {
+                        return error();
+                    }
+                }
+                self.debugger.history.edited();
+                ok()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTER_COUNT => Some(encode_register(cpu, n)),
+                _ => error(),
+            },
+            "P" => {
+                let Some((n, value)) = args.split_once('=') else {
+                    return error();
+                };
+                let n = usize::from_str_radix(n, 16).unwrap_or(REGISTER_COUNT);
+                match decode_le(value) {
+                    Some(value) if n < REGISTER_COUNT && write_register(cpu, n, value) => {
+                        self.debugger.history.edited();
+                        ok()
+                    }
+                    _ => error(),
+                }
+            }
+            "m" => {
+                let Some((address, length)) = parse_pair(args, ',') else {
+                    return error();
+                };
+                let size = cpu.platform.memory_size();
+                let start = address as usize;
+                if start >= size {
+                    return error();
+                }
+                let end = (start + length as usize).min(size);
+                Some(
+                    cpu.memory[start..end]
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect(),
+                )
+            }
+            "M" => {
+                let Some((range, data)) = args.split_once(':') else {
+                    return error();
+                };
+                let Some((address, length)) = parse_pair(range, ',') else {
+                    return error();
+                };
+                let start = address as usize;
+                let end = start + length as usize;
+                let bytes = decode_hex(data);
+                match bytes {
+                    Some(bytes)
+                        if bytes.len() == end - start && end <= cpu.platform.memory_size() =>
+                    {
+                        cpu.memory[start..end].copy_from_slice(&bytes);
+                        self.debugger.history.edited();
+                        ok()
+                    }
+                    _ => error(),
+                }
+            }
+            "Z" | "z" if args.starts_with(['2', '3', '4']) => {
+                let Some(fields) = args.get(2..) else {
+                    return error();
+                };
+                let mut fields = fields.split(',');
+                let (Some(start), Some(length)) = (
+                    fields.next().and_then(|x| u16::from_str_radix(x, 16).ok()),
+                    fields.next().and_then(|x| u16::from_str_radix(x, 16).ok()),
+                ) else {
+                    return error();
+                };
+                if length == 0 {
+                    return error();
+                }
+                let watch = Watch::Memory {
+                    start,
+                    length,
+                    reads: !args.starts_with('2'),
+                    writes: !args.starts_with('3'),
+                };
+                if kind == "Z" {
+                    self.debugger.watch(cpu, watch);
+                } else {
+                    self.debugger.watchpoints.retain(|_, other| *other != watch);
+                }
+                ok()
+            }
+            // Hardware breakpoints work the same as software ones here.
+            "Z" | "z" if args.starts_with("0,") || args.starts_with("1,") => {
+                let Some(address) = args[2..]
+                    .split(',')
+                    .next()
+                    .and_then(|address| u16::from_str_radix(address, 16).ok())
+                else {
+                    return error();
+                };
+                if kind == "Z" {
+                    self.debugger.breakpoints.insert(address);
+                } else {
+                    self.debugger.breakpoints.remove(&address);
+                }
+                ok()
+            }
+            "c" => {
+                if !self.set_program_counter(cpu, args) {
+                    return error();
+                }
+                self.debugger.resume(cpu);
+                self.state = TargetState::Running;
+                None
+            }
+            "s" => {
+                if !self.set_program_counter(cpu, args) {
+                    return error();
+                }
+                match self.debugger.tick(cpu, keypad) {
+                    Ok(StepOutcome::Executed) => Some(self.trap_reply()),
+                    result => Some(stop_reply(result)),
+                }
+            }
+            // There is only ever one thread.
+            "H" | "T" => ok(),
+            "D" => {
+                self.state = TargetState::Detached;
+                ok()
+            }
+            "k" => {
+                self.state = TargetState::Killed;
+                None
+            }
+            _ => Some(String::new()),
+        }
+    }
+
+    /// Handles the optional resume address of `c` and `s`.
+    fn set_program_counter(&mut self, cpu: &mut CPU<R>, address: &str) -> bool {
+        if address.is_empty() {
+            return true;
+        }
+        match u16::from_str_radix(address, 16) {
+            Ok(address) => {
+                cpu.program_counter = address;
+                self.debugger.history.edited();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// The reply for a SIGTRAP stop, which names the address when a memory
+    /// watchpoint went off.
+    fn trap_reply(&mut self) -> String {
+        let Some(hit) = self.debugger.watch_hit.take() else {
+            return format!("S{:02x}", SIGTRAP);
+        };
+        let (Some((_, address)), Some(Watch::Memory { reads, writes, .. })) =
+            (hit.access, self.debugger.watchpoints.get(&hit.id))
+        else {
+            return format!("S{:02x}", SIGTRAP);
+        };
+        let kind = match (reads, writes) {
+            (true, true) => "awatch",
+            (true, false) => "rwatch",
+            _ => "watch",
+        };
+        format!("T{:02x}{}:{:x};", SIGTRAP, kind, address)
+    }
+
+    /// Frames a reply, keeping it in case gdb asks for it to be resent.
+    fn packet(&mut self, data: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+        for byte in data.bytes() {
+            if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+                body.extend([b'}', byte ^ 0x20]);
+            } else {
+                body.push(byte);
+            }
+        }
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(&body);
+        packet.extend(format!("#{:02x}", checksum_of(&body)).bytes());
+        self.last_packet = packet.clone();
+        packet
+    }
+}
+
+/// The register description gdb reads with `qXfer:features:read`. 16-bit
+/// registers are sent little-endian, like everything else in the protocol
+/// that isn't memory.
+pub fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\">\
+         <feature name=\"org.rusty8.chip8\">",
+    );
+    for n in 0..16 {
+        xml.push_str(&format!(
+            "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\" regnum=\"{}\"/>",
+            n, n
+        ));
+    }
+    xml.push_str(
+        "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\
+         <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+         <reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>\
+         <reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>\
+         <reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>\
+         </feature></target>",
+    );
+    xml
+}
+
+/// The stop reply for the result of running an instruction: a trap after a
+/// step, an exit code when the program stops itself, or the signal a real
+/// processor would raise for the fault.
+fn stop_reply(result: Result<StepOutcome, CpuFault>) -> String {
+    let signal = match result {
+        Ok(StepOutcome::Exited) => return "W00".to_string(),
+        Ok(_) => SIGTRAP,
+        Err(CpuFault::UnknownOpcode(_)) => SIGILL,
+        Err(_) => SIGSEGV,
+    };
+    format!("S{:02x}", signal)
+}
+
+fn register_size(n: usize) -> usize {
+    if n == INDEX || n == PROGRAM_COUNTER {
+        2
+    } else {
+        1
+    }
+}
+
+fn encode_register(cpu: &CPU<impl RandomSource>, n: usize) -> String {
+    let value = match n {
+        INDEX => cpu.index,
+        PROGRAM_COUNTER => cpu.program_counter,
+        STACK_POINTER => cpu.stack_pointer as u16,
+        DELAY_TIMER => cpu.delay_timer as u16,
+        SOUND_TIMER => cpu.sound_timer as u16,
+        _ => cpu.registers[n] as u16,
+    };
+    value.to_le_bytes()[..register_size(n)]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Sets a register, failing if the value doesn't fit it.
+fn write_register(cpu: &mut CPU<impl RandomSource>, n: usize, value: u16) -> bool {
+    let byte = u8::try_from(value).ok();
+    match (n, byte) {
+        (INDEX, _) => cpu.index = value,
+        (PROGRAM_COUNTER, _) => cpu.program_counter = value,
+        (STACK_POINTER, Some(sp)) if sp as usize <= cpu.stack.len() => cpu.stack_pointer = sp,
+        (DELAY_TIMER, Some(dt)) => cpu.delay_timer = dt,
+        (SOUND_TIMER, Some(st)) => cpu.sound_timer = st,
+        (0..=15, Some(v)) => cpu.registers[n] = v,
+        _ => return false,
+    }
+    true
+}
+
+fn parse_pair(text: &str, separator: char) -> Option<(u32, u32)> {
+    let (first, second) = text.split_once(separator)?;
+    Some((
+        u32::from_str_radix(first, 16).ok()?,
+        u32::from_str_radix(second, 16).ok()?,
+    ))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// A register value of one or two bytes, least significant first.
+fn decode_le(text: &str) -> Option<u16> {
+    let bytes = decode_hex(text)?;
+    match bytes[..] {
+        [low] => Some(low as u16),
+        [low, high] => Some(u16::from_le_bytes([low, high])),
+        _ => None,
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
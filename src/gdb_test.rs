@@ -0,0 +1,191 @@
+#[cfg(test)]
+use crate::gdb::{target_xml, GdbStub, TargetState};
+#[cfg(test)]
+use crate::platform::Platform;
+#[cfg(test)]
+use crate::processor::CPU;
+#[cfg(test)]
+use crate::quirks::Quirks;
+#[cfg(test)]
+use crate::random::SeededRandom;
+
+#[cfg(test)]
+fn cpu_with(rom: &[u8]) -> CPU<SeededRandom> {
+    let mut cpu = CPU::with_rng(Platform::Chip8, Quirks::default(), SeededRandom::new(1));
+    cpu.load_rom(rom).unwrap();
+    cpu
+}
+
+#[cfg(test)]
+fn frame(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", data, checksum)
+}
+
+/// Sends one packet and returns the reply without its framing.
+#[cfg(test)]
+fn send(stub: &mut GdbStub<SeededRandom>, cpu: &mut CPU<SeededRandom>, data: &str) -> String {
+    let output = stub.receive(cpu, [false; 16], frame(data).as_bytes());
+    let output = String::from_utf8(output).unwrap();
+    let reply = output.strip_prefix('+').expect("packet not acknowledged");
+    if reply.is_empty() {
+        return reply.to_string();
+    }
+    let data = &reply[1..reply.len() - 3];
+    assert_eq!(reply, frame(data));
+    data.to_string()
+}
+
+#[cfg(test)]
+const ROM: [u8; 6] = [
+    0x60, 0x05, // v0 := 5
+    0xA1, 0x23, // i := 0x123
+    0x12, 0x04, // jump 0x204
+];
+
+#[test]
+fn test_registers_follow_the_target_description() {
+    let mut stub = GdbStub::new();
+    let mut cpu = cpu_with(&ROM);
+    cpu.registers[0xF] = 0xAB;
+    cpu.index = 0x0123;
+
+    let registers = send(&mut stub, &mut cpu, "g");
+    assert_eq!(registers.len(), 2 * (16 + 2 + 2 + 3));
+    assert_eq!(&registers[30..32], "ab");
+    // I, then PC, little-endian.
+    assert_eq!(&registers[32..40], "23010002");
+    assert_eq!(send(&mut stub, &mut cpu, "p11"), "0002");
+
+    assert_eq!(send(&mut stub, &mut cpu, "P3=7f"), "OK");
+    assert_eq!(cpu.registers[3], 0x7F);
+    assert_eq!(send(&mut stub, &mut cpu, "P12=11"), "E01");
+    assert_eq!(send(&mut stub, &mut cpu, "P11=0402"), "OK");
+    assert_eq!(cpu.program_counter, 0x204);
+
+    let xml = target_xml();
+    assert!(xml.contains("<reg name=\"vf\" bitsize=\"8\" type=\"uint8\" regnum=\"15\"/>"));
+    assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>"));
+    let reply = send(&mut stub, &mut cpu, "qXfer:features:read:target.xml:0,10");
+    assert_eq!(reply, format!("m{}", &xml[..0x10]));
+    let reply = send(
+        &mut stub,
+        &mut cpu,
+        "qXfer:features:read:target.xml:10,ffff",
+    );
+    assert_eq!(reply, format!("l{}", &xml[0x10..]));
+}
+
+#[test]
+fn test_memory_reads_and_writes_cpu_memory() {
+    let mut stub = GdbStub::new();
+    let mut cpu = cpu_with(&ROM);
+    assert_eq!(send(&mut stub, &mut cpu, "m200,4"), "6005a123");
+    assert_eq!(send(&mut stub, &mut cpu, "M300,2:beef"), "OK");
+    assert_eq!(cpu.memory[0x300..0x302], [0xBE, 0xEF]);
+    assert_eq!(send(&mut stub, &mut cpu, "m1000,1"), "E01");
+    assert_eq!(send(&mut stub, &mut cpu, "Mfff,2:0000"), "E01");
+}
+
+#[test]
+fn test_breakpoints_stop_a_continued_program() {
+    let mut stub = GdbStub::new();
+    let mut cpu = cpu_with(&ROM);
+    assert_eq!(send(&mut stub, &mut cpu, "s"), "S05");
+    assert_eq!(cpu.registers[0], 5);
+    assert_eq!(send(&mut stub, &mut cpu, "Z0,204,2"), "OK");
+
+    // Continuing has no reply until the program stops.
+    assert_eq!(send(&mut stub, &mut cpu, "c"), "");
+    assert_eq!(stub.state, TargetState::Running);
+    while stub.should_break(&cpu).is_none() {
+        cpu.tick([false; 16]).unwrap();
+    }
+    assert_eq!(cpu.program_counter, 0x204);
+    assert_eq!(stub.state, TargetState::Stopped);
+
+    // The breakpoint it is sitting on doesn't stop it again.
+    send(&mut stub, &mut cpu, "c");
+    assert_eq!(stub.should_break(&cpu), None);
+    let interrupt = stub.receive(&mut cpu, [false; 16], &[0x03]);
+    assert_eq!(interrupt, frame("S02").as_bytes());
+    assert_eq!(send(&mut stub, &mut cpu, "z0,204,2"), "OK");
+    assert!(stub.debugger.breakpoints.is_empty());
+}
+
+#[test]
+fn test_watchpoints_report_the_address() {
+    let mut stub = GdbStub::new();
+    let mut cpu = cpu_with(&[
+        0x60, 0x05, // v0 := 5
+        0xA3, 0x00, // i := 0x300
+        0xF0, 0x55, // save v0
+        0xF0, 0x65, // load v0
+    ]);
+    assert_eq!(send(&mut stub, &mut cpu, "Z2,2ff,2"), "OK");
+    assert_eq!(send(&mut stub, &mut cpu, "Z3,300,1"), "OK");
+    assert_eq!(send(&mut stub, &mut cpu, "s"), "S05");
+    assert_eq!(send(&mut stub, &mut cpu, "s"), "S05");
+    assert_eq!(send(&mut stub, &mut cpu, "s"), "T05watch:300;");
+
+    // A continued program stops before the instruction after the read.
+    send(&mut stub, &mut cpu, "c");
+    assert_eq!(stub.should_break(&cpu), None);
+    stub.debugger.tick(&mut cpu, [false; 16]).unwrap();
+    let stop = stub.should_break(&cpu).unwrap();
+    assert_eq!(stop, frame("T05rwatch:300;").as_bytes());
+    assert_eq!(cpu.program_counter, 0x208);
+
+    assert_eq!(send(&mut stub, &mut cpu, "z2,2ff,2"), "OK");
+    assert_eq!(stub.debugger.watchpoints.len(), 1);
+
+    // Short packets and empty ranges are refused rather than crashing.
+    assert_eq!(send(&mut stub, &mut cpu, "Z2"), "E01");
+    assert_eq!(send(&mut stub, &mut cpu, "z3"), "E01");
+    assert_eq!(send(&mut stub, &mut cpu, "Z2,300,0"), "E01");
+    assert_eq!(stub.debugger.watchpoints.len(), 1);
+}
+
+#[test]
+fn test_reverse_execution() {
+    let mut stub = GdbStub::new();
+    let mut cpu = cpu_with(&ROM);
+    assert_eq!(send(&mut stub, &mut cpu, "bs"), "T05replaylog:begin;");
+    send(&mut stub, &mut cpu, "s");
+    send(&mut stub, &mut cpu, "s");
+    send(&mut stub, &mut cpu, "s");
+    assert_eq!(send(&mut stub, &mut cpu, "bs"), "S05");
+    assert_eq!(cpu.program_counter, 0x204);
+    send(&mut stub, &mut cpu, "Z0,202,2");
+    assert_eq!(send(&mut stub, &mut cpu, "bc"), "S05");
+    assert_eq!((cpu.program_counter, cpu.index), (0x202, 0));
+    assert_eq!(send(&mut stub, &mut cpu, "bc"), "T05replaylog:begin;");
+    assert_eq!(cpu.registers[0], 0);
+}
+
+#[test]
+fn test_faults_and_bad_packets() {
+    let mut stub = GdbStub::new();
+    let mut cpu = cpu_with(&[0xFF, 0xFF]);
+    assert_eq!(send(&mut stub, &mut cpu, "s"), "S04");
+    assert_eq!(cpu.program_counter, 0x200);
+    assert_eq!(send(&mut stub, &mut cpu, "vMustReplyEmpty"), "");
+    assert_eq!(send(&mut stub, &mut cpu, "é"), "");
+    let reply = stub.receive(&mut cpu, [false; 16], b"$\xFF#ff");
+    assert_eq!(reply, format!("+{}", frame("")).as_bytes());
+
+    assert_eq!(stub.receive(&mut cpu, [false; 16], b"$g#00"), b"-");
+    // A packet can arrive in pieces, and a nak asks for the last reply again.
+    assert_eq!(stub.receive(&mut cpu, [false; 16], b"$?#"), b"");
+    let reply = stub.receive(&mut cpu, [false; 16], b"3f");
+    assert_eq!(reply, format!("+{}", frame("S05")).as_bytes());
+    assert_eq!(
+        stub.receive(&mut cpu, [false; 16], b"-"),
+        frame("S05").as_bytes()
+    );
+
+    assert_eq!(send(&mut stub, &mut cpu, "QStartNoAckMode"), "OK");
+    let reply = stub.receive(&mut cpu, [false; 16], frame("D").as_bytes());
+    assert_eq!(reply, frame("OK").as_bytes());
+    assert_eq!(stub.state, TargetState::Detached);
+}
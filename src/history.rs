@@ -0,0 +1,243 @@
+use crate::processor::CPU;
+use crate::random::RandomSource;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+/// Ticks between snapshots, which bounds how much a step back replays.
+const SNAPSHOT_INTERVAL: u64 = 1000;
+
+/// Snapshots kept before the oldest is dropped. Each is a whole `CPU`, about
+/// 320K with the MegaChip screen, so this keeps 64,000 ticks in about 20M.
+const MAX_SNAPSHOTS: usize = 64;
+
+/// Something that changed the CPU: an instruction run with the keys that
+/// were held, or a 60 Hz timer tick.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Event {
+    Tick([bool; 16]),
+    Frame,
+}
+
+#[derive(Clone)]
+struct Snapshot<R: RandomSource> {
+    /// How many ticks had run when it was taken.
+    ticks: u64,
+    /// Where in the events it was taken.
+    event: usize,
+    cpu: Rc<CPU<R>>,
+}
+
+/// A recording of the run for going backwards. The CPU is snapshotted every
+/// `SNAPSHOT_INTERVAL` ticks and everything that happens to it in between
+/// is logged, so any earlier state can be rebuilt by restoring the snapshot
+/// before it and replaying the log. Rebuilding uses the random source in the
+/// snapshot, so CXNN gives the same numbers the second time round.
+///
+/// Going back doesn't throw the later history away, so the program can be
+/// moved forwards again with `seek`. Recording anything new from an earlier
+/// point does.
+#[derive(Clone)]
+pub struct History<R: RandomSource> {
+    snapshots: VecDeque<Snapshot<R>>,
+    events: VecDeque<Event>,
+    /// How many ticks have run to reach the current state.
+    ticks: u64,
+    /// Where the current state is in the events.
+    position: usize,
+    /// Whether the CPU has been changed other than by ticks since the last
+    /// event, which replaying wouldn't reproduce.
+    edited: bool,
+}
+
+impl<R: RandomSource> Default for History<R> {
+    fn default() -> Self {
+        History {
+            snapshots: VecDeque::new(),
+            events: VecDeque::new(),
+            ticks: 0,
+            position: 0,
+            edited: false,
+        }
+    }
+}
+
+/// Snapshots are whole CPUs, so only how many there are is shown.
+impl<R: RandomSource> fmt::Debug for History<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("History")
+            .field("snapshots", &self.snapshots.len())
+            .field("events", &self.events.len())
+            .field("ticks", &self.ticks)
+            .field("position", &self.position)
+            .field("edited", &self.edited)
+            .finish()
+    }
+}
+
+impl<R: RandomSource> History<R> {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    /// Records an instruction about to run with `keypad` held.
+    pub fn record_tick(&mut self, cpu: &CPU<R>, keypad: [bool; 16]) {
+        let due = self
+            .snapshots
+            .back()
+            .is_none_or(|snapshot| self.ticks - snapshot.ticks >= SNAPSHOT_INTERVAL);
+        self.record(cpu, Event::Tick(keypad), due);
+        self.ticks += 1;
+    }
+
+    /// Records a 60 Hz timer tick about to happen. Frames before the first
+    /// tick are left out, as the first snapshot includes them.
+    pub fn record_frame(&mut self, cpu: &CPU<R>) {
+        if !self.snapshots.is_empty() {
+            self.record(cpu, Event::Frame, false);
+        }
+    }
+
+    /// Notes that the CPU has been changed outside of the recording, such as
+    /// a register set from the debugger, so the next event snapshots it.
+    pub fn edited(&mut self) {
+        self.edited = true;
+    }
+
+    /// How many ticks have run to reach the current state.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// The earliest and latest tick counts that can be gone back to.
+    pub fn range(&self) -> Option<(u64, u64)> {
+        let first = self.snapshots.front()?.ticks;
+        let later = self
+            .events
+            .range(self.position..)
+            .filter(|event| matches!(event, Event::Tick(_)))
+            .count();
+        Some((first, self.ticks + later as u64))
+    }
+
+    /// Puts the CPU back as it was once `ticks` ticks had run, just before
+    /// the next one. Returns `false`, leaving the CPU alone, if that isn't in
+    /// the history.
+    pub fn seek(&mut self, cpu: &mut CPU<R>, ticks: u64) -> bool {
+        if self
+            .range()
+            .is_none_or(|(first, last)| ticks < first || ticks > last)
+        {
+            return false;
+        }
+        let index = self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.ticks <= ticks)
+            .unwrap_or(0);
+        match self.replay(cpu, index, ticks, |_, _| ()) {
+            Some(position) => {
+                self.ticks = ticks;
+                self.position = position;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Goes back one tick.
+    pub fn step_back(&mut self, cpu: &mut CPU<R>) -> bool {
+        self.ticks > 0 && self.seek(cpu, self.ticks - 1)
+    }
+
+    /// Replays the history from the start up to now, calling `visit` with
+    /// each tick's number and the CPU just before it ran, then puts the CPU
+    /// back as it was. This is how to search backwards for something.
+    pub fn scan(&mut self, cpu: &mut CPU<R>, visit: impl FnMut(u64, &CPU<R>)) -> bool {
+        if self.snapshots.is_empty() || self.replay(cpu, 0, self.ticks, visit).is_none() {
+            return false;
+        }
+        self.seek(cpu, self.ticks)
+    }
+
+    fn record(&mut self, cpu: &CPU<R>, event: Event, snapshot: bool) {
+        // A new event from an earlier point starts a different future.
+        self.events.truncate(self.position);
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|last| last.event > self.position)
+        {
+            self.snapshots.pop_back();
+        }
+        if snapshot || self.edited {
+            self.snapshot(cpu);
+        }
+        self.events.push_back(event);
+        self.position += 1;
+    }
+
+    fn snapshot(&mut self, cpu: &CPU<R>) {
+        if self
+            .snapshots
+            .back()
+            .is_some_and(|last| last.event == self.position)
+        {
+            self.snapshots.pop_back();
+        }
+        self.snapshots.push_back(Snapshot {
+            ticks: self.ticks,
+            event: self.position,
+            cpu: Rc::new(cpu.clone()),
+        });
+        self.edited = false;
+        if self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+            let start = self.snapshots[0].event;
+            self.events.drain(..start);
+            self.position -= start;
+            for snapshot in &mut self.snapshots {
+                snapshot.event -= start;
+            }
+        }
+    }
+
+    /// Restores snapshot `index` and replays from it up to just before tick
+    /// number `ticks`, calling `visit` before each tick on the way. Returns
+    /// where that is in the events.
+    fn replay(
+        &self,
+        cpu: &mut CPU<R>,
+        index: usize,
+        ticks: u64,
+        mut visit: impl FnMut(u64, &CPU<R>),
+    ) -> Option<usize> {
+        let start = self.snapshots[index].event;
+        let mut count = 0;
+        let mut next = index;
+        for (position, &event) in self.events.iter().enumerate().skip(start) {
+            // Later snapshots are restored on the way past, as they may hold
+            // edits that replaying wouldn't make.
+            while let Some(snapshot) = self.snapshots.get(next) {
+                if snapshot.event != position {
+                    break;
+                }
+                cpu.clone_from(&snapshot.cpu);
+                count = snapshot.ticks;
+                next += 1;
+            }
+            match event {
+                Event::Tick(_) if count == ticks => return Some(position),
+                Event::Tick(keypad) => {
+                    visit(count, cpu);
+                    // Faults happen again the same way, and were already
+                    // reported the first time.
+                    let _ = cpu.tick(keypad);
+                    count += 1;
+                }
+                Event::Frame => cpu.tick_60hz(),
+            }
+        }
+        (count == ticks).then_some(self.events.len())
+    }
+}
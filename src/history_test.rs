@@ -0,0 +1,100 @@
+#[cfg(test)]
+use crate::history::History;
+#[cfg(test)]
+use crate::platform::Platform;
+#[cfg(test)]
+use crate::processor::CPU;
+#[cfg(test)]
+use crate::quirks::Quirks;
+#[cfg(test)]
+use crate::random::SeededRandom;
+
+#[cfg(test)]
+const ROM: [u8; 8] = [
+    0xC0, 0xFF, // v0 := random 0xFF
+    0x81, 0x04, // v1 += v0
+    0xF0, 0x15, // delay := v0
+    0x12, 0x00, // jump 0x200
+];
+
+#[cfg(test)]
+fn new_cpu() -> CPU<SeededRandom> {
+    let mut cpu = CPU::with_rng(Platform::Chip8, Quirks::default(), SeededRandom::new(7));
+    cpu.load_rom(&ROM).unwrap();
+    cpu
+}
+
+/// What the test checks of the CPU.
+#[cfg(test)]
+fn state(cpu: &CPU<SeededRandom>) -> ([u8; 16], u16, u8) {
+    (cpu.registers, cpu.program_counter, cpu.delay_timer)
+}
+
+/// Runs `ticks` ticks with a frame every third one, returning the state
+/// before each tick and after the last.
+#[cfg(test)]
+fn run(
+    history: &mut History<SeededRandom>,
+    cpu: &mut CPU<SeededRandom>,
+    ticks: u64,
+) -> Vec<([u8; 16], u16, u8)> {
+    let mut states = Vec::new();
+    for n in 0..ticks {
+        if n % 3 == 0 {
+            history.record_frame(cpu);
+            cpu.tick_60hz();
+        }
+        states.push(state(cpu));
+        history.record_tick(cpu, [false; 16]);
+        cpu.tick([false; 16]).unwrap();
+    }
+    states.push(state(cpu));
+    states
+}
+
+#[test]
+fn test_seeking_replays_the_same_run() {
+    let mut history = History::new();
+    let mut cpu = new_cpu();
+    let states = run(&mut history, &mut cpu, 2500);
+    assert_eq!(history.range(), Some((0, 2500)));
+
+    // Back across snapshots, then forwards again, with the same random
+    // numbers each time.
+    for ticks in [2499, 1000, 999, 1, 0, 1733, 2500] {
+        assert!(history.seek(&mut cpu, ticks));
+        assert_eq!(history.ticks(), ticks);
+        assert_eq!(state(&cpu), states[ticks as usize], "at tick {}", ticks);
+    }
+    assert!(!history.seek(&mut cpu, 2501));
+
+    assert!(history.seek(&mut cpu, 10));
+    assert!(history.step_back(&mut cpu));
+    assert_eq!(state(&cpu), states[9]);
+}
+
+#[test]
+fn test_recording_from_the_past_replaces_the_future() {
+    let mut history = History::new();
+    let mut cpu = new_cpu();
+    run(&mut history, &mut cpu, 100);
+    assert!(history.seek(&mut cpu, 40));
+
+    // An edit is kept when going back over it later.
+    cpu.registers[0xE] = 0x42;
+    history.edited();
+    let states = run(&mut history, &mut cpu, 20);
+    assert_eq!(history.range(), Some((0, 60)));
+    assert!(history.seek(&mut cpu, 50));
+    assert_eq!(state(&cpu), states[10]);
+    assert_eq!(cpu.registers[0xE], 0x42);
+    assert!(history.seek(&mut cpu, 39));
+    assert_eq!(cpu.registers[0xE], 0);
+
+    // Scanning visits every tick before now and leaves the CPU where it was.
+    let mut visited = Vec::new();
+    assert!(history.scan(&mut cpu, |ticks, _| visited.push(ticks)));
+    assert_eq!(visited, (0..39).collect::<Vec<_>>());
+    assert_eq!(history.ticks(), 39);
+    assert_eq!(cpu.registers[0xE], 0);
+}
@@ -0,0 +1,414 @@
+use crate::platform::Platform;
+
+/// A decoded instruction. Operands are stored as they appear in the opcode:
+/// `x` and `y` are register numbers, `n`, `nn` and `nnn` the immediate
+/// nibble, byte and address.
+///
+/// F000 NNNN and MegaChip's 01NN NNNN are followed by a second word holding
+/// the rest of the address. Only the first word is decoded here; the CPU
+/// reads the second one when the instruction runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    ClearScreen,
+    /// 00EE
+    Return,
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SkipIfEqual { x: u8, nn: u8 },
+    /// 4XNN
+    SkipIfNotEqual { x: u8, nn: u8 },
+    /// 5XY0
+    SkipIfRegistersEqual { x: u8, y: u8 },
+    /// 6XNN
+    SetRegister { x: u8, nn: u8 },
+    /// 7XNN
+    AddImmediate { x: u8, nn: u8 },
+    /// 8XY0
+    Move { x: u8, y: u8 },
+    /// 8XY1
+    Or { x: u8, y: u8 },
+    /// 8XY2
+    And { x: u8, y: u8 },
+    /// 8XY3
+    Xor { x: u8, y: u8 },
+    /// 8XY4
+    Add { x: u8, y: u8 },
+    /// 8XY5
+    Subtract { x: u8, y: u8 },
+    /// 8XY6
+    ShiftRight { x: u8, y: u8 },
+    /// 8XY7
+    SubtractReversed { x: u8, y: u8 },
+    /// 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    /// 9XY0
+    SkipIfRegistersNotEqual { x: u8, y: u8 },
+    /// ANNN
+    SetIndex(u16),
+    /// BNNN, or BXNN with the jumping quirk.
+    JumpWithOffset(u16),
+    /// CXNN
+    Random { x: u8, nn: u8 },
+    /// DXYN
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E
+    SkipIfPressed { x: u8 },
+    /// EXA1
+    SkipIfNotPressed { x: u8 },
+    /// FX07
+    GetDelay { x: u8 },
+    /// FX0A
+    WaitForKey { x: u8 },
+    /// FX15
+    SetDelay { x: u8 },
+    /// FX18
+    SetSound { x: u8 },
+    /// FX1E
+    AddToIndex { x: u8 },
+    /// FX29
+    SmallCharacter { x: u8 },
+    /// FX33
+    BinaryCodedDecimal { x: u8 },
+    /// FX55
+    Store { x: u8 },
+    /// FX65
+    Load { x: u8 },
+
+    /// 00CN (SUPER-CHIP)
+    ScrollDown(u8),
+    /// 00FB (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD (SUPER-CHIP)
+    Exit,
+    /// 00FE (SUPER-CHIP)
+    LowResolution,
+    /// 00FF (SUPER-CHIP)
+    HighResolution,
+    /// FX30 (SUPER-CHIP)
+    BigCharacter { x: u8 },
+    /// FX75 (SUPER-CHIP)
+    SaveFlags { x: u8 },
+    /// FX85 (SUPER-CHIP)
+    LoadFlags { x: u8 },
+
+    /// 00DN (XO-CHIP)
+    ScrollUp(u8),
+    /// 5XY2 (XO-CHIP)
+    SaveRange { x: u8, y: u8 },
+    /// 5XY3 (XO-CHIP)
+    LoadRange { x: u8, y: u8 },
+    /// F000 NNNN (XO-CHIP)
+    LongIndex,
+    /// FN01 (XO-CHIP)
+    SelectPlanes(u8),
+    /// F002 (XO-CHIP)
+    LoadAudioPattern,
+    /// FX3A (XO-CHIP)
+    SetPitch { x: u8 },
+
+    /// 0010 (MegaChip)
+    MegaOff,
+    /// 0011 (MegaChip)
+    MegaOn,
+    /// 01NN NNNN (MegaChip)
+    LongIndex24(u8),
+    /// 02NN (MegaChip)
+    LoadPalette(u8),
+    /// 03NN (MegaChip)
+    SpriteWidth(u8),
+    /// 04NN (MegaChip)
+    SpriteHeight(u8),
+    /// 05NN (MegaChip)
+    ScreenAlpha(u8),
+    /// 060N (MegaChip)
+    PlaySample(u8),
+    /// 0700 (MegaChip)
+    StopSample,
+    /// 080N (MegaChip)
+    BlendMode(u8),
+    /// 09NN (MegaChip)
+    CollisionColor(u8),
+    /// 00BN (MegaChip)
+    MegaScrollUp(u8),
+
+    /// 0230 (HIRES CHIP-8)
+    HiresClearScreen,
+
+    /// 02A0 (CHIP-8X)
+    CycleBackground,
+    /// 5XY1 (CHIP-8X)
+    AddNibbles { x: u8, y: u8 },
+    /// BXYN (CHIP-8X)
+    ZoneColor { x: u8, y: u8, n: u8 },
+    /// EXF2 (CHIP-8X)
+    SkipIfPressedKeypad2 { x: u8 },
+    /// EXF5 (CHIP-8X)
+    SkipIfNotPressedKeypad2 { x: u8 },
+    /// FXF8 (CHIP-8X)
+    PortOutput { x: u8 },
+    /// FXFB (CHIP-8X)
+    PortInput { x: u8 },
+
+    /// 00ED (CHIP-8E)
+    Stop,
+    /// 00F2 (CHIP-8E)
+    NoOperation,
+    /// 0151 (CHIP-8E)
+    WaitForDelay,
+    /// 0188 (CHIP-8E)
+    SkipNext,
+    /// 5XY1 (CHIP-8E)
+    SkipIfGreater { x: u8, y: u8 },
+    /// 5XY2 (CHIP-8E)
+    SaveRangeAndAdvance { x: u8, y: u8 },
+    /// 5XY3 (CHIP-8E)
+    LoadRangeAndAdvance { x: u8, y: u8 },
+    /// BBNN (CHIP-8E)
+    JumpBackward(u8),
+    /// BFNN (CHIP-8E)
+    JumpForward(u8),
+    /// FX03 (CHIP-8E)
+    Output { x: u8 },
+    /// FX1B (CHIP-8E)
+    SkipBytes { x: u8 },
+    /// FX4F (CHIP-8E)
+    SetDelayAndWait { x: u8 },
+    /// FXE3 (CHIP-8E)
+    WaitForInput { x: u8 },
+    /// FXE7 (CHIP-8E)
+    Input { x: u8 },
+}
+
+impl Instruction {
+    /// Decodes `opcode` as `platform` would, or returns `None` if the
+    /// platform has no such instruction.
+    pub fn decode(opcode: u16, platform: Platform) -> Option<Instruction> {
+        use Instruction::*;
+
+        let nibbles = (
+            (opcode & 0xF000) >> 12,
+            (opcode & 0x0F00) >> 8,
+            (opcode & 0x00F0) >> 4,
+            opcode & 0x000F,
+        );
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        let schip = platform.has_superchip_opcodes();
+        let xochip = platform.has_xochip_opcodes();
+        let megachip = platform.has_megachip_opcodes();
+        let hires_chip8 = platform.has_hires_chip8_opcodes();
+        let chip8x = platform.has_chip8x_opcodes();
+        let chip8e = platform.has_chip8e_opcodes();
+
+        let instruction = match nibbles {
+            (0x00, 0x02, 0x03, 0x00) if hires_chip8 => HiresClearScreen,
+            (0x00, 0x02, 0x0a, 0x00) if chip8x => CycleBackground,
+            (0x00, 0x00, 0x0e, 0x0d) if chip8e => Stop,
+            (0x00, 0x00, 0x0f, 0x02) if chip8e => NoOperation,
+            (0x00, 0x01, 0x05, 0x01) if chip8e => WaitForDelay,
+            (0x00, 0x01, 0x08, 0x08) if chip8e => SkipNext,
+            (0x05, _, _, 0x01) if chip8x => AddNibbles { x, y },
+            (0x05, _, _, 0x01) if chip8e => SkipIfGreater { x, y },
+            (0x05, _, _, 0x02) if chip8e => SaveRangeAndAdvance { x, y },
+            (0x05, _, _, 0x03) if chip8e => LoadRangeAndAdvance { x, y },
+            (0x0b, _, _, _) if chip8x => ZoneColor { x, y, n },
+            (0x0b, 0x0b, _, _) if chip8e => JumpBackward(nn),
+            (0x0b, 0x0f, _, _) if chip8e => JumpForward(nn),
+            (0x0e, _, 0x0f, 0x02) if chip8x => SkipIfPressedKeypad2 { x },
+            (0x0e, _, 0x0f, 0x05) if chip8x => SkipIfNotPressedKeypad2 { x },
+            (0x0f, _, 0x00, 0x03) if chip8e => Output { x },
+            (0x0f, _, 0x01, 0x0b) if chip8e => SkipBytes { x },
+            (0x0f, _, 0x04, 0x0f) if chip8e => SetDelayAndWait { x },
+            (0x0f, _, 0x0e, 0x03) if chip8e => WaitForInput { x },
+            (0x0f, _, 0x0e, 0x07) if chip8e => Input { x },
+            (0x0f, _, 0x0f, 0x08) if chip8x => PortOutput { x },
+            (0x0f, _, 0x0f, 0x0b) if chip8x => PortInput { x },
+            (0x00, 0x00, 0x01, 0x00) if megachip => MegaOff,
+            (0x00, 0x00, 0x01, 0x01) if megachip => MegaOn,
+            (0x00, 0x01, _, _) if megachip => LongIndex24(nn),
+            (0x00, 0x02, _, _) if megachip => LoadPalette(nn),
+            (0x00, 0x03, _, _) if megachip => SpriteWidth(nn),
+            (0x00, 0x04, _, _) if megachip => SpriteHeight(nn),
+            (0x00, 0x05, _, _) if megachip => ScreenAlpha(nn),
+            (0x00, 0x06, 0x00, _) if megachip => PlaySample(n),
+            (0x00, 0x07, 0x00, 0x00) if megachip => StopSample,
+            (0x00, 0x08, 0x00, _) if megachip => BlendMode(n),
+            (0x00, 0x09, _, _) if megachip => CollisionColor(nn),
+            (0x00, 0x00, 0x0b, _) if megachip => MegaScrollUp(n),
+            (0x00, 0x00, 0x0c, _) if schip => ScrollDown(n),
+            (0x00, 0x00, 0x0d, _) if xochip => ScrollUp(n),
+            (0x00, 0x00, 0x0e, 0x00) => ClearScreen,
+            (0x00, 0x00, 0x0e, 0x0e) => Return,
+            (0x00, 0x00, 0x0f, 0x0b) if schip => ScrollRight,
+            (0x00, 0x00, 0x0f, 0x0c) if schip => ScrollLeft,
+            (0x00, 0x00, 0x0f, 0x0d) if schip => Exit,
+            (0x00, 0x00, 0x0f, 0x0e) if schip => LowResolution,
+            (0x00, 0x00, 0x0f, 0x0f) if schip => HighResolution,
+            (0x01, _, _, _) => Jump(nnn),
+            (0x02, _, _, _) => Call(nnn),
+            (0x03, _, _, _) => SkipIfEqual { x, nn },
+            (0x04, _, _, _) => SkipIfNotEqual { x, nn },
+            (0x05, _, _, 0x00) => SkipIfRegistersEqual { x, y },
+            (0x05, _, _, 0x02) if xochip => SaveRange { x, y },
+            (0x05, _, _, 0x03) if xochip => LoadRange { x, y },
+            (0x06, _, _, _) => SetRegister { x, nn },
+            (0x07, _, _, _) => AddImmediate { x, nn },
+            (0x08, _, _, 0x00) => Move { x, y },
+            (0x08, _, _, 0x01) => Or { x, y },
+            (0x08, _, _, 0x02) => And { x, y },
+            (0x08, _, _, 0x03) => Xor { x, y },
+            (0x08, _, _, 0x04) => Add { x, y },
+            (0x08, _, _, 0x05) => Subtract { x, y },
+            (0x08, _, _, 0x06) => ShiftRight { x, y },
+            (0x08, _, _, 0x07) => SubtractReversed { x, y },
+            (0x08, _, _, 0x0e) => ShiftLeft { x, y },
+            (0x09, _, _, 0x00) => SkipIfRegistersNotEqual { x, y },
+            (0x0a, _, _, _) => SetIndex(nnn),
+            (0x0b, _, _, _) => JumpWithOffset(nnn),
+            (0x0c, _, _, _) => Random { x, nn },
+            (0x0d, _, _, _) => Draw { x, y, n },
+            (0x0e, _, 0x09, 0x0e) => SkipIfPressed { x },
+            (0x0e, _, 0x0a, 0x01) => SkipIfNotPressed { x },
+            (0x0f, 0x00, 0x00, 0x00) if xochip => LongIndex,
+            (0x0f, _, 0x00, 0x01) if xochip => SelectPlanes(x),
+            (0x0f, 0x00, 0x00, 0x02) if xochip => LoadAudioPattern,
+            (0x0f, _, 0x00, 0x07) => GetDelay { x },
+            (0x0f, _, 0x00, 0x0a) => WaitForKey { x },
+            (0x0f, _, 0x01, 0x05) => SetDelay { x },
+            (0x0f, _, 0x01, 0x08) => SetSound { x },
+            (0x0f, _, 0x01, 0x0e) => AddToIndex { x },
+            (0x0f, _, 0x02, 0x09) => SmallCharacter { x },
+            (0x0f, _, 0x03, 0x00) if schip => BigCharacter { x },
+            (0x0f, _, 0x03, 0x03) => BinaryCodedDecimal { x },
+            (0x0f, _, 0x03, 0x0a) if xochip => SetPitch { x },
+            (0x0f, _, 0x05, 0x05) => Store { x },
+            (0x0f, _, 0x06, 0x05) => Load { x },
+            (0x0f, _, 0x07, 0x05) if schip => SaveFlags { x },
+            (0x0f, _, 0x08, 0x05) if schip => LoadFlags { x },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// Turns the instruction back into its opcode. Decoding the result on the
+    /// platform it came from gives back the same instruction.
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        let xy = |x: u8, y: u8| ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4);
+        let xnn = |x: u8, nn: u8| ((x as u16 & 0xF) << 8) | nn as u16;
+        let x_ = |x: u8| (x as u16 & 0xF) << 8;
+
+        match *self {
+            ClearScreen => 0x00E0,
+            Return => 0x00EE,
+            Jump(nnn) => 0x1000 | (nnn & 0x0FFF),
+            Call(nnn) => 0x2000 | (nnn & 0x0FFF),
+            SkipIfEqual { x, nn } => 0x3000 | xnn(x, nn),
+            SkipIfNotEqual { x, nn } => 0x4000 | xnn(x, nn),
+            SkipIfRegistersEqual { x, y } => 0x5000 | xy(x, y),
+            SetRegister { x, nn } => 0x6000 | xnn(x, nn),
+            AddImmediate { x, nn } => 0x7000 | xnn(x, nn),
+            Move { x, y } => 0x8000 | xy(x, y),
+            Or { x, y } => 0x8001 | xy(x, y),
+            And { x, y } => 0x8002 | xy(x, y),
+            Xor { x, y } => 0x8003 | xy(x, y),
+            Add { x, y } => 0x8004 | xy(x, y),
+            Subtract { x, y } => 0x8005 | xy(x, y),
+            ShiftRight { x, y } => 0x8006 | xy(x, y),
+            SubtractReversed { x, y } => 0x8007 | xy(x, y),
+            ShiftLeft { x, y } => 0x800E | xy(x, y),
+            SkipIfRegistersNotEqual { x, y } => 0x9000 | xy(x, y),
+            SetIndex(nnn) => 0xA000 | (nnn & 0x0FFF),
+            JumpWithOffset(nnn) => 0xB000 | (nnn & 0x0FFF),
+            Random { x, nn } => 0xC000 | xnn(x, nn),
+            Draw { x, y, n } => 0xD000 | xy(x, y) | (n as u16 & 0xF),
+            SkipIfPressed { x } => 0xE09E | x_(x),
+            SkipIfNotPressed { x } => 0xE0A1 | x_(x),
+            GetDelay { x } => 0xF007 | x_(x),
+            WaitForKey { x } => 0xF00A | x_(x),
+            SetDelay { x } => 0xF015 | x_(x),
+            SetSound { x } => 0xF018 | x_(x),
+            AddToIndex { x } => 0xF01E | x_(x),
+            SmallCharacter { x } => 0xF029 | x_(x),
+            BinaryCodedDecimal { x } => 0xF033 | x_(x),
+            Store { x } => 0xF055 | x_(x),
+            Load { x } => 0xF065 | x_(x),
+
+            ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LowResolution => 0x00FE,
+            HighResolution => 0x00FF,
+            BigCharacter { x } => 0xF030 | x_(x),
+            SaveFlags { x } => 0xF075 | x_(x),
+            LoadFlags { x } => 0xF085 | x_(x),
+
+            ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            SaveRange { x, y } => 0x5002 | xy(x, y),
+            LoadRange { x, y } => 0x5003 | xy(x, y),
+            LongIndex => 0xF000,
+            SelectPlanes(n) => 0xF001 | x_(n),
+            LoadAudioPattern => 0xF002,
+            SetPitch { x } => 0xF03A | x_(x),
+
+            MegaOff => 0x0010,
+            MegaOn => 0x0011,
+            LongIndex24(nn) => 0x0100 | nn as u16,
+            LoadPalette(nn) => 0x0200 | nn as u16,
+            SpriteWidth(nn) => 0x0300 | nn as u16,
+            SpriteHeight(nn) => 0x0400 | nn as u16,
+            ScreenAlpha(nn) => 0x0500 | nn as u16,
+            PlaySample(n) => 0x0600 | (n as u16 & 0xF),
+            StopSample => 0x0700,
+            BlendMode(n) => 0x0800 | (n as u16 & 0xF),
+            CollisionColor(nn) => 0x0900 | nn as u16,
+            MegaScrollUp(n) => 0x00B0 | (n as u16 & 0xF),
+
+            HiresClearScreen => 0x0230,
+
+            CycleBackground => 0x02A0,
+            AddNibbles { x, y } => 0x5001 | xy(x, y),
+            ZoneColor { x, y, n } => 0xB000 | xy(x, y) | (n as u16 & 0xF),
+            SkipIfPressedKeypad2 { x } => 0xE0F2 | x_(x),
+            SkipIfNotPressedKeypad2 { x } => 0xE0F5 | x_(x),
+            PortOutput { x } => 0xF0F8 | x_(x),
+            PortInput { x } => 0xF0FB | x_(x),
+
+            Stop => 0x00ED,
+            NoOperation => 0x00F2,
+            WaitForDelay => 0x0151,
+            SkipNext => 0x0188,
+            SkipIfGreater { x, y } => 0x5001 | xy(x, y),
+            SaveRangeAndAdvance { x, y } => 0x5002 | xy(x, y),
+            LoadRangeAndAdvance { x, y } => 0x5003 | xy(x, y),
+            JumpBackward(nn) => 0xBB00 | nn as u16,
+            JumpForward(nn) => 0xBF00 | nn as u16,
+            Output { x } => 0xF003 | x_(x),
+            SkipBytes { x } => 0xF01B | x_(x),
+            SetDelayAndWait { x } => 0xF04F | x_(x),
+            WaitForInput { x } => 0xF0E3 | x_(x),
+            Input { x } => 0xF0E7 | x_(x),
+        }
+    }
+
+    /// The number of bytes the instruction takes up, including the address
+    /// word that follows F000 and 01NN.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LongIndex | Instruction::LongIndex24(_) => 4,
+            _ => 2,
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! The rusty8 interpreter core. Frontends such as `rusty8-sdl` drive a `CPU`
+//! and draw its `Renderer`; nothing in here depends on a windowing or audio
+//! library.
+//!
+//! Without the default `std` feature the crate is `#![no_std]` and never
+//! allocates, so the same interpreter can run on microcontrollers. The caller
+//! then supplies the random number generator through `CPU::with_rng`.
+//!
+//! The MegaChip screen makes up most of a `CPU`, so it comes with the default
+//! `megachip` feature. Turning that off leaves a CPU of a little over 64K,
+//! and MegaChip's own opcodes are then unknown.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+pub mod asm;
+#[cfg(feature = "std")]
+pub mod cartridge;
+#[cfg(feature = "std")]
+pub mod cfg;
+#[cfg(feature = "std")]
+pub mod dap;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod decompile;
+#[cfg(feature = "std")]
+pub mod disasm;
+#[cfg(feature = "std")]
+pub mod expr;
+pub mod fault;
+pub mod font;
+#[cfg(feature = "std")]
+pub mod gdb;
+#[cfg(feature = "std")]
+pub mod history;
+pub mod instruction;
+#[cfg(feature = "std")]
+mod json;
+#[cfg(feature = "std")]
+pub mod lint;
+#[cfg(feature = "std")]
+pub mod octo;
+pub mod platform;
+pub mod processor;
+pub mod quirks;
+pub mod random;
+#[cfg(feature = "std")]
+pub mod symbols;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod tracediff;
+
+pub use fault::{CpuFault, StepOutcome};
+pub use font::Font;
+pub use instruction::Instruction;
+pub use platform::Platform;
+#[cfg(feature = "megachip")]
+pub use processor::MegaRenderer;
+pub use processor::{Renderer, CPU};
+pub use quirks::Quirks;
+pub use random::{RandomSource, SeededRandom, VipRandom};
+
+#[cfg(all(test, feature = "std"))]
+mod asm_test;
+#[cfg(all(test, feature = "std"))]
+mod cfg_test;
+#[cfg(all(test, feature = "std"))]
+mod dap_test;
+#[cfg(all(test, feature = "std"))]
+mod debugger_test;
+#[cfg(all(test, feature = "std"))]
+mod decompile_test;
+#[cfg(all(test, feature = "std"))]
+mod disasm_test;
+#[cfg(all(test, feature = "std"))]
+mod expr_test;
+#[cfg(all(test, feature = "std"))]
+mod gdb_test;
+#[cfg(all(test, feature = "std"))]
+mod history_test;
+#[cfg(all(test, feature = "std"))]
+mod lint_test;
+#[cfg(all(test, feature = "std"))]
+mod octo_test;
+#[cfg(all(test, feature = "std"))]
+mod processor_test;
+#[cfg(all(test, feature = "std"))]
+mod symbols_test;
+#[cfg(all(test, feature = "std"))]
+mod trace_test;
+#[cfg(all(test, feature = "std"))]
+mod tracediff_test;
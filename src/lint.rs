@@ -0,0 +1,211 @@
+use crate::cfg::{self, memory_accesses, AccessKind, ControlFlowGraph};
+use crate::disasm::word_at;
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+/// How many return addresses fit on `CPU::stack`.
+const STACK_DEPTH: usize = 16;
+
+/// Something in a ROM that is probably a mistake, at the instruction that
+/// does it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub address: u16,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X}: {}", self.address, self.message)
+    }
+}
+
+/// Checks every instruction reachable from the load address, in address
+/// order.
+pub fn lint(rom: &[u8], platform: Platform) -> Vec<Warning> {
+    let graph = cfg::build(rom, platform);
+    let base = platform.load_address();
+    let rom_end = base as usize + rom.len();
+    // Keyed by address, so each problem is only reported once.
+    let mut warnings = BTreeMap::new();
+    let mut warn = |address: u16, message: String| {
+        warnings
+            .entry((address, message.clone()))
+            .or_insert(Warning { address, message });
+    };
+
+    let accesses: Vec<_> = graph
+        .blocks
+        .values()
+        .flat_map(|block| memory_accesses(rom, base, platform, &block.instructions))
+        .collect();
+    let sprites: BTreeSet<u16> = accesses
+        .iter()
+        .filter(|access| access.kind == AccessKind::Sprite)
+        .flat_map(|access| access.target..access.target.saturating_add(access.length))
+        .collect();
+
+    // Opcodes are checked where control arrives, starting at the entry point.
+    let mut arrivals = vec![base];
+    for block in graph.blocks.values() {
+        for &(address, instruction) in &block.instructions {
+            let jump = match instruction {
+                Instruction::Jump(target) => Some(("Jumps", target)),
+                Instruction::Call(target) => Some(("Calls", target)),
+                Instruction::JumpWithOffset(target) => Some(("Jumps", target)),
+                _ => None,
+            };
+            if let Some((verb, target)) = jump {
+                if target % 2 == 1 {
+                    warn(address, format!("{} to odd address {:#05X}", verb, target));
+                }
+                if target < base || target as usize >= rom_end {
+                    warn(
+                        address,
+                        format!("{} to {:#05X}, outside the ROM", verb, target),
+                    );
+                } else if sprites.contains(&target) {
+                    warn(
+                        address,
+                        format!("{} into sprite data at {:#05X}", verb, target),
+                    );
+                }
+            }
+
+            let successors = cfg::successors(rom, base, platform, address, &instruction);
+            arrivals.extend(successors.into_iter().map(|(target, _)| target));
+        }
+    }
+    for target in arrivals {
+        let Some(opcode) = word_at(rom, base, target) else {
+            continue;
+        };
+        if Instruction::decode(opcode, platform).is_none() {
+            warn(target, unknown_opcode(opcode, platform));
+        }
+    }
+
+    for access in &accesses {
+        let end = access.target.saturating_add(access.length);
+        let Some(code) = (access.target..end).find(|&a| graph.is_code(a)) else {
+            continue;
+        };
+        match access.kind {
+            AccessKind::Store => warn(access.address, format!("Stores over code at {:#05X}", code)),
+            AccessKind::Load => warn(
+                access.address,
+                format!("Loads registers from code at {:#05X}", code),
+            ),
+            AccessKind::Sprite | AccessKind::Audio => (),
+        }
+    }
+
+    let (depth, first_call) = call_depth(&graph, base, &mut warn);
+    if depth > STACK_DEPTH {
+        warn(
+            first_call.unwrap_or(base),
+            format!(
+                "Calls nest {} deep, more than the {}-entry stack holds",
+                depth, STACK_DEPTH
+            ),
+        );
+    }
+
+    warnings.into_values().collect()
+}
+
+/// Explains an opcode the platform can't decode, which faults when it runs.
+fn unknown_opcode(opcode: u16, platform: Platform) -> String {
+    let extension = [
+        (Platform::SuperChip, "SUPER-CHIP"),
+        (Platform::XoChip, "XO-CHIP"),
+        (Platform::MegaChip, "MegaChip"),
+    ]
+    .into_iter()
+    .find(|&(other, _)| Instruction::decode(opcode, other).is_some());
+    match extension {
+        Some((_, name)) => format!(
+            "{:04X} is a {} instruction, which {} doesn't support",
+            opcode,
+            name,
+            platform.name()
+        ),
+        None => format!("Unknown opcode {:04X}, which stops the interpreter", opcode),
+    }
+}
+
+/// A subroutine `call_depth` is partway through.
+struct Frame {
+    entry: u16,
+    /// The call that got here, in the caller.
+    call: Option<u16>,
+    /// The calls still to follow, last first.
+    calls: Vec<(u16, u16)>,
+    deepest: (usize, Option<u16>),
+}
+
+impl Frame {
+    fn new(graph: &ControlFlowGraph, entry: u16, call: Option<u16>) -> Frame {
+        let mut calls: Vec<(u16, u16)> = graph
+            .subroutine(entry)
+            .into_iter()
+            .filter_map(|start| match *graph.blocks[&start].instructions.last()? {
+                (address, Instruction::Call(target)) => Some((address, target)),
+                _ => None,
+            })
+            .collect();
+        calls.reverse();
+        Frame {
+            entry,
+            call,
+            calls,
+            deepest: (0, None),
+        }
+    }
+
+    fn reach(&mut self, depth: usize, call: Option<u16>) {
+        if depth > self.deepest.0 {
+            self.deepest = (depth, call);
+        }
+    }
+}
+
+/// The deepest the stack gets below the subroutine at `entry`, and the call
+/// in `entry` that starts the deepest chain. Recursion is reported rather
+/// than followed.
+fn call_depth(
+    graph: &ControlFlowGraph,
+    entry: u16,
+    warn: &mut dyn FnMut(u16, String),
+) -> (usize, Option<u16>) {
+    let mut depths = HashMap::new();
+    let mut active = vec![Frame::new(graph, entry, None)];
+    loop {
+        let frame = active.last_mut().unwrap();
+        let Some((address, target)) = frame.calls.pop() else {
+            let done = active.pop().unwrap();
+            depths.insert(done.entry, done.deepest.0);
+            match active.last_mut() {
+                Some(caller) => caller.reach(done.deepest.0 + 1, done.call),
+                None => return done.deepest,
+            }
+            continue;
+        };
+        if active.iter().any(|frame| frame.entry == target) {
+            warn(
+                address,
+                format!(
+                    "Recursive call to {:#05X} can overflow the {}-entry stack",
+                    target, STACK_DEPTH
+                ),
+            );
+            active.last_mut().unwrap().reach(1, Some(address));
+        } else if let Some(&depth) = depths.get(&target) {
+            active.last_mut().unwrap().reach(depth + 1, Some(address));
+        } else {
+            active.push(Frame::new(graph, target, Some(address)));
+        }
+    }
+}
@@ -0,0 +1,92 @@
+#[cfg(test)]
+use crate::lint::lint;
+#[cfg(test)]
+use crate::platform::Platform;
+
+#[cfg(test)]
+fn messages(rom: &[u8], platform: Platform) -> Vec<String> {
+    lint(rom, platform)
+        .iter()
+        .map(|warning| warning.to_string())
+        .collect()
+}
+
+#[test]
+fn test_lint_jumps() {
+    let rom = [
+        0xA2, 0x0A, // i := 0x20A
+        0xD0, 0x02, // sprite v0 v0 2
+        0x30, 0x00, // skip if v0 == 0
+        0x12, 0x0A, // jump into the sprite
+        0x12, 0x0D, // jump to an odd address
+        0x60, 0x00, // the sprite, also run as v0 := 0
+        0x12, 0x00, 0xE0, // jump 0x200, and 00E0 at 0x20D
+    ];
+    assert_eq!(
+        messages(&rom, Platform::Chip8),
+        [
+            "0206: Jumps into sprite data at 0x20A",
+            "0208: Jumps to odd address 0x20D",
+        ]
+    );
+}
+
+#[test]
+fn test_lint_opcodes() {
+    let rom = [
+        0x30, 0x00, // skip if v0 == 0
+        0x00, 0xFF, // hires
+        0xF0, 0x02, // audio
+    ];
+    assert_eq!(
+        messages(&rom, Platform::Chip8),
+        [
+            "0202: 00FF is a SUPER-CHIP instruction, which chip8 doesn't support",
+            "0204: F002 is a XO-CHIP instruction, which chip8 doesn't support",
+        ]
+    );
+    assert!(messages(&rom, Platform::XoChip).is_empty());
+    assert_eq!(
+        messages(&[0xF0, 0xFF], Platform::Chip8),
+        ["0200: Unknown opcode F0FF, which stops the interpreter"]
+    );
+}
+
+#[test]
+fn test_lint_call_depth() {
+    let recursive = [0x22, 0x04, 0x12, 0x02, 0x22, 0x04, 0x00, 0xEE];
+    assert_eq!(
+        messages(&recursive, Platform::Chip8),
+        ["0204: Recursive call to 0x204 can overflow the 16-entry stack"]
+    );
+
+    // Seventeen subroutines, each calling the next.
+    let mut nested = Vec::new();
+    for i in 0..17u16 {
+        let target = 0x202 + i * 2;
+        nested.extend([0x20 | (target >> 8) as u8, target as u8]);
+    }
+    nested.extend([0x00, 0xEE]);
+    assert_eq!(
+        messages(&nested, Platform::Chip8),
+        ["0200: Calls nest 17 deep, more than the 16-entry stack holds"]
+    );
+}
+
+#[test]
+fn test_lint_code_overwrites() {
+    let rom = [
+        0xA2, 0x00, // i := 0x200
+        0xF1, 0x55, // save v1
+        0xA2, 0x00, // i := 0x200
+        0xF0, 0x65, // load v0
+        0x12, 0x08, // jump 0x208
+    ];
+    assert_eq!(
+        messages(&rom, Platform::Chip8),
+        [
+            "0202: Stores over code at 0x200",
+            "0206: Loads registers from code at 0x200",
+        ]
+    );
+}
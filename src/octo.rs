@@ -0,0 +1,924 @@
+use crate::asm::AsmError;
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use crate::symbols::SymbolMap;
+use std::collections::{BTreeMap, HashMap};
+
+/// Stops a macro that expands itself from running forever.
+const MAX_EXPANSIONS: usize = 100_000;
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// How a label's address is written into an instruction once it is known.
+#[derive(Clone, Copy, Debug)]
+enum Patch {
+    /// The NNN of a jump, call or `i :=`.
+    Address,
+    /// The word after F000.
+    Long,
+    /// The NN of the first `:unpack` instruction: the nibble (if any) and the
+    /// high bits of the address.
+    UnpackHigh(Option<u8>),
+    /// The NN of the second `:unpack` instruction.
+    UnpackLow,
+}
+
+/// An address operand, written into the ROM once every label is known.
+struct Fixup {
+    at: usize,
+    name: String,
+    /// The address, when it was a number or constant rather than a label.
+    address: Option<usize>,
+    patch: Patch,
+    line: usize,
+}
+
+/// An open `loop` or `if ... begin` block.
+enum Block {
+    Loop { start: usize, exits: Vec<usize> },
+    If { jump: usize },
+    Else { jump: usize },
+}
+
+/// The operators in a condition such as `v0 != 5` or `v1 key`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Key,
+    NotKey,
+}
+
+struct Compiler {
+    file: String,
+    /// The tokens still to be compiled, last first, so macros can push their
+    /// bodies back onto the front.
+    tokens: Vec<Token>,
+    line: usize,
+    platform: Platform,
+    base: usize,
+    memory_size: usize,
+    here: usize,
+    rom: Vec<u8>,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    expansions: usize,
+    /// The line each instruction came from, for `SymbolMap::lines`.
+    lines: BTreeMap<u16, usize>,
+}
+
+/// Compiles an Octo program to a ROM that loads at the platform's load
+/// address.
+pub fn compile(source: &str, file: &str, platform: Platform) -> Result<Vec<u8>, AsmError> {
+    compile_with_symbols(source, file, platform).map(|(rom, _)| rom)
+}
+
+/// Compiles an Octo program, also returning its labels and the source line
+/// of every instruction for a debugger.
+pub fn compile_with_symbols(
+    source: &str,
+    file: &str,
+    platform: Platform,
+) -> Result<(Vec<u8>, SymbolMap), AsmError> {
+    let mut tokens: Vec<Token> = source
+        .lines()
+        .enumerate()
+        .flat_map(|(number, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.split_whitespace().map(move |text| Token {
+                text: text.to_string(),
+                line: number + 1,
+            })
+        })
+        .collect();
+    tokens.reverse();
+
+    let base = platform.load_address() as usize;
+    let mut compiler = Compiler {
+        file: file.to_string(),
+        tokens,
+        line: 1,
+        platform,
+        base,
+        memory_size: platform.memory_size(),
+        here: base,
+        rom: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+        expansions: 0,
+        lines: BTreeMap::new(),
+    };
+    while !compiler.tokens.is_empty() {
+        compiler.statement()?;
+    }
+    compiler.finish()
+}
+
+/// Reads and compiles the Octo program at `path`.
+pub fn compile_file(path: &str, platform: Platform) -> Result<Vec<u8>, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    compile(&source, path, platform).map_err(|e| e.to_string())
+}
+
+impl Compiler {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn next(&mut self) -> Result<String, AsmError> {
+        let token = self
+            .tokens
+            .pop()
+            .ok_or_else(|| self.error("Unexpected end of file"))?;
+        self.line = token.line;
+        Ok(token.text)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.last().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token != expected {
+            return Err(self.error(format!("Expected {}, found {}", expected, token)));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        use Instruction::*;
+
+        let token = self.next()?;
+        if let Some(x) = self.register(&token) {
+            return self.register_operation(x);
+        }
+        match token.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(name, self.here)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.expect_register()?;
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let token = self.next()?;
+                let value = self.value(&token)?;
+                self.constants.insert(name, value as f64);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    let value = self.calc()?;
+                    if !(-0x80 as f64..=0xFF as f64).contains(&value) {
+                        return Err(self.error(format!("{} is out of range", value)));
+                    }
+                    value as i64
+                } else {
+                    let token = self.next()?;
+                    self.ranged(&token, -0x80, 0xFF)?
+                };
+                self.emit_byte(value as u8)?;
+            }
+            ":org" => {
+                let token = self.next()?;
+                let address = self.value(&token)?;
+                if address < self.base as i64 || address >= self.memory_size as i64 {
+                    return Err(self.error(format!("Cannot :org to {:#X}", address)));
+                }
+                self.here = address as usize;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(name, self.here + 1)?;
+            }
+            ":unpack" => {
+                let nibble = match self.next()?.as_str() {
+                    "long" => None,
+                    token => Some(self.ranged(token, 0, 0xF)? as u8),
+                };
+                let label = self.next()?;
+                self.address(&label, self.here, Patch::UnpackHigh(nibble))?;
+                self.emit(SetRegister { x: 0, nn: 0 })?;
+                self.address(&label, self.here, Patch::UnpackLow)?;
+                self.emit(SetRegister { x: 1, nn: 0 })?;
+            }
+            ":macro" => self.define_macro()?,
+            ":call" => {
+                let target = self.next()?;
+                self.address(&target, self.here, Patch::Address)?;
+                self.emit(Call(0))?;
+            }
+            ":proto" | ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+
+            ";" | "return" => self.emit(Return)?,
+            "clear" => self.emit(ClearScreen)?,
+            "hires" => self.emit(HighResolution)?,
+            "lores" => self.emit(LowResolution)?,
+            "exit" => self.emit(Exit)?,
+            "scroll-left" => self.emit(ScrollLeft)?,
+            "scroll-right" => self.emit(ScrollRight)?,
+            "audio" => self.emit(LoadAudioPattern)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(ScrollDown(n))?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(ScrollUp(n))?;
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(SelectPlanes(n))?;
+            }
+            "bcd" => {
+                let x = self.expect_register()?;
+                self.emit(BinaryCodedDecimal { x })?;
+            }
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                let range = if self.peek() == Some("-") {
+                    self.next()?;
+                    Some(self.expect_register()?)
+                } else {
+                    None
+                };
+                self.emit(match (token.as_str(), range) {
+                    ("save", None) => Store { x },
+                    ("load", None) => Load { x },
+                    ("save", Some(y)) => SaveRange { x, y },
+                    (_, Some(y)) => LoadRange { x, y },
+                    _ => unreachable!(),
+                })?;
+            }
+            "saveflags" => {
+                let x = self.expect_register()?;
+                self.emit(SaveFlags { x })?;
+            }
+            "loadflags" => {
+                let x = self.expect_register()?;
+                self.emit(LoadFlags { x })?;
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.nibble()?;
+                self.emit(Draw { x, y, n })?;
+            }
+            "jump" | "jump0" => {
+                let target = self.next()?;
+                self.address(&target, self.here, Patch::Address)?;
+                self.emit(if token == "jump" {
+                    Jump(0)
+                } else {
+                    JumpWithOffset(0)
+                })?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                self.emit(match token.as_str() {
+                    "delay" => SetDelay { x },
+                    "buzzer" => SetSound { x },
+                    _ => SetPitch { x },
+                })?;
+            }
+            "i" => self.index_operation()?,
+
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here,
+                exits: Vec::new(),
+            }),
+            "while" => {
+                // The skip steps over the jump out of the loop for as long as
+                // the condition holds.
+                self.condition(true)?;
+                let jump = self.here;
+                self.emit(Jump(0))?;
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop { .. }))
+                {
+                    Some(Block::Loop { exits, .. }) => exits.push(jump),
+                    _ => return Err(self.error("while is not inside a loop")),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits }) => {
+                    self.emit(Jump(0))?;
+                    self.patch(self.here - 2, start, Patch::Address)?;
+                    for exit in exits {
+                        self.patch(exit, self.here, Patch::Address)?;
+                    }
+                }
+                _ => return Err(self.error("again without a matching loop")),
+            },
+            "if" => {
+                let comparison = self.condition_tokens()?;
+                match self.next()?.as_str() {
+                    "then" => self.emit_condition(comparison, false)?,
+                    "begin" => {
+                        self.emit_condition(comparison, true)?;
+                        self.blocks.push(Block::If { jump: self.here });
+                        self.emit(Jump(0))?;
+                    }
+                    other => {
+                        return Err(self.error(format!("Expected then or begin, found {}", other)))
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump }) => {
+                    let end = self.here;
+                    self.emit(Jump(0))?;
+                    self.patch(jump, self.here, Patch::Address)?;
+                    self.blocks.push(Block::Else { jump: end });
+                }
+                _ => return Err(self.error("else without a matching if ... begin")),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump }) | Some(Block::Else { jump }) => {
+                    self.patch(jump, self.here, Patch::Address)?;
+                }
+                _ => return Err(self.error("end without a matching if ... begin")),
+            },
+
+            _ if self.macros.contains_key(&token) => self.expand(&token)?,
+            _ => match parse_number(&token) {
+                Some(value) if (-0x80..=0xFF).contains(&value) => self.emit_byte(value as u8)?,
+                Some(_) => return Err(self.error(format!("{} is out of range", token))),
+                // Any other name is a subroutine, which may be defined later.
+                None if is_name(&token) => {
+                    self.address(&token, self.here, Patch::Address)?;
+                    self.emit(Call(0))?;
+                }
+                None => return Err(self.error(format!("Unexpected {}", token))),
+            },
+        }
+        Ok(())
+    }
+
+    fn register_operation(&mut self, x: u8) -> Result<(), AsmError> {
+        use Instruction::*;
+
+        let operator = self.next()?;
+        let operand = self.next()?;
+        let y = self.register(&operand);
+        let instruction = match (operator.as_str(), y) {
+            (":=", Some(y)) => Move { x, y },
+            (":=", None) => match operand.as_str() {
+                "key" => WaitForKey { x },
+                "delay" => GetDelay { x },
+                "random" => {
+                    let mask = self.next()?;
+                    Random {
+                        x,
+                        nn: self.ranged(&mask, 0, 0xFF)? as u8,
+                    }
+                }
+                _ => SetRegister {
+                    x,
+                    nn: self.ranged(&operand, -0x80, 0xFF)? as u8,
+                },
+            },
+            ("+=", Some(y)) => Add { x, y },
+            ("+=", None) => AddImmediate {
+                x,
+                nn: self.ranged(&operand, -0x80, 0xFF)? as u8,
+            },
+            ("-=", Some(y)) => Subtract { x, y },
+            ("-=", None) => AddImmediate {
+                x,
+                nn: (self.ranged(&operand, -0xFF, 0x80)? as u8).wrapping_neg(),
+            },
+            ("=-", Some(y)) => SubtractReversed { x, y },
+            ("|=", Some(y)) => Or { x, y },
+            ("&=", Some(y)) => And { x, y },
+            ("^=", Some(y)) => Xor { x, y },
+            (">>=", Some(y)) => ShiftRight { x, y },
+            ("<<=", Some(y)) => ShiftLeft { x, y },
+            _ => {
+                return Err(self.error(format!(
+                    "Unknown operation: v{:x} {} {}",
+                    x, operator, operand
+                )))
+            }
+        };
+        self.emit(instruction)
+    }
+
+    fn index_operation(&mut self) -> Result<(), AsmError> {
+        use Instruction::*;
+
+        match self.next()?.as_str() {
+            ":=" => match self.next()?.as_str() {
+                "hex" => {
+                    let x = self.expect_register()?;
+                    self.emit(SmallCharacter { x })
+                }
+                "bighex" => {
+                    let x = self.expect_register()?;
+                    self.emit(BigCharacter { x })
+                }
+                "long" => {
+                    let target = self.next()?;
+                    self.emit(LongIndex)?;
+                    self.address(&target, self.here, Patch::Long)?;
+                    self.emit_byte(0)?;
+                    self.emit_byte(0)
+                }
+                target => {
+                    let target = target.to_string();
+                    self.address(&target, self.here, Patch::Address)?;
+                    self.emit(SetIndex(0))
+                }
+            },
+            "+=" => {
+                let x = self.expect_register()?;
+                self.emit(AddToIndex { x })
+            }
+            operator => Err(self.error(format!("Unknown operation: i {}", operator))),
+        }
+    }
+
+    /// Reads a condition and emits the skip that steps over the next
+    /// instruction when the condition is `skip_when`.
+    fn condition(&mut self, skip_when: bool) -> Result<(), AsmError> {
+        let comparison = self.condition_tokens()?;
+        self.emit_condition(comparison, skip_when)
+    }
+
+    fn condition_tokens(&mut self) -> Result<(u8, Comparison, Option<String>), AsmError> {
+        let x = self.expect_register()?;
+        let comparison = match self.next()?.as_str() {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            "<=" => Comparison::LessOrEqual,
+            ">=" => Comparison::GreaterOrEqual,
+            "key" => return Ok((x, Comparison::Key, None)),
+            "-key" => return Ok((x, Comparison::NotKey, None)),
+            other => return Err(self.error(format!("Unknown comparison: {}", other))),
+        };
+        let operand = self.next()?;
+        Ok((x, comparison, Some(operand)))
+    }
+
+    fn emit_condition(
+        &mut self,
+        (x, comparison, operand): (u8, Comparison, Option<String>),
+        skip_when: bool,
+    ) -> Result<(), AsmError> {
+        use Instruction::*;
+
+        let operand = operand.unwrap_or_default();
+        let y = self.register(&operand);
+        let instruction = match (comparison, skip_when) {
+            (Comparison::Key, true) | (Comparison::NotKey, false) => SkipIfPressed { x },
+            (Comparison::Key, false) | (Comparison::NotKey, true) => SkipIfNotPressed { x },
+            (Comparison::Equal, true) | (Comparison::NotEqual, false) => match y {
+                Some(y) => SkipIfRegistersEqual { x, y },
+                None => SkipIfEqual {
+                    x,
+                    nn: self.ranged(&operand, -0x80, 0xFF)? as u8,
+                },
+            },
+            (Comparison::Equal, false) | (Comparison::NotEqual, true) => match y {
+                Some(y) => SkipIfRegistersNotEqual { x, y },
+                None => SkipIfNotEqual {
+                    x,
+                    nn: self.ranged(&operand, -0x80, 0xFF)? as u8,
+                },
+            },
+            // The ordered comparisons subtract into vF and test the borrow
+            // flag, which is 1 when there was no borrow.
+            (comparison, skip_when) => {
+                self.emit(match y {
+                    Some(y) => Move { x: 0xF, y },
+                    None => SetRegister {
+                        x: 0xF,
+                        nn: self.ranged(&operand, -0x80, 0xFF)? as u8,
+                    },
+                })?;
+                let (subtract, flag_when_true) = match comparison {
+                    // vF = vX - operand, no borrow when vX >= operand.
+                    Comparison::GreaterOrEqual => (SubtractReversed { x: 0xF, y: x }, 1),
+                    Comparison::Less => (SubtractReversed { x: 0xF, y: x }, 0),
+                    // vF = operand - vX, no borrow when operand >= vX.
+                    Comparison::LessOrEqual => (Subtract { x: 0xF, y: x }, 1),
+                    _ => (Subtract { x: 0xF, y: x }, 0),
+                };
+                self.emit(subtract)?;
+                if skip_when {
+                    SkipIfEqual {
+                        x: 0xF,
+                        nn: flag_when_true,
+                    }
+                } else {
+                    SkipIfNotEqual {
+                        x: 0xF,
+                        nn: flag_when_true,
+                    }
+                }
+            }
+        };
+        self.emit(instruction)
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name()?;
+        let mut parameters = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            parameters.push(token);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self
+                .tokens
+                .pop()
+                .ok_or_else(|| self.error(format!("The body of macro {} is never closed", name)))?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { parameters, body });
+        Ok(())
+    }
+
+    fn expand(&mut self, name: &str) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(format!("Macro {} expands forever", name)));
+        }
+        let count = self.macros[name].parameters.len();
+        let mut arguments = HashMap::new();
+        for i in 0..count {
+            let argument = self.next()?;
+            arguments.insert(self.macros[name].parameters[i].clone(), argument);
+        }
+        let line = self.line;
+        for token in self.macros[name].body.iter().rev() {
+            let text = arguments.get(&token.text).unwrap_or(&token.text);
+            self.tokens.push(Token {
+                text: text.clone(),
+                line: if arguments.contains_key(&token.text) {
+                    line
+                } else {
+                    token.line
+                },
+            });
+        }
+        Ok(())
+    }
+
+    /// Evaluates a `{ ... }` expression. As in Octo, operators have no
+    /// precedence and are applied from right to left.
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        self.expect("{")?;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "}" {
+                break;
+            }
+            tokens.push(token);
+        }
+        let mut position = 0;
+        let value = self.calc_expression(&tokens, &mut position)?;
+        if position != tokens.len() {
+            return Err(self.error(format!("Unexpected {} in expression", tokens[position])));
+        }
+        Ok(value)
+    }
+
+    fn calc_expression(&self, tokens: &[String], position: &mut usize) -> Result<f64, AsmError> {
+        let left = self.calc_term(tokens, position)?;
+        let operator = match tokens.get(*position) {
+            None => return Ok(left),
+            Some(operator) if operator == ")" => return Ok(left),
+            Some(operator) => operator.clone(),
+        };
+        *position += 1;
+        let right = self.calc_expression(tokens, position)?;
+        let (a, b) = (left as i64, right as i64);
+        let shift = |shift: fn(i64, u32) -> Option<i64>| {
+            u32::try_from(b)
+                .ok()
+                .and_then(|b| shift(a, b))
+                .map(|value| value as f64)
+                .ok_or_else(|| self.error(format!("Cannot shift by {}", b)))
+        };
+        Ok(match operator.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => shift(i64::checked_shl)?,
+            ">>" => shift(i64::checked_shr)?,
+            "<" => (left < right) as i64 as f64,
+            ">" => (left > right) as i64 as f64,
+            "<=" => (left <= right) as i64 as f64,
+            ">=" => (left >= right) as i64 as f64,
+            "==" => (left == right) as i64 as f64,
+            "!=" => (left != right) as i64 as f64,
+            _ => return Err(self.error(format!("Unknown operator: {}", operator))),
+        })
+    }
+
+    fn calc_term(&self, tokens: &[String], position: &mut usize) -> Result<f64, AsmError> {
+        let token = tokens
+            .get(*position)
+            .ok_or_else(|| self.error("Missing value in expression"))?;
+        *position += 1;
+        if token == "(" {
+            let value = self.calc_expression(tokens, position)?;
+            if tokens.get(*position).map(String::as_str) != Some(")") {
+                return Err(self.error("Missing ) in expression"));
+            }
+            *position += 1;
+            return Ok(value);
+        }
+        let unary: Option<fn(f64) -> f64> = match token.as_str() {
+            "-" => Some(|v| -v),
+            "~" => Some(|v| !(v as i64) as f64),
+            "!" => Some(|v| (v == 0.0) as i64 as f64),
+            "abs" => Some(f64::abs),
+            "sqrt" => Some(f64::sqrt),
+            "sin" => Some(f64::sin),
+            "cos" => Some(f64::cos),
+            "floor" => Some(f64::floor),
+            "ceil" => Some(f64::ceil),
+            _ => None,
+        };
+        if let Some(unary) = unary {
+            return Ok(unary(self.calc_term(tokens, position)?));
+        }
+        match token.as_str() {
+            "@" => {
+                let address = self.calc_term(tokens, position)? as usize;
+                let byte = address
+                    .checked_sub(self.base)
+                    .and_then(|offset| self.rom.get(offset));
+                Ok(byte.copied().unwrap_or(0) as f64)
+            }
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => match self.constants.get(token) {
+                Some(&value) => Ok(value),
+                None => self.value(token).map(|value| value as f64),
+            },
+        }
+    }
+
+    /// Resolves a number, constant or already defined label.
+    fn value(&self, token: &str) -> Result<i64, AsmError> {
+        if let Some(value) = parse_number(token) {
+            return Ok(value);
+        }
+        if let Some(&value) = self.constants.get(token) {
+            return Ok(value as i64);
+        }
+        if let Some(&address) = self.labels.get(token) {
+            return Ok(address as i64);
+        }
+        Err(self.error(format!("Undefined name: {}", token)))
+    }
+
+    fn ranged(&self, token: &str, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = self.value(token)?;
+        if value < min || value > max {
+            return Err(self.error(format!("{} is out of range", token)));
+        }
+        Ok(value)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        Ok(self.ranged(&token, 0, 0xF)? as u8)
+    }
+
+    fn register(&self, token: &str) -> Option<u8> {
+        if let Some(&register) = self.aliases.get(token) {
+            return Some(register);
+        }
+        let digit = token.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn expect_register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.register(&token)
+            .ok_or_else(|| self.error(format!("Expected a register, found {}", token)))
+    }
+
+    /// Reads the name being defined by `:`, `:const` and the like.
+    fn name(&mut self) -> Result<String, AsmError> {
+        let name = self.next()?;
+        if !is_name(&name) || self.register(&name).is_some() {
+            return Err(self.error(format!("{} cannot be used as a name", name)));
+        }
+        Ok(name)
+    }
+
+    fn define_label(&mut self, name: String, address: usize) -> Result<(), AsmError> {
+        if self.labels.contains_key(&name) {
+            return Err(self.error(format!("{} is defined more than once", name)));
+        }
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    /// Records that the address `token` names goes into the instruction at
+    /// `at`. Labels may be defined later, so every address is written in once
+    /// compilation is finished.
+    fn address(&mut self, token: &str, at: usize, patch: Patch) -> Result<(), AsmError> {
+        let address =
+            match parse_number(token).or_else(|| self.constants.get(token).map(|&v| v as i64)) {
+                Some(address) if address < 0 => {
+                    return Err(self.error(format!("{} is not an address", token)))
+                }
+                Some(address) => Some(address as usize),
+                None if is_name(token) => None,
+                None => return Err(self.error(format!("Expected an address, found {}", token))),
+            };
+        self.fixups.push(Fixup {
+            at,
+            name: token.to_string(),
+            address,
+            patch,
+            line: self.line,
+        });
+        Ok(())
+    }
+
+    fn patch(&mut self, at: usize, address: usize, patch: Patch) -> Result<(), AsmError> {
+        let offset = at - self.base;
+        match patch {
+            Patch::Address => {
+                if address > 0xFFF {
+                    return Err(self.error(format!("{:#X} does not fit in 12 bits", address)));
+                }
+                self.rom[offset] = (self.rom[offset] & 0xF0) | (address >> 8) as u8;
+                self.rom[offset + 1] = address as u8;
+            }
+            Patch::Long => {
+                if address > 0xFFFF {
+                    return Err(self.error(format!("{:#X} does not fit in 16 bits", address)));
+                }
+                self.rom[offset] = (address >> 8) as u8;
+                self.rom[offset + 1] = address as u8;
+            }
+            Patch::UnpackHigh(nibble) => {
+                self.rom[offset + 1] = match nibble {
+                    Some(nibble) => (nibble << 4) | ((address >> 8) & 0xF) as u8,
+                    None => (address >> 8) as u8,
+                };
+            }
+            Patch::UnpackLow => self.rom[offset + 1] = address as u8,
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<(), AsmError> {
+        let opcode = instruction.encode();
+        if Instruction::decode(opcode, self.platform) != Some(instruction) {
+            return Err(self.error(format!(
+                "{:04X} is not available on {}",
+                opcode,
+                self.platform.name()
+            )));
+        }
+        self.lines.insert(self.here as u16, self.line);
+        let [high, low] = opcode.to_be_bytes();
+        self.emit_byte(high)?;
+        self.emit_byte(low)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+        if self.here >= self.memory_size {
+            return Err(self.error("The program does not fit in memory"));
+        }
+        let offset = self.here - self.base;
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(Vec<u8>, SymbolMap), AsmError> {
+        if !self.blocks.is_empty() {
+            return Err(self.error("A loop or if ... begin is never closed"));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+            let address = match fixup
+                .address
+                .or_else(|| self.labels.get(&fixup.name).copied())
+            {
+                Some(address) => address,
+                None => return Err(self.error(format!("Undefined name: {}", fixup.name))),
+            };
+            self.patch(fixup.at, address, fixup.patch)?;
+        }
+        let mut symbols = SymbolMap::new();
+        symbols.file = Some(self.file.clone());
+        for (name, &address) in &self.labels {
+            let name = name.clone();
+            symbols.labels.entry(address as u16).or_insert(name);
+        }
+        symbols.lines = self.lines;
+        Ok((self.rom, symbols))
+    }
+}
+
+fn is_name(token: &str) -> bool {
+    let mut chars = token.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn parse_number(token: &str) -> Option<i64> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits
+        .strip_prefix("0b")
+        .or_else(|| digits.strip_prefix("0B"))
+    {
+        i64::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
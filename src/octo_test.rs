@@ -0,0 +1,114 @@
+#[cfg(test)]
+use crate::octo::compile;
+#[cfg(test)]
+use crate::platform::Platform;
+
+#[test]
+fn test_compile_loops_calls_and_data() {
+    let source = "
+        : main
+          v0 := 0
+          loop
+            v0 += 1
+            if v0 == 5 then v1 := 2
+            draw-it     # defined below
+          again
+          jump main
+        : draw-it
+          i := sprite
+          sprite v0 v1 1
+          ;
+        : sprite
+          0xFF
+    ";
+    assert_eq!(
+        compile(source, "test.8o", Platform::Chip8).unwrap(),
+        [
+            0x60, 0x00, 0x70, 0x01, 0x40, 0x05, 0x61, 0x02, 0x22, 0x0E, 0x12, 0x02, 0x12, 0x00,
+            0xA2, 0x14, 0xD0, 0x11, 0x00, 0xEE, 0xFF,
+        ]
+    );
+}
+
+#[test]
+fn test_compile_blocks_and_comparisons() {
+    let source = "
+        if v1 > v2 begin
+          v3 := 1
+        else
+          v3 := 2
+        end
+        loop
+          while v4 != 3
+          v4 += 1
+        again
+    ";
+    assert_eq!(
+        compile(source, "test.8o", Platform::Chip8).unwrap(),
+        [
+            0x8F, 0x20, 0x8F, 0x15, 0x3F, 0x00, 0x12, 0x0C, 0x63, 0x01, 0x12, 0x0E, 0x63, 0x02,
+            0x44, 0x03, 0x12, 0x16, 0x74, 0x01, 0x12, 0x0E,
+        ]
+    );
+}
+
+#[test]
+fn test_compile_directives() {
+    let source = "
+        :alias x v5
+        :const SPEED 3
+        :calc DOUBLE { SPEED * 2 + 1 }
+        :macro add-to reg amount { reg += amount }
+        add-to x DOUBLE
+        x -= SPEED
+        :unpack 0xA data
+        :next target
+        v2 := 0
+        i := long data
+        :org 0x210
+        : data
+          :byte { target & 0xFF }
+    ";
+    assert_eq!(
+        compile(source, "test.8o", Platform::XoChip).unwrap(),
+        [
+            0x75, 0x09, 0x75, 0xFD, 0x60, 0xA2, 0x61, 0x10, 0x62, 0x00, 0xF0, 0x00, 0x02, 0x10,
+            0x00, 0x00, 0x09,
+        ]
+    );
+}
+
+#[test]
+fn test_compile_errors_have_line_numbers() {
+    let error = compile("clear\nv0 := 300", "test.8o", Platform::Chip8).unwrap_err();
+    assert_eq!(error.to_string(), "test.8o:2: 300 is out of range");
+
+    let error = compile("clear\n\njump nowhere", "test.8o", Platform::Chip8).unwrap_err();
+    assert_eq!(error.to_string(), "test.8o:3: Undefined name: nowhere");
+
+    let error = compile("loop\nv0 := 1", "test.8o", Platform::Chip8).unwrap_err();
+    assert!(error.message.contains("never closed"));
+
+    let error = compile("again", "test.8o", Platform::Chip8).unwrap_err();
+    assert_eq!(error.line, 1);
+
+    let error = compile(":calc x { 1 << 64 }", "test.8o", Platform::Chip8).unwrap_err();
+    assert_eq!(error.to_string(), "test.8o:1: Cannot shift by 64");
+    let error = compile(":calc x { 1 >> -1 }", "test.8o", Platform::Chip8).unwrap_err();
+    assert_eq!(error.to_string(), "test.8o:1: Cannot shift by -1");
+    let error = compile(":byte 256", "test.8o", Platform::Chip8).unwrap_err();
+    assert_eq!(error.to_string(), "test.8o:1: 256 is out of range");
+    let error = compile(":byte { 0x80 * 4 }", "test.8o", Platform::Chip8).unwrap_err();
+    assert_eq!(error.to_string(), "test.8o:1: 512 is out of range");
+    assert!(compile(":byte { 1 << 7 } :byte -1", "test.8o", Platform::Chip8).is_ok());
+
+    let source = "hires\ni := long 0x300\nplane 2\n";
+    let error = compile(source, "test.8o", Platform::Chip8).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "test.8o:1: 00FF is not available on chip8"
+    );
+    let error = compile(source, "test.8o", Platform::SuperChip).unwrap_err();
+    assert_eq!(error.line, 2);
+    assert!(compile(source, "test.8o", Platform::XoChip).is_ok());
+}
@@ -0,0 +1,141 @@
+use crate::font::Font;
+use crate::quirks::Quirks;
+
+/// The machine a ROM was written for. Each platform describes where programs
+/// are loaded, the size of the low resolution screen and the opcodes it adds
+/// to the base instruction set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    /// The 64x64 two-page display variant of the COSMAC VIP interpreter.
+    HiresChip8,
+    /// The ETI-660 learner's computer, with a 64x48 screen.
+    Eti660,
+    /// The VIP interpreter extended for the VP-590 colour board.
+    Chip8X,
+    /// Gilles Detillieux's extended VIP interpreter.
+    Chip8E,
+    SuperChip,
+    XoChip,
+    /// MegaChip's 01NN NNNN can address 16M, but memory here stops at 64K
+    /// like XO-CHIP's: bigger ROMs are rejected, and addresses past the end
+    /// fault rather than wrapping.
+    MegaChip,
+}
+
+impl Platform {
+    /// Looks up a platform by the name accepted on the command line.
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" | "chip-8" => Some(Platform::Chip8),
+            "hires" | "hires-chip8" => Some(Platform::HiresChip8),
+            "eti660" | "eti-660" => Some(Platform::Eti660),
+            "chip8x" | "chip-8x" => Some(Platform::Chip8X),
+            "chip8e" | "chip-8e" => Some(Platform::Chip8E),
+            "schip" | "superchip" => Some(Platform::SuperChip),
+            "xochip" | "xo-chip" => Some(Platform::XoChip),
+            "megachip" | "mchip" => Some(Platform::MegaChip),
+            _ => None,
+        }
+    }
+
+    /// The name `from_name` takes for the platform.
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::HiresChip8 => "hires",
+            Platform::Eti660 => "eti660",
+            Platform::Chip8X => "chip8x",
+            Platform::Chip8E => "chip8e",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+            Platform::MegaChip => "megachip",
+        }
+    }
+
+    /// The quirk profile used when none is given explicitly.
+    pub fn default_quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::HiresChip8 | Platform::Eti660 | Platform::Chip8X | Platform::Chip8E => {
+                Quirks::COSMAC_VIP
+            }
+            Platform::SuperChip | Platform::MegaChip => Quirks::SUPERCHIP_11,
+            Platform::XoChip => Quirks::XO_CHIP,
+        }
+    }
+
+    /// The font installed at boot unless another one is chosen.
+    pub fn default_font(self) -> Font {
+        match self {
+            Platform::Chip8 | Platform::HiresChip8 | Platform::Chip8X | Platform::Chip8E => {
+                Font::COSMAC_VIP
+            }
+            Platform::Eti660 => Font::ETI_660,
+            Platform::SuperChip | Platform::XoChip | Platform::MegaChip => Font::OCTO,
+        }
+    }
+
+    /// Where ROMs are loaded and execution starts.
+    pub fn load_address(self) -> u16 {
+        match self {
+            Platform::HiresChip8 => 0x244,
+            Platform::Eti660 => 0x600,
+            Platform::Chip8X => 0x300,
+            _ => 0x200,
+        }
+    }
+
+    /// The number of bytes of memory programs can address.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::XoChip | Platform::MegaChip => 0x10000,
+            _ => 0x1000,
+        }
+    }
+
+    /// The width and height of the low resolution screen.
+    pub fn resolution(self) -> (usize, usize) {
+        match self {
+            Platform::HiresChip8 => (64, 64),
+            Platform::Eti660 => (64, 48),
+            _ => (64, 32),
+        }
+    }
+
+    /// Whether the SUPER-CHIP 1.1 scrolling, hires and RPL opcodes are decoded.
+    pub fn has_superchip_opcodes(self) -> bool {
+        matches!(
+            self,
+            Platform::SuperChip | Platform::XoChip | Platform::MegaChip
+        )
+    }
+
+    /// Whether the XO-CHIP bitplane, long index and audio opcodes are decoded.
+    pub fn has_xochip_opcodes(self) -> bool {
+        matches!(self, Platform::XoChip)
+    }
+
+    /// Whether the MegaChip mode switch, palette, sprite and sample opcodes
+    /// are decoded, which needs the `megachip` feature.
+    pub fn has_megachip_opcodes(self) -> bool {
+        cfg!(feature = "megachip") && matches!(self, Platform::MegaChip)
+    }
+
+    /// Whether 0230 clears the 64x64 screen.
+    pub fn has_hires_chip8_opcodes(self) -> bool {
+        matches!(self, Platform::HiresChip8)
+    }
+
+    /// Whether the colour (BXYN, 02A0), nibble add (5XY1) and second keypad
+    /// (EXF2/EXF5) opcodes are decoded. BNNN is not available.
+    pub fn has_chip8x_opcodes(self) -> bool {
+        matches!(self, Platform::Chip8X)
+    }
+
+    /// Whether the extra skips, register ranges, relative jumps and port
+    /// opcodes are decoded.
+    pub fn has_chip8e_opcodes(self) -> bool {
+        matches!(self, Platform::Chip8E)
+    }
+}
@@ -1,8 +1,29 @@
-use rand::Rng;
-
-pub struct CPU {
+use crate::fault::{CpuFault, StepOutcome};
+use crate::font::{Font, BIG_FONT_SIZE, DEFAULT_FONT_ADDRESS, SMALL_FONT_SIZE};
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::random::RandomSource;
+#[cfg(feature = "std")]
+use crate::random::SeededRandom;
+
+pub const MEMORY_SIZE: usize = 0x10000;
+pub const SCREEN_WIDTH: usize = 128;
+pub const SCREEN_HEIGHT: usize = 64;
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
+
+/// CHIP-8X colours each 8-pixel-wide strip of a row separately.
+pub const COLOR_ZONE_COLUMNS: usize = 8;
+pub const COLOR_ZONE_ROWS: usize = 32;
+
+/// The interpreter state. `R` supplies the bytes for CXNN; it is passed in by
+/// the caller so the core never has to reach for an entropy source itself.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+pub struct CPU<R: RandomSource> {
     pub keypad: [bool; 16],
-    pub memory: [u8; 4096],
+    pub memory: [u8; MEMORY_SIZE],
     pub registers: [u8; 16],
     pub index: u16,
     pub program_counter: u16,
@@ -11,58 +32,318 @@ pub struct CPU {
     pub delay_timer: u8,
     pub sound_timer: u8,
     pub renderer: Renderer,
-    random: rand::rngs::StdRng,
-    waiting_for_key: Option<usize>,
+    pub font_address: u16,
+    pub big_font_address: u16,
+    pub rpl_flags: [u8; 16],
+    pub audio_pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+    pub audio_updated: bool,
+    pub mega_mode: bool,
+    #[cfg(feature = "megachip")]
+    pub mega_renderer: MegaRenderer,
+    pub sample: Option<Sample>,
+    pub port_output: u8,
+    pub exited: bool,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    random: R,
+    waiting_for_delay: bool,
 }
 
+/// Each pixel holds one bit per bitplane, giving a colour index from 0 to 3.
+/// On CHIP-8X the foreground colour instead comes from `zone_colors`.
+#[derive(Clone)]
 pub struct Renderer {
-    pub buffer: [[bool; 64]; 32],
+    pub buffer: [[u8; SCREEN_WIDTH]; SCREEN_HEIGHT],
+    pub hires: bool,
+    pub planes: u8,
+    pub zone_colors: Option<[[u8; COLOR_ZONE_COLUMNS]; COLOR_ZONE_ROWS]>,
+    pub background: u8,
     pub redraw: bool,
+    lores_width: usize,
+    lores_height: usize,
+}
+
+impl Renderer {
+    fn new(platform: Platform) -> Self {
+        let (lores_width, lores_height) = platform.resolution();
+        Renderer {
+            buffer: [[0; SCREEN_WIDTH]; SCREEN_HEIGHT],
+            hires: false,
+            planes: 1,
+            zone_colors: if platform.has_chip8x_opcodes() {
+                Some([[0; COLOR_ZONE_COLUMNS]; COLOR_ZONE_ROWS])
+            } else {
+                None
+            },
+            background: 0,
+            redraw: false,
+            lores_width,
+            lores_height,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            SCREEN_WIDTH
+        } else {
+            self.lores_width
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            SCREEN_HEIGHT
+        } else {
+            self.lores_height
+        }
+    }
+
+    fn clear(&mut self) {
+        for pixel in self.buffer.iter_mut().flatten() {
+            *pixel &= !self.planes;
+        }
+        self.redraw = true;
+    }
+
+    /// Moves the selected planes by `dx`/`dy` pixels, filling in with blanks.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.width() as isize;
+        let height = self.height() as isize;
+        let planes = self.planes;
+        let source = self.buffer;
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                    source[from_y as usize][from_x as usize]
+                } else {
+                    0
+                };
+                let pixel = &mut self.buffer[y as usize][x as usize];
+                *pixel = (*pixel & !planes) | (moved & planes);
+            }
+        }
+        self.redraw = true;
+    }
 }
 
-impl CPU {
-    pub fn new() -> Self {
-        CPU {
+/// How MegaChip sprite pixels are combined with what is already on screen.
+#[cfg(feature = "megachip")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Alpha25,
+    Alpha50,
+    Alpha75,
+    Additive,
+    Multiply,
+}
+
+#[cfg(feature = "megachip")]
+impl BlendMode {
+    fn blend(self, source: u32, destination: u32) -> u32 {
+        let mut result = 0xFF00_0000;
+        for shift in [0, 8, 16] {
+            let s = (source >> shift) & 0xFF;
+            let d = (destination >> shift) & 0xFF;
+            let channel = match self {
+                BlendMode::Normal => s,
+                BlendMode::Alpha25 => (s + 3 * d) / 4,
+                BlendMode::Alpha50 => (s + d) / 2,
+                BlendMode::Alpha75 => (3 * s + d) / 4,
+                BlendMode::Additive => (s + d).min(0xFF),
+                BlendMode::Multiply => s * d / 0xFF,
+            };
+            result |= channel << shift;
+        }
+        result
+    }
+}
+
+/// The 256x192 true-colour screen used while MegaChip mode is on. Sprites
+/// are drawn with palette indices, which are kept alongside the blended ARGB
+/// colours for collision detection.
+#[cfg(feature = "megachip")]
+#[derive(Clone)]
+pub struct MegaRenderer {
+    pub pixels: [[u32; MEGA_WIDTH]; MEGA_HEIGHT],
+    pub indices: [[u8; MEGA_WIDTH]; MEGA_HEIGHT],
+    pub palette: [u32; 256],
+    pub sprite_width: usize,
+    pub sprite_height: usize,
+    pub alpha: u8,
+    pub blend_mode: BlendMode,
+    pub collision_color: u8,
+    pub redraw: bool,
+    clear_pending: bool,
+}
+
+#[cfg(feature = "megachip")]
+impl MegaRenderer {
+    fn new() -> Self {
+        MegaRenderer {
+            pixels: [[0xFF00_0000; MEGA_WIDTH]; MEGA_HEIGHT],
+            indices: [[0; MEGA_WIDTH]; MEGA_HEIGHT],
+            palette: [0xFF00_0000; 256],
+            sprite_width: 0,
+            sprite_height: 0,
+            alpha: 0xFF,
+            blend_mode: BlendMode::Normal,
+            collision_color: 0,
+            redraw: false,
+            clear_pending: false,
+        }
+    }
+
+    /// The MegaChip screen is double buffered: 00E0 presents the frame drawn
+    /// so far, and it is only wiped once drawing starts on the next one.
+    fn present(&mut self) {
+        self.redraw = true;
+        self.clear_pending = true;
+    }
+
+    fn begin_drawing(&mut self) {
+        if self.clear_pending {
+            self.pixels = [[0xFF00_0000; MEGA_WIDTH]; MEGA_HEIGHT];
+            self.indices = [[0; MEGA_WIDTH]; MEGA_HEIGHT];
+            self.clear_pending = false;
+        }
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        self.begin_drawing();
+        for y in 0..MEGA_HEIGHT {
+            if y + n < MEGA_HEIGHT {
+                self.pixels[y] = self.pixels[y + n];
+                self.indices[y] = self.indices[y + n];
+            } else {
+                self.pixels[y] = [0xFF00_0000; MEGA_WIDTH];
+                self.indices[y] = [0; MEGA_WIDTH];
+            }
+        }
+    }
+}
+
+/// A digitised sound started by MegaChip's 060N. The 8-bit unsigned PCM data
+/// lives in memory at `address`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sample {
+    pub address: usize,
+    pub length: usize,
+    pub rate: u16,
+    pub looping: bool,
+}
+
+#[cfg(feature = "std")]
+impl CPU<SeededRandom> {
+    /// Creates a CPU whose random numbers are seeded from the operating system.
+    pub fn new(platform: Platform, quirks: Quirks) -> Self {
+        CPU::with_rng(platform, quirks, SeededRandom::from_entropy())
+    }
+}
+
+impl<R: RandomSource> CPU<R> {
+    pub fn with_rng(platform: Platform, quirks: Quirks, random: R) -> Self {
+        let mut cpu = CPU {
             keypad: [false; 16],
-            memory: [0; 4096],
+            memory: [0; MEMORY_SIZE],
             registers: [0; 16],
             index: 0,
-            program_counter: 0x200,
+            program_counter: platform.load_address(),
             stack: [0; 16],
             stack_pointer: 0,
             delay_timer: 0,
             sound_timer: 0,
-            renderer: Renderer {
-                buffer: [[false; 64]; 32],
-                redraw: false,
-            },
-            random: rand::SeedableRng::from_entropy(),
-            waiting_for_key: None,
+            renderer: Renderer::new(platform),
+            font_address: 0,
+            big_font_address: 0,
+            rpl_flags: [0; 16],
+            audio_pattern: None,
+            pitch: 64,
+            audio_updated: false,
+            mega_mode: false,
+            #[cfg(feature = "megachip")]
+            mega_renderer: MegaRenderer::new(),
+            sample: None,
+            port_output: 0,
+            exited: false,
+            platform,
+            quirks,
+            random,
+            waiting_for_delay: false,
+        };
+        // The default address always leaves room for both fonts.
+        let _ = cpu.install_font(&platform.default_font(), DEFAULT_FONT_ADDRESS);
+        cpu
+    }
+
+    /// Copies the small font to `address` and the big font straight after it,
+    /// pointing FX29 and FX30 at them.
+    pub fn install_font(&mut self, font: &Font, address: u16) -> Result<(), CpuFault> {
+        let small_start = address as usize;
+        let big_start = small_start + SMALL_FONT_SIZE;
+        let end = big_start + BIG_FONT_SIZE;
+        if end > self.platform.memory_size() {
+            return Err(CpuFault::MemoryOutOfBounds { address: end - 1 });
         }
+        self.memory[small_start..big_start].copy_from_slice(&font.small);
+        self.memory[big_start..end].copy_from_slice(&font.big);
+        self.font_address = address;
+        self.big_font_address = big_start as u16;
+        Ok(())
+    }
+
+    pub fn load_rom(&mut self, rom_data: &[u8]) -> Result<(), CpuFault> {
+        let start = self.platform.load_address() as usize;
+        let capacity = self.platform.memory_size() - start;
+        if rom_data.len() > capacity {
+            return Err(CpuFault::RomTooLarge {
+                size: rom_data.len(),
+                capacity,
+            });
+        }
+        self.memory[start..start + rom_data.len()].copy_from_slice(rom_data);
+        Ok(())
     }
 
-    pub fn load_rom(&mut self, rom_data: &[u8]) {
-        self.memory[0x200..0x200 + rom_data.len()].copy_from_slice(rom_data);
+    /// Reads the big-endian word at `address`.
+    pub fn fetch(&self, address: u16) -> Result<u16, CpuFault> {
+        let high = self.read_memory(address as usize)?;
+        let low = self.read_memory(address as usize + 1)?;
+        Ok(((high as u16) << 8) | low as u16)
     }
 
-    pub fn tick(&mut self, keypad: [bool; 16]) {
+    pub fn tick(&mut self, keypad: [bool; 16]) -> Result<StepOutcome, CpuFault> {
         self.keypad = keypad;
-        if let Some(register) = self.waiting_for_key {
-            for key in 0..=0xF {
-                if self.keypad[key] {
-                    self.registers[register] = key as u8;
-                    self.waiting_for_key = None;
-                    self.program_counter += 2; // Move to the next instruction
-                    return;
-                }
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+        if self.waiting_for_delay {
+            if self.delay_timer > 0 {
+                return Ok(StepOutcome::Waiting);
             }
-        } else {
-            let opcode: u16 = ((self.memory[self.program_counter as usize] as u16) << 8)
-                | self.memory[(self.program_counter + 1) as usize] as u16;
+            self.waiting_for_delay = false;
+        }
+        let pc = self.program_counter;
+        let opcode = self.fetch(pc)?;
 
-            self.program_counter += 2;
-            self.execute_opcode(opcode);
+        self.program_counter = self.next_instruction()?;
+        let outcome = self.execute_opcode(opcode);
+        if outcome.is_err() {
+            self.program_counter = pc;
         }
+        outcome
+    }
+
+    /// The address after the instruction at PC, which is a fault for one in
+    /// the last word of memory.
+    fn next_instruction(&self) -> Result<u16, CpuFault> {
+        self.program_counter
+            .checked_add(2)
+            .ok_or(CpuFault::MemoryOutOfBounds {
+                address: self.program_counter as usize + 2,
+            })
     }
 
     pub fn tick_60hz(&mut self) {
@@ -74,284 +355,661 @@ impl CPU {
         }
     }
 
-    pub fn execute_opcode(&mut self, opcode: u16) {
-        let nibbles = (
-            (opcode & 0xF000) >> 12,
-            (opcode & 0x0F00) >> 8,
-            (opcode & 0x00F0) >> 4,
-            opcode & 0x000F,
-        );
-
-        match nibbles {
-            (0x00, 0x00, 0x0e, 0x00) => self.clear_display(),
-            (0x00, 0x00, 0x0e, 0x0e) => self.return_from_subroutine(),
-            (0x01, _, _, _) => self.jump(opcode),
-            (0x02, _, _, _) => self.call(opcode),
-            (0x03, _, _, _) => self.skip_if_x_equal(opcode),
-            (0x04, _, _, _) => self.skip_if_x_not_equal(opcode),
-            (0x05, _, _, 0x00) => self.skip_if_x_and_y_equal(opcode),
-            (0x06, _, _, _) => self.set_x(opcode),
-            (0x07, _, _, _) => self.add_x(opcode),
-            (0x08, _, _, 0x00) => self.arithmetic(opcode),
-            (0x08, _, _, 0x01) => self.arithmetic(opcode),
-            (0x08, _, _, 0x02) => self.arithmetic(opcode),
-            (0x08, _, _, 0x03) => self.arithmetic(opcode),
-            (0x08, _, _, 0x04) => self.arithmetic(opcode),
-            (0x08, _, _, 0x05) => self.arithmetic(opcode),
-            (0x08, _, _, 0x06) => self.arithmetic(opcode),
-            (0x08, _, _, 0x07) => self.arithmetic(opcode),
-            (0x08, _, _, 0x0e) => self.arithmetic(opcode),
-            (0x09, _, _, 0x00) => self.skip_if_x_and_y_different(opcode),
-            (0x0a, _, _, _) => self.set_index(opcode),
-            (0x0b, _, _, _) => self.jump_with_offset(opcode),
-            (0x0c, _, _, _) => self.random(opcode),
-            (0x0d, _, _, _) => self.draw_sprite(opcode),
-            (0x0e, _, 0x09, 0x0e) => self.skip_if_pressed(opcode),
-            (0x0e, _, 0x0a, 0x01) => self.skip_if_not_pressed(opcode),
-            (0x0f, _, 0x00, 0x07) => self.misc(opcode),
-            (0x0f, _, 0x00, 0x0a) => self.misc(opcode),
-            (0x0f, _, 0x01, 0x05) => self.misc(opcode),
-            (0x0f, _, 0x01, 0x08) => self.misc(opcode),
-            (0x0f, _, 0x01, 0x0e) => self.misc(opcode),
-            (0x0f, _, 0x02, 0x09) => self.misc(opcode),
-            (0x0f, _, 0x03, 0x03) => self.misc(opcode),
-            (0x0f, _, 0x05, 0x05) => self.misc(opcode),
-            (0x0f, _, 0x06, 0x05) => self.misc(opcode),
-            _ => (),
+    /// Decodes and runs one instruction.
+    pub fn execute_opcode(&mut self, opcode: u16) -> Result<StepOutcome, CpuFault> {
+        let instruction =
+            Instruction::decode(opcode, self.platform).ok_or(CpuFault::UnknownOpcode(opcode))?;
+        self.execute(instruction)
+    }
+
+    /// Runs an instruction that has already been fetched, with the program
+    /// counter pointing past it.
+    pub fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, CpuFault> {
+        use Instruction::*;
+
+        let next_instruction = self.program_counter;
+
+        match instruction {
+            ClearScreen | HiresClearScreen => self.clear_display(),
+            Return => self.return_from_subroutine()?,
+            Jump(nnn) => self.jump(nnn),
+            Call(nnn) => self.call(nnn)?,
+            SkipIfEqual { x, nn } => self.skip_if(self.registers[x as usize] == nn),
+            SkipIfNotEqual { x, nn } => self.skip_if(self.registers[x as usize] != nn),
+            SkipIfRegistersEqual { x, y } => {
+                self.skip_if(self.registers[x as usize] == self.registers[y as usize])
+            }
+            SetRegister { x, nn } => self.registers[x as usize] = nn,
+            AddImmediate { x, nn } => {
+                self.registers[x as usize] = self.registers[x as usize].wrapping_add(nn)
+            }
+            Move { x, y } => self.registers[x as usize] = self.registers[y as usize],
+            Or { x, y } => self.logic(x, y, |vx, vy| vx | vy),
+            And { x, y } => self.logic(x, y, |vx, vy| vx & vy),
+            Xor { x, y } => self.logic(x, y, |vx, vy| vx ^ vy),
+            Add { x, y } => self.add(x, y),
+            Subtract { x, y } => self.subtract(x, x, y),
+            ShiftRight { x, y } => self.shift_right(x, y),
+            SubtractReversed { x, y } => self.subtract(x, y, x),
+            ShiftLeft { x, y } => self.shift_left(x, y),
+            SkipIfRegistersNotEqual { x, y } => {
+                self.skip_if(self.registers[x as usize] != self.registers[y as usize])
+            }
+            SetIndex(nnn) => self.index = nnn,
+            JumpWithOffset(nnn) => self.jump_with_offset(nnn),
+            Random { x, nn } => self.random(x, nn),
+            Draw { x, y, n } => self.draw_sprite(x, y, n)?,
+            SkipIfPressed { x } | SkipIfPressedKeypad2 { x } => {
+                self.skip_if(self.keypad[self.registers[x as usize] as usize & 0xF])
+            }
+            SkipIfNotPressed { x } | SkipIfNotPressedKeypad2 { x } => {
+                self.skip_if(!self.keypad[self.registers[x as usize] as usize & 0xF])
+            }
+            GetDelay { x } => self.registers[x as usize] = self.delay_timer,
+            WaitForKey { x } => self.wait_for_key_press(x),
+            SetDelay { x } => self.delay_timer = self.registers[x as usize],
+            SetSound { x } => self.sound_timer = self.registers[x as usize],
+            AddToIndex { x } => self.add_x_to_index(x),
+            SmallCharacter { x } => self.set_index_for_char(x),
+            BinaryCodedDecimal { x } => self.binary_coded_decimal(x)?,
+            Store { x } => self.save_x(x)?,
+            Load { x } => self.load_x(x)?,
+
+            ScrollDown(n) => self.renderer.scroll(0, n as isize),
+            ScrollRight => self.renderer.scroll(4, 0),
+            ScrollLeft => self.renderer.scroll(-4, 0),
+            Exit | Stop => self.exited = true,
+            LowResolution => self.set_hires(false),
+            HighResolution => self.set_hires(true),
+            BigCharacter { x } => self.set_index_for_big_char(x),
+            SaveFlags { x } => self.save_flags(x),
+            LoadFlags { x } => self.load_flags(x),
+
+            ScrollUp(n) | MegaScrollUp(n) => self.scroll_up(n),
+            SaveRange { x, y } => self.save_range(x, y)?,
+            LoadRange { x, y } => self.load_range(x, y)?,
+            LongIndex => self.set_long_index()?,
+            SelectPlanes(n) => self.renderer.planes = n & 0x3,
+            LoadAudioPattern => self.load_audio_pattern()?,
+            SetPitch { x } => self.set_pitch(x),
+
+            #[cfg(feature = "megachip")]
+            MegaOff => self.set_mega_mode(false),
+            #[cfg(feature = "megachip")]
+            MegaOn => self.set_mega_mode(true),
+            #[cfg(feature = "megachip")]
+            LongIndex24(nn) => self.set_long_index_24(nn)?,
+            #[cfg(feature = "megachip")]
+            LoadPalette(nn) => self.load_palette(nn)?,
+            #[cfg(feature = "megachip")]
+            SpriteWidth(nn) => self.mega_renderer.sprite_width = nn as usize,
+            #[cfg(feature = "megachip")]
+            SpriteHeight(nn) => self.mega_renderer.sprite_height = nn as usize,
+            #[cfg(feature = "megachip")]
+            ScreenAlpha(nn) => self.mega_renderer.alpha = nn,
+            #[cfg(feature = "megachip")]
+            PlaySample(n) => self.play_sample(n)?,
+            #[cfg(feature = "megachip")]
+            StopSample => self.stop_sample(),
+            #[cfg(feature = "megachip")]
+            BlendMode(n) => self.set_blend_mode(n),
+            #[cfg(feature = "megachip")]
+            CollisionColor(nn) => self.mega_renderer.collision_color = nn,
+            // Without the MegaChip screen these are never decoded, but could
+            // still be passed in.
+            #[cfg(not(feature = "megachip"))]
+            MegaOff | MegaOn | LongIndex24(_) | LoadPalette(_) | SpriteWidth(_)
+            | SpriteHeight(_) | ScreenAlpha(_) | PlaySample(_) | StopSample | BlendMode(_)
+            | CollisionColor(_) => return Err(CpuFault::UnknownOpcode(instruction.encode())),
+
+            CycleBackground => self.cycle_background(),
+            AddNibbles { x, y } => self.add_nibbles(x, y),
+            ZoneColor { x, y, n } => self.set_zone_color(x, y, n),
+            PortOutput { x } | Output { x } => self.port_output = self.registers[x as usize],
+            // Nothing is attached to the input ports, so they always read as
+            // zero.
+            PortInput { x } | WaitForInput { x } | Input { x } => self.registers[x as usize] = 0,
+
+            NoOperation => (),
+            WaitForDelay => self.waiting_for_delay = true,
+            SkipNext => self.skip_next_instruction(),
+            SkipIfGreater { x, y } => {
+                self.skip_if(self.registers[x as usize] > self.registers[y as usize])
+            }
+            SaveRangeAndAdvance { x, y } => self.save_range_and_advance(x, y)?,
+            LoadRangeAndAdvance { x, y } => self.load_range_and_advance(x, y)?,
+            JumpBackward(nn) => self.jump_backward(nn),
+            JumpForward(nn) => self.jump_forward(nn),
+            SkipBytes { x } => {
+                self.program_counter = self
+                    .program_counter
+                    .wrapping_add(self.registers[x as usize] as u16)
+            }
+            SetDelayAndWait { x } => {
+                self.delay_timer = self.registers[x as usize];
+                self.waiting_for_delay = true;
+            }
+        }
+
+        Ok(if self.exited {
+            StepOutcome::Exited
+        } else if self.waiting_for_delay
+            || (matches!(instruction, WaitForKey { .. })
+                && self.program_counter != next_instruction)
+        {
+            StepOutcome::Waiting
+        } else {
+            StepOutcome::Executed
+        })
+    }
+
+    fn read_memory(&self, address: usize) -> Result<u8, CpuFault> {
+        if address < self.platform.memory_size() {
+            Ok(self.memory[address])
+        } else {
+            Err(CpuFault::MemoryOutOfBounds { address })
+        }
+    }
+
+    fn write_memory(&mut self, address: usize, value: u8) -> Result<(), CpuFault> {
+        if address < self.platform.memory_size() {
+            self.memory[address] = value;
+            Ok(())
+        } else {
+            Err(CpuFault::MemoryOutOfBounds { address })
         }
     }
 
     fn clear_display(&mut self) {
-        self.renderer.buffer = [[false; 64]; 32];
+        #[cfg(feature = "megachip")]
+        if self.mega_mode {
+            self.mega_renderer.present();
+            return;
+        }
+        self.renderer.clear();
+    }
+
+    fn scroll_up(&mut self, n: u8) {
+        #[cfg(feature = "megachip")]
+        if self.mega_mode {
+            self.mega_renderer.scroll_up(n as usize);
+            return;
+        }
+        self.renderer.scroll(0, -(n as isize));
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.renderer.hires = hires;
+        self.renderer.buffer = [[0; SCREEN_WIDTH]; SCREEN_HEIGHT];
         self.renderer.redraw = true;
     }
 
-    fn return_from_subroutine(&mut self) {
+    fn return_from_subroutine(&mut self) -> Result<(), CpuFault> {
+        if self.stack_pointer == 0 {
+            return Err(CpuFault::StackUnderflow);
+        }
         self.stack_pointer -= 1;
         self.program_counter = self.stack[self.stack_pointer as usize];
+        Ok(())
     }
 
-    fn jump(&mut self, opcode: u16) {
-        self.program_counter = opcode & 0x0FFF;
+    fn jump(&mut self, nnn: u16) {
+        self.program_counter = nnn;
     }
 
-    fn call(&mut self, opcode: u16) {
+    fn call(&mut self, nnn: u16) -> Result<(), CpuFault> {
+        if self.stack_pointer as usize >= self.stack.len() {
+            return Err(CpuFault::StackOverflow);
+        }
         self.stack[self.stack_pointer as usize] = self.program_counter;
         self.stack_pointer += 1;
-        self.program_counter = opcode & 0x0FFF;
+        self.program_counter = nnn;
+        Ok(())
     }
 
-    fn skip_if_x_equal(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let nn = (opcode & 0x00FF) as u8;
-        if self.registers[x] == nn {
-            self.program_counter += 2;
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.skip_next_instruction();
         }
     }
 
-    fn skip_if_x_not_equal(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let nn = (opcode & 0x00FF) as u8;
-        if self.registers[x] != nn {
-            self.program_counter += 2;
+    /// Skips over the next instruction, which is four bytes long for XO-CHIP's
+    /// F000 NNNN.
+    fn skip_next_instruction(&mut self) {
+        let pc = self.program_counter as usize;
+        if self.platform.has_xochip_opcodes()
+            && self.memory.get(pc) == Some(&0xF0)
+            && self.memory.get(pc + 1) == Some(&0x00)
+        {
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
+        self.program_counter = self.program_counter.wrapping_add(2);
     }
 
-    fn skip_if_x_and_y_equal(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        if self.registers[x] == self.registers[y] {
-            self.program_counter += 2;
+    fn logic(&mut self, x: u8, y: u8, operation: fn(u8, u8) -> u8) {
+        let (x, y) = (x as usize, y as usize);
+        self.registers[x] = operation(self.registers[x], self.registers[y]);
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
         }
     }
 
-    fn set_x(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let nn = (opcode & 0x00FF) as u8;
-        self.registers[x] = nn;
+    fn add(&mut self, x: u8, y: u8) {
+        let (x, y) = (x as usize, y as usize);
+        let (result, overflow) = self.registers[x].overflowing_add(self.registers[y]);
+        self.registers[x] = result;
+        self.registers[0xF] = if overflow { 1 } else { 0 };
     }
 
-    fn add_x(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let nn = (opcode & 0x00FF) as u8;
-        self.registers[x] = self.registers[x].wrapping_add(nn);
+    /// Stores V`minuend` - V`subtrahend` in VX, with VF set when there was no
+    /// borrow. 8XY5 and 8XY7 only differ in the order of the operands.
+    fn subtract(&mut self, x: u8, minuend: u8, subtrahend: u8) {
+        let minuend = self.registers[minuend as usize];
+        let subtrahend = self.registers[subtrahend as usize];
+        self.registers[x as usize] = minuend.wrapping_sub(subtrahend);
+        self.registers[0xF] = if minuend >= subtrahend { 1 } else { 0 };
     }
 
-    fn arithmetic(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        let n = (opcode & 0x000F) as u8;
+    fn shift_right(&mut self, x: u8, y: u8) {
+        let source = if self.quirks.shift_uses_vy { y } else { x } as usize;
+        let lsb = self.registers[source] & 0x1;
+        self.registers[x as usize] = self.registers[source] >> 1;
+        self.registers[0xF] = lsb;
+    }
 
-        match n {
-            0x0 => self.registers[x] = self.registers[y],
-            0x1 => self.registers[x] |= self.registers[y],
-            0x2 => self.registers[x] &= self.registers[y],
-            0x3 => self.registers[x] ^= self.registers[y],
-            0x4 => {
-                let (result, overflow) = self.registers[x].overflowing_add(self.registers[y]);
-                self.registers[x] = result;
-                self.registers[0xF] = if overflow { 1 } else { 0 };
-            }
-            0x5 => {
-                let vx = self.registers[x];
-                let vy = self.registers[y];
-                self.registers[x] = vx.wrapping_sub(vy);
-                self.registers[0xF] = if vx >= vy { 1 } else { 0 };
+    fn shift_left(&mut self, x: u8, y: u8) {
+        let source = if self.quirks.shift_uses_vy { y } else { x } as usize;
+        let msb = (self.registers[source] >> 7) & 0x1;
+        self.registers[x as usize] = self.registers[source] << 1;
+        self.registers[0xF] = msb;
+    }
+
+    fn jump_with_offset(&mut self, nnn: u16) {
+        let offset = if self.quirks.jump_uses_vx {
+            self.registers[(nnn >> 8) as usize]
+        } else {
+            self.registers[0]
+        };
+        self.program_counter = nnn + offset as u16;
+    }
+
+    fn random(&mut self, x: u8, nn: u8) {
+        self.registers[x as usize] = self.random.next_byte(&self.memory) & nn;
+    }
+
+    fn draw_sprite(&mut self, x: u8, y: u8, n: u8) -> Result<(), CpuFault> {
+        #[cfg(feature = "megachip")]
+        if self.mega_mode {
+            return self.draw_mega_sprite(x, y);
+        }
+
+        let x = self.registers[x as usize] as usize;
+        let y = self.registers[y as usize] as usize;
+        let n = n as usize;
+
+        // DXY0 draws a 16x16 sprite made of two bytes per row on SUPER-CHIP.
+        let (rows, width) = if n == 0 && self.platform.has_superchip_opcodes() {
+            (16, 16)
+        } else {
+            (n, 8)
+        };
+        let bytes_per_row = width / 8;
+
+        self.registers[0xF] = 0;
+
+        let screen_width = self.renderer.width();
+        let screen_height = self.renderer.height();
+
+        // The starting position always wraps; only pixels running off the
+        // edge are affected by the wrapping quirk.
+        let x = x % screen_width;
+        let y = y % screen_height;
+        let wrap = self.quirks.wrap_sprites;
+
+        // Each selected plane takes its own copy of the sprite data, one
+        // after the other.
+        let mut address = self.index as usize;
+        for plane in 0..2 {
+            let plane_bit = 1 << plane;
+            if self.renderer.planes & plane_bit == 0 {
+                continue;
             }
-            0x6 => {
-                let lsb = self.registers[x] & 0x1;
-                self.registers[x] >>= 1;
-                self.registers[0xF] = lsb;
+            for row in 0..rows {
+                let row_address = address + row * bytes_per_row;
+                if !wrap && y + row >= screen_height {
+                    continue;
+                }
+                let sprite_row = if bytes_per_row == 2 {
+                    let high = self.read_memory(row_address)? as u16;
+                    (high << 8) | self.read_memory(row_address + 1)? as u16
+                } else {
+                    self.read_memory(row_address)? as u16
+                };
+                let renderer = &mut self.renderer;
+                for bit in 0..width {
+                    if !wrap && x + bit >= screen_width {
+                        break;
+                    }
+                    let sprite_bit = (sprite_row >> (width - 1 - bit)) & 1;
+                    let buffer_x = (x + bit) % screen_width;
+                    let buffer_y = (y + row) % screen_height;
+
+                    if sprite_bit == 1 {
+                        if renderer.buffer[buffer_y][buffer_x] & plane_bit != 0 {
+                            self.registers[0xF] = 1;
+                        }
+                        renderer.buffer[buffer_y][buffer_x] ^= plane_bit;
+                    }
+                }
             }
-            0x7 => {
-                let vx = self.registers[x];
-                let vy = self.registers[y];
-                self.registers[x] = vy.wrapping_sub(vx);
-                self.registers[0xF] = if vy >= vx { 1 } else { 0 };
+            address += rows * bytes_per_row;
+        }
+
+        self.renderer.redraw = true;
+        Ok(())
+    }
+
+    #[cfg(feature = "megachip")]
+    fn draw_mega_sprite(&mut self, x: u8, y: u8) -> Result<(), CpuFault> {
+        let x = self.registers[x as usize] as usize;
+        let y = self.registers[y as usize] as usize;
+
+        self.registers[0xF] = 0;
+
+        self.mega_renderer.begin_drawing();
+
+        // A width or height of zero stands for 256.
+        let width = match self.mega_renderer.sprite_width {
+            0 => 256,
+            width => width,
+        };
+        let height = match self.mega_renderer.sprite_height {
+            0 => 256,
+            height => height,
+        };
+
+        for row in 0..height {
+            let buffer_y = y + row;
+            if buffer_y >= MEGA_HEIGHT {
+                break;
             }
-            0xE => {
-                let msb = (self.registers[x] >> 7) & 0x1;
-                self.registers[x] <<= 1;
-                self.registers[0xF] = msb;
+            for column in 0..width {
+                let buffer_x = x + column;
+                if buffer_x >= MEGA_WIDTH {
+                    break;
+                }
+                let color_index = self.read_memory(self.index as usize + row * width + column)?;
+                if color_index == 0 {
+                    continue;
+                }
+                let renderer = &mut self.mega_renderer;
+                let existing = renderer.indices[buffer_y][buffer_x];
+                if existing != 0 && existing == renderer.collision_color {
+                    self.registers[0xF] = 1;
+                }
+                renderer.indices[buffer_y][buffer_x] = color_index;
+                renderer.pixels[buffer_y][buffer_x] = renderer.blend_mode.blend(
+                    renderer.palette[color_index as usize],
+                    renderer.pixels[buffer_y][buffer_x],
+                );
             }
-            _ => (),
         }
+        Ok(())
     }
 
-    fn skip_if_x_and_y_different(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        if self.registers[x] != self.registers[y] {
-            self.program_counter += 2;
+    fn wait_for_key_press(&mut self, x: u8) {
+        if let Some(key) = self.keypad.iter().position(|&k| k) {
+            self.registers[x as usize] = key as u8;
+        } else {
+            // Repeat this instruction until a key is pressed
+            self.program_counter = self.program_counter.wrapping_sub(2);
         }
     }
 
-    fn set_index(&mut self, opcode: u16) {
-        self.index = opcode & 0x0FFF;
+    fn add_x_to_index(&mut self, x: u8) {
+        let (result, _) = self
+            .index
+            .overflowing_add(self.registers[x as usize] as u16);
+        self.index = result;
     }
 
-    fn jump_with_offset(&mut self, opcode: u16) {
-        self.program_counter = (opcode & 0x0FFF) + self.registers[0] as u16;
+    fn set_index_for_char(&mut self, x: u8) {
+        self.index = self.font_address + (self.registers[x as usize] as u16 & 0xF) * 5;
     }
 
-    fn random(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let nn = (opcode & 0x00FF) as u8;
-        self.registers[x] = self.random.gen::<u8>() & nn;
+    fn set_index_for_big_char(&mut self, x: u8) {
+        self.index = self.big_font_address + (self.registers[x as usize] as u16 & 0xF) * 10;
     }
 
-    fn draw_sprite(&mut self, opcode: u16) {
-        let x = self.registers[((opcode & 0x0F00) >> 8) as usize] as usize;
-        let y = self.registers[((opcode & 0x00F0) >> 4) as usize] as usize;
-        let n = (opcode & 0x000F) as usize;
+    fn set_pitch(&mut self, x: u8) {
+        self.pitch = self.registers[x as usize];
+        self.audio_updated = true;
+    }
 
-        self.registers[0xF] = 0;
+    fn binary_coded_decimal(&mut self, x: u8) -> Result<(), CpuFault> {
+        let value = self.registers[x as usize];
+        let index = self.index as usize;
+        self.write_memory(index, value / 100)?;
+        self.write_memory(index + 1, (value / 10) % 10)?;
+        self.write_memory(index + 2, value % 10)
+    }
 
-        let renderer = &mut self.renderer;
-        for row in 0..n {
-            let sprite_byte = self.memory[(self.index + row as u16) as usize];
-            for bit in 0..8 {
-                let sprite_bit = (sprite_byte >> (7 - bit)) & 1;
-                let buffer_x = (x + bit) % 64;
-                let buffer_y = (y + row) % 32;
-
-                if sprite_bit == 1 {
-                    if renderer.buffer[buffer_y][buffer_x] {
-                        self.registers[0xF] = 1;
-                    }
-                    renderer.buffer[buffer_y][buffer_x] ^= true;
-                }
-            }
+    fn save_x(&mut self, x: u8) -> Result<(), CpuFault> {
+        let x = x as usize;
+        for i in 0..=x {
+            self.write_memory(self.index as usize + i, self.registers[i])?;
         }
+        self.advance_index(x);
+        Ok(())
+    }
 
-        renderer.redraw = true;
+    fn load_x(&mut self, x: u8) -> Result<(), CpuFault> {
+        let x = x as usize;
+        for i in 0..=x {
+            self.registers[i] = self.read_memory(self.index as usize + i)?;
+        }
+        self.advance_index(x);
+        Ok(())
     }
 
-    fn skip_if_pressed(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        if self.keypad[self.registers[x] as usize] {
-            self.program_counter += 2;
+    fn advance_index(&mut self, x: usize) {
+        match self.quirks.index_increment {
+            IndexIncrement::None => (),
+            IndexIncrement::ByX => self.index = self.index.wrapping_add(x as u16),
+            IndexIncrement::ByXPlusOne => self.index = self.index.wrapping_add(x as u16 + 1),
         }
     }
 
-    fn skip_if_not_pressed(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        if !self.keypad[self.registers[x] as usize] {
-            self.program_counter += 2;
+    fn save_flags(&mut self, x: u8) {
+        let x = x as usize;
+        self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]);
+    }
+
+    fn load_flags(&mut self, x: u8) {
+        let x = x as usize;
+        self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+    }
+
+    fn save_range(&mut self, x: u8, y: u8) -> Result<(), CpuFault> {
+        let (x, y) = (x as usize, y as usize);
+        let count = x.abs_diff(y) + 1;
+        for offset in 0..count {
+            let register = if x <= y { x + offset } else { x - offset };
+            self.write_memory(self.index as usize + offset, self.registers[register])?;
         }
+        Ok(())
     }
 
-    fn wait_for_key_press(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        if let Some(key) = self.keypad.iter().position(|&k| k) {
-            self.registers[x] = key as u8;
-        } else {
-            self.program_counter -= 2; // Repeat this instruction until a key is pressed
+    fn load_range(&mut self, x: u8, y: u8) -> Result<(), CpuFault> {
+        let (x, y) = (x as usize, y as usize);
+        let count = x.abs_diff(y) + 1;
+        for offset in 0..count {
+            let register = if x <= y { x + offset } else { x - offset };
+            self.registers[register] = self.read_memory(self.index as usize + offset)?;
         }
+        Ok(())
     }
 
-    fn misc(&mut self, opcode: u16) {
-        let nn = (opcode & 0x00FF) as u8;
+    fn set_long_index(&mut self) -> Result<(), CpuFault> {
+        self.index = self.fetch(self.program_counter)?;
+        self.program_counter = self.program_counter.wrapping_add(2);
+        Ok(())
+    }
 
-        match nn {
-            0x07 => self.set_x_to_delay(opcode),
-            0x0A => self.wait_for_key_press(opcode),
-            0x15 => self.set_delay_to_x(opcode),
-            0x18 => self.set_sound_to_x(opcode),
-            0x1E => self.add_x_to_index(opcode),
-            0x29 => self.set_index_for_char(opcode),
-            0x33 => self.binary_coded_decimal(opcode),
-            0x55 => self.save_x(opcode),
-            0x65 => self.load_x(opcode),
-            _ => (),
+    fn load_audio_pattern(&mut self) -> Result<(), CpuFault> {
+        let mut pattern = [0; 16];
+        for (offset, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.read_memory(self.index as usize + offset)?;
         }
+        self.audio_pattern = Some(pattern);
+        self.audio_updated = true;
+        Ok(())
     }
 
-    fn set_x_to_delay(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        self.registers[x] = self.delay_timer;
+    #[cfg(feature = "megachip")]
+    fn set_mega_mode(&mut self, enabled: bool) {
+        self.mega_mode = enabled;
+        self.mega_renderer.present();
+        self.renderer.redraw = true;
     }
 
-    fn set_delay_to_x(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        self.delay_timer = self.registers[x];
+    /// 01NN NNNN loads a 24-bit address. Memory stops at 64 KiB, so anything
+    /// above that is a fault rather than being silently truncated.
+    #[cfg(feature = "megachip")]
+    fn set_long_index_24(&mut self, nn: u8) -> Result<(), CpuFault> {
+        let address = ((nn as usize) << 16) | self.fetch(self.program_counter)? as usize;
+        if address >= self.platform.memory_size() {
+            return Err(CpuFault::MemoryOutOfBounds { address });
+        }
+        self.index = address as u16;
+        self.program_counter = self.program_counter.wrapping_add(2);
+        Ok(())
+    }
+
+    #[cfg(feature = "megachip")]
+    fn load_palette(&mut self, count: u8) -> Result<(), CpuFault> {
+        for entry in 0..count as usize {
+            let address = self.index as usize + entry * 4;
+            let mut color = 0;
+            for offset in 0..4 {
+                color = (color << 8) | self.read_memory(address + offset)? as u32;
+            }
+            // Index 0 is transparent, so the loaded colours start at 1.
+            if entry + 1 < 256 {
+                self.mega_renderer.palette[entry + 1] = color;
+            }
+        }
+        Ok(())
     }
 
-    fn set_sound_to_x(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        self.sound_timer = self.registers[x];
+    /// The sample header at I holds a 16-bit rate and 24-bit length, followed
+    /// by a reserved byte and the PCM data itself.
+    #[cfg(feature = "megachip")]
+    fn play_sample(&mut self, n: u8) -> Result<(), CpuFault> {
+        let address = self.index as usize;
+        let mut header = [0; 6];
+        for (offset, byte) in header.iter_mut().enumerate() {
+            *byte = self.read_memory(address + offset)? as usize;
+        }
+        let rate = ((header[0] << 8) | header[1]) as u16;
+        let length = (header[2] << 16) | (header[3] << 8) | header[4];
+        let end = address + 6 + length;
+        if end > self.platform.memory_size() {
+            return Err(CpuFault::MemoryOutOfBounds { address: end - 1 });
+        }
+        self.sample = Some(Sample {
+            address: address + 6,
+            length,
+            rate,
+            looping: n == 0,
+        });
+        self.audio_updated = true;
+        Ok(())
+    }
+
+    #[cfg(feature = "megachip")]
+    fn stop_sample(&mut self) {
+        self.sample = None;
+        self.audio_updated = true;
+    }
+
+    #[cfg(feature = "megachip")]
+    fn set_blend_mode(&mut self, n: u8) {
+        self.mega_renderer.blend_mode = match n {
+            1 => BlendMode::Alpha25,
+            2 => BlendMode::Alpha50,
+            3 => BlendMode::Alpha75,
+            4 => BlendMode::Additive,
+            5 => BlendMode::Multiply,
+            _ => BlendMode::Normal,
+        };
+    }
+
+    fn cycle_background(&mut self) {
+        self.renderer.background = (self.renderer.background + 1) % 4;
+        self.renderer.redraw = true;
     }
 
-    fn add_x_to_index(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let (result, _) = self.index.overflowing_add(self.registers[x] as u16);
-        self.index = result;
+    /// 5XY1 on CHIP-8X adds each nibble separately, keeping three bits of each.
+    fn add_nibbles(&mut self, x: u8, y: u8) {
+        let vx = self.registers[x as usize];
+        let vy = self.registers[y as usize];
+        let high = ((vx >> 4) + (vy >> 4)) & 0x7;
+        let low = ((vx & 0xF) + (vy & 0xF)) & 0x7;
+        self.registers[x as usize] = (high << 4) | low;
+    }
+
+    /// BXY0 colours 8x4 zones: the low nibbles of VX and VY give the first
+    /// zone column and row, the high nibbles the number of extra zones. BXYN
+    /// colours N pixel rows starting at VY in the zone column holding VX. The
+    /// colour is taken from V(X+1).
+    fn set_zone_color(&mut self, x: u8, y: u8, n: u8) {
+        let x = x as usize;
+        let n = n as usize;
+        let vx = self.registers[x] as usize;
+        let vy = self.registers[y as usize] as usize;
+        let color = self.registers[(x + 1) & 0xF] & 0x7;
+
+        let (columns, rows) = if n == 0 {
+            let first_column = vx & 0xF;
+            let first_row = (vy & 0xF) * 4;
+            (
+                first_column..=first_column + (vx >> 4),
+                first_row..first_row + ((vy >> 4) + 1) * 4,
+            )
+        } else {
+            let column = (vx / 8) % COLOR_ZONE_COLUMNS;
+            (column..=column, vy..vy + n)
+        };
+
+        if let Some(zones) = self.renderer.zone_colors.as_mut() {
+            for row in rows {
+                for column in columns.clone() {
+                    zones[row % COLOR_ZONE_ROWS][column % COLOR_ZONE_COLUMNS] = color;
+                }
+            }
+        }
+        self.renderer.redraw = true;
     }
 
-    fn set_index_for_char(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        self.index = (self.registers[x] as u16) * 5;
+    fn save_range_and_advance(&mut self, x: u8, y: u8) -> Result<(), CpuFault> {
+        self.save_range(x, y)?;
+        self.index = self.index.wrapping_add(x.abs_diff(y) as u16 + 1);
+        Ok(())
     }
 
-    fn binary_coded_decimal(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let value = self.registers[x];
-        self.memory[self.index as usize] = value / 100;
-        self.memory[(self.index + 1) as usize] = (value / 10) % 10;
-        self.memory[(self.index + 2) as usize] = value % 10;
+    fn load_range_and_advance(&mut self, x: u8, y: u8) -> Result<(), CpuFault> {
+        self.load_range(x, y)?;
+        self.index = self.index.wrapping_add(x.abs_diff(y) as u16 + 1);
+        Ok(())
     }
 
-    fn save_x(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        for i in 0..=x {
-            self.memory[(self.index + i as u16) as usize] = self.registers[i];
-        }
+    /// BBNN and BFNN jump relative to the address of the jump itself.
+    fn jump_backward(&mut self, nn: u8) {
+        self.program_counter = self.program_counter.wrapping_sub(2).wrapping_sub(nn as u16);
     }
 
-    fn load_x(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        for i in 0..=x {
-            self.registers[i] = self.memory[(self.index + i as u16) as usize];
-        }
+    fn jump_forward(&mut self, nn: u8) {
+        self.program_counter = self.program_counter.wrapping_sub(2).wrapping_add(nn as u16);
     }
 }
@@ -1,49 +1,57 @@
 #[cfg(test)]
-use crate::processor::CPU;
+use crate::fault::{CpuFault, StepOutcome};
+#[cfg(test)]
+use crate::font::Font;
+#[cfg(test)]
+use crate::instruction::Instruction;
+#[cfg(test)]
+use crate::platform::Platform;
+#[cfg(all(test, feature = "megachip"))]
+use crate::processor::MegaRenderer;
+#[cfg(test)]
+use crate::processor::{Renderer, CPU, MEMORY_SIZE};
+#[cfg(test)]
+use crate::quirks::Quirks;
+#[cfg(test)]
+use crate::random::{SeededRandom, VipRandom};
 
 #[test]
 fn test_00e0_clear_display() {
-    let mut cpu = CPU::new();
-    cpu.renderer.buffer[0][0] = true;
-    cpu.renderer.buffer[31][63] = true;
-    cpu.execute_opcode(0x00E0); // Clear display
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
+    cpu.renderer.buffer[0][0] = 1;
+    cpu.renderer.buffer[31][63] = 1;
+    cpu.execute_opcode(0x00E0).unwrap(); // Clear display
     assert!(cpu
         .renderer
         .buffer
         .iter()
-        .all(|row| row.iter().all(|&pixel| !pixel)));
+        .all(|row| row.iter().all(|&pixel| pixel == 0)));
     assert!(cpu.renderer.redraw);
 }
 
 #[test]
 fn test_dxyn_draw_sprite() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.index = 0x300;
     cpu.memory[0x300] = 0b10101010;
     cpu.memory[0x301] = 0b01010101;
     cpu.registers[0] = 0; // X coordinate
     cpu.registers[1] = 0; // Y coordinate
-    cpu.execute_opcode(0xD012); // Draw 2-byte sprite at (0, 0)
+    cpu.execute_opcode(0xD012).unwrap(); // Draw 2-byte sprite at (0, 0)
     assert_eq!(
-        cpu.renderer.buffer[0],
+        cpu.renderer.buffer[0][..64],
         [
-            true, false, true, false, true, false, true, false, false, false, false, false, false,
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            false, false, false
+            1, 0, 1, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0
         ]
     );
     assert_eq!(
-        cpu.renderer.buffer[1],
+        cpu.renderer.buffer[1][..64],
         [
-            false, true, false, true, false, true, false, true, false, false, false, false, false,
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            false, false, false
+            0, 1, 0, 1, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0
         ]
     );
     assert!(cpu.renderer.redraw);
@@ -51,144 +59,144 @@ fn test_dxyn_draw_sprite() {
 
 #[test]
 fn test_3x_skip_if_equal() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[6] = 0x43;
-    cpu.execute_opcode(0x3643); // Skip if V6 == 0x43
+    cpu.execute_opcode(0x3643).unwrap(); // Skip if V6 == 0x43
     assert_eq!(cpu.program_counter, 0x202); // PC should be incremented by 2 if condition is met
 }
 
 #[test]
 fn test_4x_skip_if_not_equal() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[5] = 0x42;
-    cpu.execute_opcode(0x4543); // Skip if Vs5 != 0x43
+    cpu.execute_opcode(0x4543).unwrap(); // Skip if Vs5 != 0x43
     assert_eq!(cpu.program_counter, 0x202);
 }
 
 #[test]
 fn test_5x_skip_if_equal() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[5] = 0x42;
     cpu.registers[6] = 0x42;
-    cpu.execute_opcode(0x5560); // Skip if V5 == V6
+    cpu.execute_opcode(0x5560).unwrap(); // Skip if V5 == V6
     assert_eq!(cpu.program_counter, 0x202);
 }
 
 #[test]
 fn test_7x_add() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[6] = 255;
-    cpu.execute_opcode(0x7601); // V6 += 1
+    cpu.execute_opcode(0x7601).unwrap(); // V6 += 1
     assert_eq!(cpu.registers[6], 0); // Should wrap around
 }
 
 #[test]
 fn test_9x_skip_if_not_equal() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[5] = 42;
     cpu.registers[6] = 43;
-    cpu.execute_opcode(0x9560); // Skip if V5 != V6
+    cpu.execute_opcode(0x9560).unwrap(); // Skip if V5 != V6
     assert_eq!(cpu.program_counter, 0x202);
 }
 
 #[test]
 fn test_ax_set_index() {
-    let mut cpu = CPU::new();
-    cpu.execute_opcode(0xA123); // I = 0x123
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
+    cpu.execute_opcode(0xA123).unwrap(); // I = 0x123
     assert_eq!(cpu.index, 0x123);
 }
 
 #[test]
 fn test_8xy0_set() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[5] = 42;
-    cpu.execute_opcode(0x8750); // V7 = V5
+    cpu.execute_opcode(0x8750).unwrap(); // V7 = V5
     assert_eq!(cpu.registers[7], 42);
 }
 
 #[test]
 fn test_8xy1_or() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[7] = 0b1010;
     cpu.registers[1] = 0b0101;
-    cpu.execute_opcode(0x8711); // V7 |= V1
+    cpu.execute_opcode(0x8711).unwrap(); // V7 |= V1
     assert_eq!(cpu.registers[7], 0b1111);
 }
 
 #[test]
 fn test_8xy2_and() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[6] = 0b1100;
     cpu.registers[7] = 0b1010;
-    cpu.execute_opcode(0x8672); // V6 &= V7
+    cpu.execute_opcode(0x8672).unwrap(); // V6 &= V7
     assert_eq!(cpu.registers[6], 0b1000);
 }
 
 #[test]
 fn test_8xy3_xor() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[6] = 0b1100;
     cpu.registers[7] = 0b1010;
-    cpu.execute_opcode(0x8673); // V6 ^= V7
+    cpu.execute_opcode(0x8673).unwrap(); // V6 ^= V7
     assert_eq!(cpu.registers[6], 0b0110);
 }
 
 #[test]
 fn test_8xy4_add_with_carry() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[7] = 200;
     cpu.registers[6] = 100;
-    cpu.execute_opcode(0x8764); // V7 += V6
+    cpu.execute_opcode(0x8764).unwrap(); // V7 += V6
     assert_eq!(cpu.registers[7], 44);
     assert_eq!(cpu.registers[0xF], 1); // Carry flag
 }
 
 #[test]
 fn test_8xy5_sub() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[7] = 10;
     cpu.registers[6] = 5;
-    cpu.execute_opcode(0x8765); // V7 -= V6
+    cpu.execute_opcode(0x8765).unwrap(); // V7 -= V6
     assert_eq!(cpu.registers[7], 5);
     assert_eq!(cpu.registers[0xF], 1); // No borrow
 }
 
 #[test]
 fn test_8xy6_shift_right() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[6] = 0b11010110;
-    cpu.execute_opcode(0x8606); // V6 = V6 SHR 1
+    cpu.execute_opcode(0x8606).unwrap(); // V6 = V6 SHR 1
     assert_eq!(cpu.registers[6], 0b01101011);
     assert_eq!(cpu.registers[0xF], 0);
 
     cpu.registers[6] = 0b11010111;
-    cpu.execute_opcode(0x8606); // V6 = V6 SHR 1
+    cpu.execute_opcode(0x8606).unwrap(); // V6 = V6 SHR 1
     assert_eq!(cpu.registers[6], 0b01101011);
     assert_eq!(cpu.registers[0xF], 1);
 }
 
 #[test]
 fn test_8xye_shift_left() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[6] = 0b11010110;
-    cpu.execute_opcode(0x860E); // V6 = V6 SHL 1
+    cpu.execute_opcode(0x860E).unwrap(); // V6 = V6 SHL 1
     assert_eq!(cpu.registers[6], 0b10101100);
     assert_eq!(cpu.registers[0xF], 1);
 
     cpu.registers[6] = 0b01010110;
-    cpu.execute_opcode(0x860E); // V6 = V6 SHL 1
+    cpu.execute_opcode(0x860E).unwrap(); // V6 = V6 SHL 1
     assert_eq!(cpu.registers[6], 0b10101100);
     assert_eq!(cpu.registers[0xF], 0);
 }
 
 #[test]
 fn test_fx55_fx65_save_load_registers() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[0] = 10;
     cpu.registers[1] = 20;
     cpu.registers[2] = 30;
     cpu.index = 0x300;
-    cpu.execute_opcode(0xF255); // Store registers V0 through V2 in memory starting at location I
+    cpu.execute_opcode(0xF255).unwrap(); // Store registers V0 through V2 in memory starting at location I
     assert_eq!(cpu.memory[0x300], 10);
     assert_eq!(cpu.memory[0x301], 20);
     assert_eq!(cpu.memory[0x302], 30);
@@ -198,7 +206,7 @@ fn test_fx55_fx65_save_load_registers() {
     cpu.registers[1] = 0;
     cpu.registers[2] = 0;
     cpu.index = 0x300;
-    cpu.execute_opcode(0xF265); // Read registers V0 through V2 from memory starting at location I
+    cpu.execute_opcode(0xF265).unwrap(); // Read registers V0 through V2 from memory starting at location I
     assert_eq!(cpu.registers[0], 10);
     assert_eq!(cpu.registers[1], 20);
     assert_eq!(cpu.registers[2], 30);
@@ -207,11 +215,588 @@ fn test_fx55_fx65_save_load_registers() {
 
 #[test]
 fn test_fx33_binary_coded_decimal() {
-    let mut cpu = CPU::new();
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
     cpu.registers[2] = 137;
     cpu.index = 0x300;
-    cpu.execute_opcode(0xF233); // Store BCD of V2
+    cpu.execute_opcode(0xF233).unwrap(); // Store BCD of V2
     assert_eq!(cpu.memory[0x300], 1);
     assert_eq!(cpu.memory[0x301], 3);
     assert_eq!(cpu.memory[0x302], 7);
 }
+
+#[test]
+fn test_8xy1_vf_reset_quirk() {
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::COSMAC_VIP);
+    cpu.registers[0xF] = 7;
+    cpu.execute_opcode(0x8011).unwrap(); // V0 |= V1
+    assert_eq!(cpu.registers[0xF], 0);
+
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::SUPERCHIP_11);
+    cpu.registers[0xF] = 7;
+    cpu.execute_opcode(0x8011).unwrap();
+    assert_eq!(cpu.registers[0xF], 7);
+}
+
+#[test]
+fn test_8xy6_shift_uses_vy_quirk() {
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::COSMAC_VIP);
+    cpu.registers[6] = 0xFF;
+    cpu.registers[7] = 0b00000011;
+    cpu.execute_opcode(0x8676).unwrap(); // V6 = V7 SHR 1
+    assert_eq!(cpu.registers[6], 0b00000001);
+    assert_eq!(cpu.registers[0xF], 1);
+}
+
+#[test]
+fn test_fx55_index_increment_quirk() {
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::COSMAC_VIP);
+    cpu.index = 0x300;
+    cpu.execute_opcode(0xF255).unwrap();
+    assert_eq!(cpu.index, 0x303);
+
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::CHIP_48);
+    cpu.index = 0x300;
+    cpu.execute_opcode(0xF265).unwrap();
+    assert_eq!(cpu.index, 0x302);
+}
+
+#[test]
+fn test_bnnn_jump_quirk() {
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
+    cpu.registers[0] = 0x10;
+    cpu.registers[3] = 0x20;
+    cpu.execute_opcode(0xB300).unwrap(); // Jump to 0x300 + V0
+    assert_eq!(cpu.program_counter, 0x310);
+
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::SUPERCHIP_11);
+    cpu.registers[0] = 0x10;
+    cpu.registers[3] = 0x20;
+    cpu.execute_opcode(0xB300).unwrap(); // Jump to 0x300 + V3
+    assert_eq!(cpu.program_counter, 0x320);
+}
+
+#[test]
+fn test_dxyn_clip_quirk() {
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::COSMAC_VIP);
+    cpu.index = 0x300;
+    cpu.memory[0x300] = 0xFF;
+    cpu.registers[0] = 60;
+    cpu.registers[1] = 0;
+    cpu.execute_opcode(0xD011).unwrap();
+    assert_eq!(cpu.renderer.buffer[0][63], 1);
+    assert_eq!(cpu.renderer.buffer[0][0], 0); // Clipped, not wrapped
+
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
+    cpu.index = 0x300;
+    cpu.memory[0x300] = 0xFF;
+    cpu.registers[0] = 60;
+    cpu.registers[1] = 0;
+    cpu.execute_opcode(0xD011).unwrap();
+    assert_eq!(cpu.renderer.buffer[0][0], 1); // Wrapped around
+}
+
+#[test]
+fn test_00ff_00fe_resolution_switch() {
+    let mut cpu = CPU::new(Platform::SuperChip, Quirks::SUPERCHIP_11);
+    cpu.execute_opcode(0x00FF).unwrap(); // High resolution
+    assert!(cpu.renderer.hires);
+    assert_eq!((cpu.renderer.width(), cpu.renderer.height()), (128, 64));
+    cpu.execute_opcode(0x00FE).unwrap(); // Low resolution
+    assert_eq!((cpu.renderer.width(), cpu.renderer.height()), (64, 32));
+
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
+    assert_eq!(
+        cpu.execute_opcode(0x00FF), // Not decoded on plain CHIP-8
+        Err(CpuFault::UnknownOpcode(0x00FF))
+    );
+    assert!(!cpu.renderer.hires);
+}
+
+#[test]
+fn test_00cn_scroll_down() {
+    let mut cpu = CPU::new(Platform::SuperChip, Quirks::SUPERCHIP_11);
+    cpu.renderer.buffer[0][5] = 1;
+    cpu.execute_opcode(0x00C3).unwrap(); // Scroll down 3 rows
+    assert_eq!(cpu.renderer.buffer[0][5], 0);
+    assert_eq!(cpu.renderer.buffer[3][5], 1);
+}
+
+#[test]
+fn test_00fb_00fc_scroll_horizontal() {
+    let mut cpu = CPU::new(Platform::SuperChip, Quirks::SUPERCHIP_11);
+    cpu.renderer.buffer[0][0] = 1;
+    cpu.execute_opcode(0x00FB).unwrap(); // Scroll right 4 pixels
+    assert_eq!(cpu.renderer.buffer[0][0], 0);
+    assert_eq!(cpu.renderer.buffer[0][4], 1);
+    cpu.execute_opcode(0x00FC).unwrap(); // Scroll left 4 pixels
+    assert_eq!(cpu.renderer.buffer[0][0], 1);
+    assert_eq!(cpu.renderer.buffer[0][4], 0);
+}
+
+#[test]
+fn test_dxy0_draw_big_sprite() {
+    let mut cpu = CPU::new(Platform::SuperChip, Quirks::SUPERCHIP_11);
+    cpu.execute_opcode(0x00FF).unwrap();
+    cpu.index = 0x300;
+    cpu.memory[0x300] = 0x80;
+    cpu.memory[0x301] = 0x01;
+    cpu.memory[0x31E] = 0xFF;
+    cpu.execute_opcode(0xD010).unwrap(); // Draw 16x16 sprite at (0, 0)
+    assert_eq!(cpu.renderer.buffer[0][0], 1);
+    assert_eq!(cpu.renderer.buffer[0][15], 1);
+    assert_eq!(cpu.renderer.buffer[0][16], 0);
+    assert_eq!(cpu.renderer.buffer[15][7], 1);
+    assert_eq!(cpu.renderer.buffer[15][8], 0);
+    assert_eq!(cpu.registers[0xF], 0);
+}
+
+#[test]
+fn test_fx75_fx85_rpl_flags() {
+    let mut cpu = CPU::new(Platform::SuperChip, Quirks::SUPERCHIP_11);
+    cpu.registers[0] = 1;
+    cpu.registers[1] = 2;
+    cpu.execute_opcode(0xF175).unwrap(); // Save V0..V1 to flags
+    cpu.registers[0] = 0;
+    cpu.registers[1] = 0;
+    cpu.execute_opcode(0xF185).unwrap(); // Restore V0..V1 from flags
+    assert_eq!(cpu.registers[0], 1);
+    assert_eq!(cpu.registers[1], 2);
+}
+
+#[test]
+fn test_00fd_exit() {
+    let mut cpu = CPU::new(Platform::SuperChip, Quirks::SUPERCHIP_11);
+    cpu.load_rom(&[0x00, 0xFD, 0x60, 0x01]).unwrap();
+    cpu.tick([false; 16]).unwrap();
+    assert!(cpu.exited);
+    cpu.tick([false; 16]).unwrap();
+    assert_eq!(cpu.registers[0], 0);
+}
+
+#[test]
+fn test_f000_nnnn_long_index() {
+    let mut cpu = CPU::new(Platform::XoChip, Quirks::XO_CHIP);
+    cpu.load_rom(&[0xF0, 0x00, 0x12, 0x34]).unwrap();
+    cpu.tick([false; 16]).unwrap();
+    assert_eq!(cpu.index, 0x1234);
+    assert_eq!(cpu.program_counter, 0x204);
+}
+
+#[test]
+fn test_skip_over_long_index() {
+    let mut cpu = CPU::new(Platform::XoChip, Quirks::XO_CHIP);
+    cpu.load_rom(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34]).unwrap();
+    cpu.tick([false; 16]).unwrap(); // V0 == 0, so skip the whole F000 NNNN
+    assert_eq!(cpu.program_counter, 0x206);
+}
+
+#[test]
+fn test_5xy2_5xy3_register_ranges() {
+    let mut cpu = CPU::new(Platform::XoChip, Quirks::XO_CHIP);
+    cpu.registers[2] = 2;
+    cpu.registers[3] = 3;
+    cpu.registers[4] = 4;
+    cpu.index = 0x300;
+    cpu.execute_opcode(0x5242).unwrap(); // Save V2..V4
+    assert_eq!(cpu.memory[0x300..0x303], [2, 3, 4]);
+    assert_eq!(cpu.index, 0x300);
+
+    cpu.execute_opcode(0x5422).unwrap(); // Save V4..V2 in reverse order
+    assert_eq!(cpu.memory[0x300..0x303], [4, 3, 2]);
+
+    cpu.registers[2] = 0;
+    cpu.registers[3] = 0;
+    cpu.registers[4] = 0;
+    cpu.execute_opcode(0x5243).unwrap(); // Load V2..V4
+    assert_eq!(cpu.registers[2..5], [4, 3, 2]);
+}
+
+#[test]
+fn test_fn01_bitplanes() {
+    let mut cpu = CPU::new(Platform::XoChip, Quirks::XO_CHIP);
+    cpu.index = 0x300;
+    cpu.memory[0x300] = 0x80; // Plane 1 data
+    cpu.memory[0x301] = 0xC0; // Plane 2 data
+    cpu.execute_opcode(0xF301).unwrap(); // Select both planes
+    cpu.execute_opcode(0xD011).unwrap();
+    assert_eq!(cpu.renderer.buffer[0][0], 3);
+    assert_eq!(cpu.renderer.buffer[0][1], 2);
+
+    cpu.execute_opcode(0xF201).unwrap(); // Select plane 2 only
+    cpu.execute_opcode(0x00E0).unwrap();
+    assert_eq!(cpu.renderer.buffer[0][0], 1);
+    assert_eq!(cpu.renderer.buffer[0][1], 0);
+}
+
+#[test]
+fn test_f002_fx3a_audio() {
+    let mut cpu = CPU::new(Platform::XoChip, Quirks::XO_CHIP);
+    cpu.index = 0x300;
+    cpu.memory[0x300..0x310].copy_from_slice(&[0xAA; 16]);
+    cpu.execute_opcode(0xF002).unwrap();
+    assert_eq!(cpu.audio_pattern, Some([0xAA; 16]));
+    cpu.registers[1] = 100;
+    cpu.execute_opcode(0xF13A).unwrap();
+    assert_eq!(cpu.pitch, 100);
+    assert!(cpu.audio_updated);
+}
+
+#[cfg(feature = "megachip")]
+#[test]
+fn test_0011_megachip_sprite() {
+    let mut cpu = CPU::new(Platform::MegaChip, Quirks::SUPERCHIP_11);
+    cpu.execute_opcode(0x0011).unwrap(); // Enable MegaChip mode
+    assert!(cpu.mega_mode);
+
+    cpu.index = 0x300;
+    cpu.memory[0x300..0x304].copy_from_slice(&[0xFF, 0x12, 0x34, 0x56]);
+    cpu.execute_opcode(0x0201).unwrap(); // Load one palette entry
+    assert_eq!(cpu.mega_renderer.palette[1], 0xFF123456);
+
+    cpu.memory[0x310..0x314].copy_from_slice(&[1, 0, 1, 1]);
+    cpu.index = 0x310;
+    cpu.execute_opcode(0x0302).unwrap(); // Sprite width 2
+    cpu.execute_opcode(0x0402).unwrap(); // Sprite height 2
+    cpu.registers[0] = 10;
+    cpu.registers[1] = 20;
+    cpu.execute_opcode(0xD010).unwrap();
+    assert_eq!(cpu.mega_renderer.indices[20][10], 1);
+    assert_eq!(cpu.mega_renderer.indices[20][11], 0);
+    assert_eq!(cpu.mega_renderer.pixels[21][11], 0xFF123456);
+    assert_eq!(cpu.registers[0xF], 0);
+
+    cpu.execute_opcode(0x0901).unwrap(); // Collide with colour 1
+    cpu.execute_opcode(0xD010).unwrap();
+    assert_eq!(cpu.registers[0xF], 1);
+}
+
+#[cfg(feature = "megachip")]
+#[test]
+fn test_080n_megachip_blend() {
+    let mut cpu = CPU::new(Platform::MegaChip, Quirks::SUPERCHIP_11);
+    cpu.execute_opcode(0x0011).unwrap();
+    cpu.mega_renderer.palette[1] = 0xFF804020;
+    cpu.mega_renderer.pixels[0][0] = 0xFF000000;
+    cpu.mega_renderer.sprite_width = 1;
+    cpu.mega_renderer.sprite_height = 1;
+    cpu.index = 0x300;
+    cpu.memory[0x300] = 1;
+    cpu.execute_opcode(0x0802).unwrap(); // 50% blend
+    cpu.execute_opcode(0xD000).unwrap();
+    assert_eq!(cpu.mega_renderer.pixels[0][0], 0xFF402010);
+}
+
+#[cfg(feature = "megachip")]
+#[test]
+fn test_00e0_megachip_presents_before_clearing() {
+    let mut cpu = CPU::new(Platform::MegaChip, Quirks::SUPERCHIP_11);
+    cpu.execute_opcode(0x0011).unwrap();
+    cpu.mega_renderer.indices[0][0] = 5;
+    cpu.execute_opcode(0x00E0).unwrap();
+    assert!(cpu.mega_renderer.redraw);
+    assert_eq!(cpu.mega_renderer.indices[0][0], 5); // Still shown until the next draw
+    cpu.execute_opcode(0x00B1).unwrap(); // Scroll up starts a new frame
+    assert_eq!(cpu.mega_renderer.indices[0][0], 0);
+}
+
+#[cfg(feature = "megachip")]
+#[test]
+fn test_060n_megachip_sample() {
+    let mut cpu = CPU::new(Platform::MegaChip, Quirks::SUPERCHIP_11);
+    cpu.index = 0x300;
+    cpu.memory[0x300..0x306].copy_from_slice(&[0x1F, 0x40, 0x00, 0x01, 0x00, 0x00]);
+    cpu.execute_opcode(0x0601).unwrap(); // Play once
+    let sample = cpu.sample.unwrap();
+    assert_eq!(sample.rate, 8000);
+    assert_eq!(sample.length, 256);
+    assert_eq!(sample.address, 0x306);
+    assert!(!sample.looping);
+    cpu.execute_opcode(0x0700).unwrap();
+    assert!(cpu.sample.is_none());
+}
+
+#[cfg(feature = "megachip")]
+#[test]
+fn test_megachip_memory_stops_at_64k() {
+    let mut cpu = CPU::new(Platform::MegaChip, Quirks::SUPERCHIP_11);
+    assert_eq!(
+        cpu.load_rom(&[0; 0xFE01]),
+        Err(CpuFault::RomTooLarge {
+            size: 0xFE01,
+            capacity: 0xFE00
+        })
+    );
+
+    cpu.load_rom(&[0x01, 0x01, 0x00, 0x00]).unwrap(); // i := long 0x10000
+    assert_eq!(
+        cpu.tick([false; 16]),
+        Err(CpuFault::MemoryOutOfBounds { address: 0x10000 })
+    );
+    assert_eq!(cpu.program_counter, 0x200);
+
+    // A sample can't run past the end either.
+    cpu.index = 0xFF00;
+    cpu.memory[0xFF00..0xFF06].copy_from_slice(&[0x1F, 0x40, 0x00, 0x01, 0x00, 0x00]);
+    assert_eq!(
+        cpu.execute_opcode(0x0601),
+        Err(CpuFault::MemoryOutOfBounds { address: 0x10005 })
+    );
+}
+
+#[test]
+fn test_platform_load_address_and_resolution() {
+    let mut cpu = CPU::new(Platform::Eti660, Quirks::COSMAC_VIP);
+    assert_eq!(cpu.program_counter, 0x600);
+    cpu.load_rom(&[0x60, 0x2A]).unwrap();
+    assert_eq!(cpu.memory[0x600], 0x60);
+    assert_eq!((cpu.renderer.width(), cpu.renderer.height()), (64, 48));
+
+    let cpu = CPU::new(Platform::HiresChip8, Quirks::COSMAC_VIP);
+    assert_eq!(cpu.program_counter, 0x244);
+    assert_eq!((cpu.renderer.width(), cpu.renderer.height()), (64, 64));
+}
+
+#[test]
+fn test_chip8x_colour_opcodes() {
+    let mut cpu = CPU::new(Platform::Chip8X, Quirks::COSMAC_VIP);
+    cpu.registers[0] = 0x12;
+    cpu.registers[1] = 0x37;
+    cpu.execute_opcode(0x5011).unwrap(); // Nibble-wise add
+    assert_eq!(cpu.registers[0], 0x41);
+
+    cpu.registers[2] = 0x10; // Zone columns 0..=1
+    cpu.registers[3] = 2; // Colour blue
+    cpu.registers[4] = 0x01; // Zone row 1 (pixel rows 4..8)
+    cpu.execute_opcode(0xB240).unwrap();
+    let zones = cpu.renderer.zone_colors.unwrap();
+    assert_eq!(zones[4][0], 2);
+    assert_eq!(zones[7][1], 2);
+    assert_eq!(zones[8][0], 0);
+    assert_eq!(zones[4][2], 0);
+
+    cpu.execute_opcode(0x02A0).unwrap(); // Cycle background colour
+    assert_eq!(cpu.renderer.background, 1);
+}
+
+#[test]
+fn test_chip8e_opcodes() {
+    let mut cpu = CPU::new(Platform::Chip8E, Quirks::COSMAC_VIP);
+    cpu.registers[1] = 5;
+    cpu.registers[2] = 3;
+    cpu.execute_opcode(0x5121).unwrap(); // Skip if V1 > V2
+    assert_eq!(cpu.program_counter, 0x202);
+
+    cpu.index = 0x300;
+    cpu.execute_opcode(0x5122).unwrap(); // Save V1..V2 and advance I
+    assert_eq!(cpu.memory[0x300..0x302], [5, 3]);
+    assert_eq!(cpu.index, 0x302);
+
+    cpu.program_counter = 0x210; // As if BB04 at 0x20E had just been fetched
+    cpu.execute_opcode(0xBB04).unwrap();
+    assert_eq!(cpu.program_counter, 0x20A);
+    cpu.program_counter = 0x210;
+    cpu.execute_opcode(0xBF04).unwrap();
+    assert_eq!(cpu.program_counter, 0x212);
+}
+
+#[test]
+fn test_fx0a_wait_for_key() {
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
+    cpu.load_rom(&[0xF3, 0x0A]).unwrap();
+    assert_eq!(cpu.tick([false; 16]), Ok(StepOutcome::Waiting));
+    assert_eq!(cpu.program_counter, 0x200);
+    let mut keypad = [false; 16];
+    keypad[5] = true;
+    assert_eq!(cpu.tick(keypad), Ok(StepOutcome::Executed));
+    assert_eq!((cpu.registers[3], cpu.program_counter), (5, 0x202));
+
+    // Run directly with PC at 0, it backs up to the end of memory.
+    cpu.keypad = [false; 16];
+    cpu.program_counter = 0;
+    let outcome = cpu.execute(Instruction::WaitForKey { x: 0 });
+    assert_eq!(outcome, Ok(StepOutcome::Waiting));
+    assert_eq!(cpu.program_counter, 0xFFFE);
+}
+
+#[test]
+fn test_chip8e_wait_for_delay() {
+    let mut cpu = CPU::new(Platform::Chip8E, Quirks::COSMAC_VIP);
+    cpu.registers[0] = 1;
+    cpu.load_rom(&[0xF0, 0x4F, 0x61, 0x01]).unwrap();
+    cpu.tick([false; 16]).unwrap(); // Set the delay timer and wait for it
+    cpu.tick([false; 16]).unwrap();
+    assert_eq!(cpu.registers[1], 0);
+    cpu.tick_60hz();
+    cpu.tick([false; 16]).unwrap();
+    assert_eq!(cpu.registers[1], 1);
+}
+
+#[test]
+fn test_fx29_points_at_installed_font() {
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
+    cpu.registers[3] = 0xA;
+    cpu.execute_opcode(0xF329).unwrap();
+    assert_eq!(cpu.index, 0x050 + 0xA * 5);
+    let glyph = &Font::COSMAC_VIP.small[50..55];
+    assert_eq!(
+        &cpu.memory[cpu.index as usize..cpu.index as usize + 5],
+        glyph
+    );
+}
+
+#[test]
+fn test_install_font_at_custom_address() {
+    let mut cpu = CPU::new(Platform::SuperChip, Quirks::SUPERCHIP_11);
+    cpu.install_font(&Font::FISH_N_CHIPS, 0x100).unwrap();
+    cpu.registers[0] = 1;
+    cpu.execute_opcode(0xF029).unwrap();
+    assert_eq!(cpu.index, 0x105);
+    assert_eq!(cpu.memory[0x105..0x10A], Font::FISH_N_CHIPS.small[5..10]);
+    cpu.execute_opcode(0xF030).unwrap();
+    assert_eq!(cpu.index, 0x100 + 80 + 10);
+    assert_eq!(cpu.memory[0x15A..0x164], Font::FISH_N_CHIPS.big[10..20]);
+}
+
+#[test]
+fn test_font_from_bytes() {
+    let font = Font::from_bytes(&[0xAA; 80]).unwrap();
+    assert_eq!(font.small, [0xAA; 80]);
+    assert_eq!(font.big, Font::OCTO.big);
+    assert!(Font::from_bytes(&[0; 81]).is_err());
+}
+
+#[test]
+fn test_stack_faults() {
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
+    assert_eq!(cpu.execute_opcode(0x00EE), Err(CpuFault::StackUnderflow));
+    for _ in 0..16 {
+        cpu.execute_opcode(0x2200).unwrap();
+    }
+    assert_eq!(cpu.execute_opcode(0x2200), Err(CpuFault::StackOverflow));
+}
+
+#[test]
+fn test_memory_faults() {
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
+    assert_eq!(
+        cpu.load_rom(&[0; 0xE01]),
+        Err(CpuFault::RomTooLarge {
+            size: 0xE01,
+            capacity: 0xE00
+        })
+    );
+
+    cpu.index = 0xFFE;
+    assert_eq!(
+        cpu.execute_opcode(0xF255),
+        Err(CpuFault::MemoryOutOfBounds { address: 0x1000 })
+    );
+
+    // The XO-CHIP address space runs to 64 KiB.
+    let mut cpu = CPU::new(Platform::XoChip, Quirks::XO_CHIP);
+    cpu.index = 0xFFE;
+    cpu.execute_opcode(0xF255).unwrap();
+
+    // Big sprites running off the end fault rather than wrapping round.
+    cpu.index = 0xFFF0;
+    assert_eq!(
+        cpu.execute_opcode(0xD010),
+        Err(CpuFault::MemoryOutOfBounds { address: 0x10000 })
+    );
+
+    // So does running off the end.
+    cpu.memory[0xFFFE..].copy_from_slice(&[0x60, 0x01]);
+    cpu.program_counter = 0xFFFE;
+    assert_eq!(
+        cpu.tick([false; 16]),
+        Err(CpuFault::MemoryOutOfBounds { address: 0x10000 })
+    );
+    assert_eq!(cpu.program_counter, 0xFFFE);
+}
+
+#[test]
+fn test_tick_leaves_pc_on_faulting_instruction() {
+    let mut cpu = CPU::new(Platform::Chip8, Quirks::default());
+    cpu.load_rom(&[0x00, 0xEE]).unwrap();
+    assert_eq!(cpu.tick([false; 16]), Err(CpuFault::StackUnderflow));
+    assert_eq!(cpu.program_counter, 0x200);
+}
+
+#[test]
+fn test_cxnn_is_reproducible_with_a_seed() {
+    let mut first = CPU::with_rng(Platform::Chip8, Quirks::default(), SeededRandom::new(42));
+    let mut second = CPU::with_rng(Platform::Chip8, Quirks::default(), SeededRandom::new(42));
+    for _ in 0..8 {
+        first.execute_opcode(0xC0FF).unwrap();
+        second.execute_opcode(0xC0FF).unwrap();
+        assert_eq!(first.registers[0], second.registers[0]);
+    }
+
+    first.execute_opcode(0xC10F).unwrap();
+    assert_eq!(first.registers[1] & 0xF0, 0);
+}
+
+#[test]
+fn test_vip_random_reads_the_interpreter_page() {
+    let mut cpu = CPU::with_rng(Platform::Chip8, Quirks::COSMAC_VIP, VipRandom::new(0));
+    cpu.memory[0x101] = 3;
+    cpu.memory[0x102] = 4;
+    cpu.execute_opcode(0xC0FF).unwrap();
+    assert_eq!(cpu.registers[0], 3);
+    cpu.execute_opcode(0xC0FF).unwrap();
+    assert_eq!(cpu.registers[0], 7);
+}
+
+#[test]
+fn test_decode_encode_round_trip() {
+    let platforms = [
+        Platform::Chip8,
+        Platform::HiresChip8,
+        Platform::Eti660,
+        Platform::Chip8X,
+        Platform::Chip8E,
+        Platform::SuperChip,
+        Platform::XoChip,
+        Platform::MegaChip,
+    ];
+    for platform in platforms {
+        for opcode in 0..=0xFFFF {
+            if let Some(instruction) = Instruction::decode(opcode, platform) {
+                assert_eq!(instruction.encode(), opcode, "{:?}", instruction);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decode_depends_on_platform() {
+    assert_eq!(
+        Instruction::decode(0xD125, Platform::Chip8),
+        Some(Instruction::Draw { x: 1, y: 2, n: 5 })
+    );
+    assert_eq!(Instruction::decode(0x00FF, Platform::Chip8), None);
+    assert_eq!(
+        Instruction::decode(0x00FF, Platform::SuperChip),
+        Some(Instruction::HighResolution)
+    );
+    assert_eq!(
+        Instruction::decode(0x5121, Platform::Chip8X),
+        Some(Instruction::AddNibbles { x: 1, y: 2 })
+    );
+    assert_eq!(
+        Instruction::decode(0x5121, Platform::Chip8E),
+        Some(Instruction::SkipIfGreater { x: 1, y: 2 })
+    );
+    assert_eq!(Instruction::LongIndex.size(), 4);
+}
+
+#[test]
+fn test_cpu_size() {
+    // Memory and the screens are nearly all of a CPU, and the MegaChip
+    // screen only comes with its feature.
+    let limit = MEMORY_SIZE + std::mem::size_of::<Renderer>() + 0x400;
+    #[cfg(feature = "megachip")]
+    let limit = limit + std::mem::size_of::<MegaRenderer>();
+    assert!(std::mem::size_of::<CPU<VipRandom>>() <= limit);
+    assert!(cfg!(feature = "megachip") || limit < 80 * 1024);
+}
@@ -0,0 +1,90 @@
+/// How FX55/FX65 leave the index register once the transfer is done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left untouched.
+    None,
+    /// I is advanced by X (CHIP-48's off-by-one).
+    ByX,
+    /// I is advanced by X + 1, pointing just past the last byte transferred.
+    ByXPlusOne,
+}
+
+/// Behaviors that differ between CHIP-8 implementations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY1/8XY2/8XY3 reset VF to zero.
+    pub vf_reset: bool,
+    /// 8XY6/8XYE shift VY into VX instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// What FX55/FX65 do to I.
+    pub index_increment: IndexIncrement,
+    /// BNNN jumps to XNN + VX instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// Sprites wrap around the screen edges instead of being clipped.
+    pub wrap_sprites: bool,
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        vf_reset: true,
+        shift_uses_vy: true,
+        index_increment: IndexIncrement::ByXPlusOne,
+        jump_uses_vx: false,
+        wrap_sprites: false,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        vf_reset: false,
+        shift_uses_vy: false,
+        index_increment: IndexIncrement::ByX,
+        jump_uses_vx: true,
+        wrap_sprites: false,
+    };
+
+    /// SUPER-CHIP 1.0 was built on CHIP-48 and kept all of these quirks,
+    /// including I advancing by X; 1.1 is the version that changed it.
+    pub const SUPERCHIP_10: Quirks = Quirks::CHIP_48;
+
+    pub const SUPERCHIP_11: Quirks = Quirks {
+        vf_reset: false,
+        shift_uses_vy: false,
+        index_increment: IndexIncrement::None,
+        jump_uses_vx: true,
+        wrap_sprites: false,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        vf_reset: false,
+        shift_uses_vy: true,
+        index_increment: IndexIncrement::ByXPlusOne,
+        jump_uses_vx: false,
+        wrap_sprites: true,
+    };
+
+    /// Looks up a preset by the name accepted on the command line.
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name {
+            "default" => Some(Quirks::default()),
+            "vip" | "cosmac-vip" => Some(Quirks::COSMAC_VIP),
+            "chip48" | "chip-48" => Some(Quirks::CHIP_48),
+            "schip10" | "superchip-1.0" => Some(Quirks::SUPERCHIP_10),
+            "schip11" | "schip" | "superchip-1.1" => Some(Quirks::SUPERCHIP_11),
+            "xochip" | "xo-chip" => Some(Quirks::XO_CHIP),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    /// The behavior rusty8 has always had: in-place shifts, untouched I and
+    /// V0-relative jumps, with sprites wrapping at the edges.
+    fn default() -> Self {
+        Quirks {
+            vf_reset: false,
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::None,
+            jump_uses_vx: false,
+            wrap_sprites: true,
+        }
+    }
+}
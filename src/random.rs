@@ -0,0 +1,101 @@
+/// Supplies the bytes that CXNN masks with NN. Generators are plain values,
+/// so the debugger can snapshot one along with the rest of the CPU.
+pub trait RandomSource: Clone {
+    /// Returns the next random byte. `memory` is the CPU's address space, for
+    /// generators that, like the COSMAC VIP's, draw on the interpreter's own
+    /// memory.
+    fn next_byte(&mut self, memory: &[u8]) -> u8;
+}
+
+/// A xorshift generator that produces the same sequence for the same seed, so
+/// a run can be replayed exactly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        // Xorshift never leaves the all-zero state, so zero is swapped for an
+        // arbitrary non-zero constant.
+        let state = if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        };
+        SeededRandom { state }
+    }
+
+    /// Seeds the generator from the operating system.
+    #[cfg(feature = "std")]
+    pub fn from_entropy() -> Self {
+        SeededRandom::new(rand::random())
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 56) as u8
+    }
+}
+
+/// The COSMAC VIP interpreter's generator. A pointer steps through the page
+/// at 0x100 and each byte it lands on is added to the previous result. On the
+/// VIP that page holds the interpreter itself, so the real sequence is only
+/// reproduced when the interpreter image is loaded below 0x200.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VipRandom {
+    pointer: u8,
+    value: u8,
+}
+
+impl VipRandom {
+    pub fn new(seed: u64) -> Self {
+        VipRandom {
+            pointer: seed as u8,
+            value: (seed >> 8) as u8,
+        }
+    }
+}
+
+impl RandomSource for VipRandom {
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        self.pointer = self.pointer.wrapping_add(1);
+        let byte = memory
+            .get(0x100 + self.pointer as usize)
+            .copied()
+            .unwrap_or(0);
+        self.value = self.value.wrapping_add(byte);
+        self.value
+    }
+}
+
+/// One of the built-in generators, for frontends that pick one at run time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuiltinRandom {
+    Seeded(SeededRandom),
+    Vip(VipRandom),
+}
+
+impl BuiltinRandom {
+    /// Looks up a generator by the name accepted on the command line.
+    pub fn from_name(name: &str, seed: u64) -> Option<BuiltinRandom> {
+        match name {
+            "seeded" => Some(BuiltinRandom::Seeded(SeededRandom::new(seed))),
+            "vip" => Some(BuiltinRandom::Vip(VipRandom::new(seed))),
+            _ => None,
+        }
+    }
+}
+
+impl RandomSource for BuiltinRandom {
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        match self {
+            BuiltinRandom::Seeded(random) => random.next_byte(memory),
+            BuiltinRandom::Vip(random) => random.next_byte(memory),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What a debugger knows about a program's source: the labels, and the
+/// source line each instruction was compiled from.
+///
+/// Symbol files give one symbol a line, in any of these forms, with
+/// addresses in hex and `#` or `;` starting a comment:
+///
+/// ```text
+/// 02A4 main_loop
+/// main_loop 0x2A4
+/// : main_loop 0x2A4
+/// main_loop = 0x2A4
+/// 02A4 game.8o:17
+/// ```
+///
+/// The last maps an address to a source line. All the source lines have to
+/// be in the same file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    /// The source file the lines refer to.
+    pub file: Option<String>,
+    /// Label names by address.
+    pub labels: BTreeMap<u16, String>,
+    /// Source lines, counting from 1, by instruction address.
+    pub lines: BTreeMap<u16, usize>,
+}
+
+impl SymbolMap {
+    pub fn new() -> Self {
+        SymbolMap::default()
+    }
+
+    /// Reads a symbol file. A relative source file in it is taken to be
+    /// next to the symbol file.
+    pub fn load(path: &str) -> Result<SymbolMap, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut symbols = SymbolMap::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        if let Some(file) = &mut symbols.file {
+            let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+            *file = directory.join(&*file).to_string_lossy().into_owned();
+        }
+        Ok(symbols)
+    }
+
+    pub fn parse(text: &str) -> Result<SymbolMap, String> {
+        let mut symbols = SymbolMap::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let no_address = || format!("line {}: expected an address and a name", n + 1);
+            let (address, name) = match words[..] {
+                [] => continue,
+                [":", name, address] | [name, "=", address] => {
+                    (parse_address(address).ok_or_else(no_address)?, name)
+                }
+                // Names like `add` are hex numbers too, so the address is the
+                // second word only if it is the one with a prefix.
+                [first, second] => match (parse_address(first), parse_address(second)) {
+                    (Some(_), Some(address)) if prefixed(second) && !prefixed(first) => {
+                        (address, first)
+                    }
+                    (Some(address), _) => (address, second),
+                    (None, Some(address)) => (address, first),
+                    (None, None) => return Err(no_address()),
+                },
+                _ => return Err(no_address()),
+            };
+            match source_line(name) {
+                Some((file, line)) => {
+                    if *symbols.file.get_or_insert_with(|| file.to_string()) != file {
+                        return Err(format!("line {}: more than one source file", n + 1));
+                    }
+                    symbols.lines.insert(address, line);
+                }
+                None => {
+                    symbols.labels.insert(address, name.to_string());
+                }
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Looks up an address written as `label` or `label+offset`, with the
+    /// offset in hex.
+    pub fn resolve(&self, text: &str) -> Option<u16> {
+        let (name, offset) = match text.split_once('+') {
+            Some((name, offset)) => (name, parse_address(offset)?),
+            None => (text, 0),
+        };
+        let (&address, _) = self.labels.iter().find(|(_, label)| *label == name)?;
+        address.checked_add(offset)
+    }
+
+    /// The line the instruction at `address` came from.
+    pub fn line_at(&self, address: u16) -> Option<usize> {
+        self.lines.get(&address).copied()
+    }
+
+    /// Where a breakpoint on `line` goes: the first instruction of that line,
+    /// or of the next line with code on it. Returns the line used as well.
+    pub fn address_of_line(&self, line: usize) -> Option<(u16, usize)> {
+        let line = self.lines.values().copied().filter(|&l| l >= line).min()?;
+        let address = self
+            .lines
+            .iter()
+            .find(|&(_, &l)| l == line)
+            .map(|(&address, _)| address)?;
+        Some((address, line))
+    }
+
+    /// The nearest label at or before `address`, and how far past it
+    /// `address` is.
+    pub fn label_for(&self, address: u16) -> Option<(&str, u16)> {
+        self.labels
+            .range(..=address)
+            .next_back()
+            .map(|(&start, name)| (name.as_str(), address - start))
+    }
+
+    /// Writes an address in hex, followed by `<label+offset>` when there is
+    /// a label before it.
+    pub fn annotate(&self, address: u16) -> String {
+        match self.label_for(address) {
+            Some(_) => format!("{:04X} <{}>", address, self.describe(address)),
+            None => format!("{:04X}", address),
+        }
+    }
+
+    /// Names an address as `label+offset`, or in hex when no label comes
+    /// before it.
+    pub fn describe(&self, address: u16) -> String {
+        match self.label_for(address) {
+            Some((label, 0)) => label.to_string(),
+            Some((label, offset)) => format!("{}+{:X}", label, offset),
+            None => format!("{:04X}", address),
+        }
+    }
+}
+
+fn prefixed(text: &str) -> bool {
+    text.starts_with("0x") || text.starts_with("0X") || text.starts_with('$')
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// Splits `file:line`.
+fn source_line(name: &str) -> Option<(&str, usize)> {
+    let (file, line) = name.rsplit_once(':')?;
+    let line = line.parse().ok().filter(|&line| line > 0)?;
+    Some((file, line)).filter(|(file, _)| !file.is_empty())
+}
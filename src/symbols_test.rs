@@ -0,0 +1,41 @@
+#[cfg(test)]
+use crate::symbols::SymbolMap;
+
+#[test]
+fn test_symbol_file_forms() {
+    let text = "\
+# labels
+0200 main
+add 0x20A      ; a label that looks like hex
+: draw 0x210
+sprite = 0x300
+0202 game.8o:4
+0204 game.8o:5
+";
+    let symbols = SymbolMap::parse(text).unwrap();
+    assert_eq!(symbols.labels.len(), 4);
+    assert_eq!(symbols.labels[&0x20A], "add");
+    assert_eq!(symbols.labels[&0x300], "sprite");
+    assert_eq!(symbols.file.as_deref(), Some("game.8o"));
+    assert_eq!(symbols.line_at(0x204), Some(5));
+
+    assert_eq!(symbols.resolve("draw"), Some(0x210));
+    assert_eq!(symbols.resolve("main+1A"), Some(0x21A));
+    assert_eq!(symbols.resolve("nowhere"), None);
+    assert_eq!(symbols.annotate(0x20E), "020E <add+4>");
+    assert_eq!(symbols.annotate(0x100), "0100");
+
+    assert!(SymbolMap::parse("0200").is_err());
+    assert!(SymbolMap::parse("0200 a.8o:1\n0202 b.8o:1").is_err());
+}
+
+#[test]
+fn test_source_files_are_next_to_the_symbol_file() {
+    let directory = std::env::temp_dir().join("rusty8_symbols");
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("game.sym");
+    std::fs::write(&path, "0200 game.8o:1\n").unwrap();
+    let symbols = SymbolMap::load(&path.to_string_lossy()).unwrap();
+    let expected = directory.join("game.8o");
+    assert_eq!(symbols.file.as_deref(), expected.to_str());
+}
@@ -0,0 +1,498 @@
+use crate::cfg::AccessKind;
+use crate::debugger;
+use crate::disasm::{mnemonic, RomMap, Syntax};
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use crate::processor::CPU;
+use crate::random::RandomSource;
+use crate::symbols::SymbolMap;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// The start of a binary trace, followed by a version byte and the length
+/// and name of the platform.
+const MAGIC: &[u8; 8] = b"RUSTY8TR";
+const VERSION: u8 = 1;
+
+/// Bits in a binary record's change flags, after the 16 for V0-VF.
+const CHANGED_INDEX: u32 = 1 << 16;
+const CHANGED_STACK_POINTER: u32 = 1 << 17;
+const CHANGED_DELAY: u32 = 1 << 18;
+const CHANGED_SOUND: u32 = 1 << 19;
+const WROTE_MEMORY: u32 = 1 << 20;
+
+/// How a trace is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// A line of text per instruction:
+    ///
+    /// ```text
+    ///       12 0204 <main+4>  8014  ADD V0, V1           V=05020000000000000000000000000000 I=0000 SP=0 DT=00 ST=00 -> V0=07
+    /// ```
+    ///
+    /// which is the cycle, PC, opcode, mnemonic and the registers, I, SP and
+    /// timers before it ran, then what it changed, with memory it stored to
+    /// written like `[0300]=010203`, or `-` for nothing.
+    Text,
+    /// The same in about a fifth of the space, for long runs. After the
+    /// header each instruction is the cycles since the last one as a LEB128
+    /// number, PC and opcode, the word after for four byte instructions,
+    /// V0-VF, I, SP, DT and ST, then 24 bits of flags for what changed and
+    /// the new values. Words are little-endian.
+    Binary,
+}
+
+impl TraceFormat {
+    /// Looks up a format by the name accepted on the command line.
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name {
+            "text" => Some(TraceFormat::Text),
+            "binary" => Some(TraceFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// The registers an instruction can change.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub index: u16,
+    pub stack_pointer: u8,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl Registers {
+    pub fn capture(cpu: &CPU<impl RandomSource>) -> Registers {
+        Registers {
+            v: cpu.registers,
+            index: cpu.index,
+            stack_pointer: cpu.stack_pointer,
+            delay_timer: cpu.delay_timer,
+            sound_timer: cpu.sound_timer,
+        }
+    }
+}
+
+/// One instruction run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// How many instructions ran before it.
+    pub cycle: u64,
+    pub address: u16,
+    pub opcode: u16,
+    /// The second word of F000 NNNN and 01NN NNNN.
+    pub operand: Option<u16>,
+    pub before: Registers,
+    pub after: Registers,
+    /// Where it stored to memory through I, and the bytes stored.
+    pub writes: Option<(u16, Vec<u8>)>,
+}
+
+/// Which instructions go in the trace.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Only those at addresses from the first to the second, inclusive.
+    pub range: Option<(u16, u16)>,
+    /// Only those whose opcode starts with one of these hex digits, a bit
+    /// per digit, so 8XYN arithmetic is `1 << 8`.
+    pub classes: Option<u16>,
+}
+
+impl Filter {
+    /// Reads a range written as `start-end` in hex.
+    pub fn parse_range(text: &str) -> Result<(u16, u16), String> {
+        let error = || format!("Invalid address range {}, expected start-end", text);
+        let (start, end) = text.split_once('-').ok_or_else(error)?;
+        let parse = |address: &str| {
+            u16::from_str_radix(address.trim_start_matches("0x"), 16).map_err(|_| error())
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start > end {
+            return Err(error());
+        }
+        Ok((start, end))
+    }
+
+    /// Reads opcode classes given as their hex digits, such as `8,D,F` or
+    /// `8df`.
+    pub fn parse_classes(text: &str) -> Result<u16, String> {
+        let mut classes = 0;
+        for c in text.chars().filter(|&c| c != ',') {
+            let digit = c
+                .to_digit(16)
+                .ok_or_else(|| format!("Invalid opcode class {}, expected 0-F", c))?;
+            classes |= 1 << digit;
+        }
+        Ok(classes)
+    }
+
+    pub fn matches(&self, address: u16, opcode: u16) -> bool {
+        self.range
+            .is_none_or(|(start, end)| (start..=end).contains(&address))
+            && self
+                .classes
+                .is_none_or(|classes| classes & (1 << (opcode >> 12)) != 0)
+    }
+}
+
+/// Writes a trace of the instructions run, for comparing with another run
+/// or emulator. `begin` is called before each instruction and `end` after
+/// it; an instruction that faults is left out.
+pub struct Tracer {
+    output: Box<dyn Write>,
+    format: TraceFormat,
+    platform: Platform,
+    filter: Filter,
+    symbols: SymbolMap,
+    /// The labels again, for naming operands in the mnemonics.
+    map: RomMap,
+    /// How many instructions have begun.
+    cycle: u64,
+    /// The cycle of the last step written, which binary records count from.
+    last_written: u64,
+    /// The instruction that has begun, unless the filter leaves it out.
+    running: Option<(Step, Option<(u16, u16)>)>,
+}
+
+impl Tracer {
+    /// Starts a trace in a new file.
+    pub fn create(
+        path: &str,
+        platform: Platform,
+        format: TraceFormat,
+        filter: Filter,
+    ) -> Result<Tracer, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        Tracer::new(Box::new(BufWriter::new(file)), platform, format, filter)
+    }
+
+    pub fn new(
+        mut output: Box<dyn Write>,
+        platform: Platform,
+        format: TraceFormat,
+        filter: Filter,
+    ) -> Result<Tracer, String> {
+        if format == TraceFormat::Binary {
+            let name = platform.name().as_bytes();
+            let mut header = MAGIC.to_vec();
+            header.extend_from_slice(&[VERSION, name.len() as u8]);
+            header.extend_from_slice(name);
+            output
+                .write_all(&header)
+                .map_err(|e| format!("Couldn't write the trace: {}", e))?;
+        }
+        Ok(Tracer {
+            output,
+            format,
+            platform,
+            filter,
+            symbols: SymbolMap::new(),
+            map: rom_map(&SymbolMap::new()),
+            cycle: 0,
+            last_written: 0,
+            running: None,
+        })
+    }
+
+    /// Names addresses in the text format by `symbols`.
+    pub fn set_symbols(&mut self, symbols: SymbolMap) {
+        self.map = rom_map(&symbols);
+        self.symbols = symbols;
+    }
+
+    /// Notes the state before the instruction at PC runs.
+    pub fn begin(&mut self, cpu: &CPU<impl RandomSource>) {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        self.running = None;
+        let address = cpu.program_counter;
+        let Ok(opcode) = cpu.fetch(address) else {
+            return;
+        };
+        if !self.filter.matches(address, opcode) {
+            return;
+        }
+        let instruction = Instruction::decode(opcode, cpu.platform);
+        let operand = match instruction {
+            Some(instruction) if instruction.size() == 4 => cpu.fetch(address.wrapping_add(2)).ok(),
+            _ => None,
+        };
+        // Stores go through I as it was before the instruction.
+        let store = debugger::memory_access(cpu)
+            .filter(|&(_, kind)| kind == AccessKind::Store)
+            .map(|(length, _)| (cpu.index, length as u16));
+        let registers = Registers::capture(cpu);
+        let step = Step {
+            cycle,
+            address,
+            opcode,
+            operand,
+            before: registers,
+            after: registers,
+            writes: None,
+        };
+        self.running = Some((step, store));
+    }
+
+    /// Writes out the instruction begun, now that it has run.
+    pub fn end(&mut self, cpu: &CPU<impl RandomSource>) -> Result<(), String> {
+        let Some((mut step, store)) = self.running.take() else {
+            return Ok(());
+        };
+        step.after = Registers::capture(cpu);
+        step.writes = store.map(|(start, length)| {
+            let start_index = start as usize;
+            let end = (start_index + length as usize).min(cpu.platform.memory_size());
+            (start, cpu.memory[start_index.min(end)..end].to_vec())
+        });
+        let result = match self.format {
+            TraceFormat::Text => {
+                let line = write_text(&step, self.platform, &self.symbols, &self.map);
+                writeln!(self.output, "{}", line)
+            }
+            TraceFormat::Binary => {
+                let record = encode(&step, step.cycle - self.last_written);
+                self.output.write_all(&record)
+            }
+        };
+        self.last_written = step.cycle;
+        result.map_err(|e| format!("Couldn't write the trace: {}", e))
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.output
+            .flush()
+            .map_err(|e| format!("Couldn't write the trace: {}", e))
+    }
+}
+
+/// Formats a step as a line of the text format.
+pub fn format_step(step: &Step, platform: Platform, symbols: &SymbolMap) -> String {
+    write_text(step, platform, symbols, &rom_map(symbols))
+}
+
+/// Whether `bytes` are a binary trace rather than text.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Reads a binary trace back, returning the platform it was made on.
+pub fn read_binary(bytes: &[u8]) -> Result<(Platform, Vec<Step>), String> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("Not a binary trace".to_string());
+    }
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(format!("Unknown binary trace version {}", version));
+    }
+    let length = reader.byte()? as usize;
+    let name = String::from_utf8_lossy(reader.take(length)?).into_owned();
+    let platform =
+        Platform::from_name(&name).ok_or_else(|| format!("Unknown platform: {}", name))?;
+
+    let mut steps = Vec::new();
+    let mut cycle = 0;
+    while reader.position < bytes.len() {
+        cycle += reader.varint()?;
+        let address = reader.word()?;
+        let opcode = reader.word()?;
+        let operand = match Instruction::decode(opcode, platform) {
+            Some(instruction) if instruction.size() == 4 => Some(reader.word()?),
+            _ => None,
+        };
+        let mut before = Registers::default();
+        before.v.copy_from_slice(reader.take(16)?);
+        before.index = reader.word()?;
+        before.stack_pointer = reader.byte()?;
+        before.delay_timer = reader.byte()?;
+        before.sound_timer = reader.byte()?;
+
+        let flags = u32::from_le_bytes([reader.byte()?, reader.byte()?, reader.byte()?, 0]);
+        let mut after = before;
+        for x in 0..16 {
+            if flags & (1 << x) != 0 {
+                after.v[x] = reader.byte()?;
+            }
+        }
+        if flags & CHANGED_INDEX != 0 {
+            after.index = reader.word()?;
+        }
+        if flags & CHANGED_STACK_POINTER != 0 {
+            after.stack_pointer = reader.byte()?;
+        }
+        if flags & CHANGED_DELAY != 0 {
+            after.delay_timer = reader.byte()?;
+        }
+        if flags & CHANGED_SOUND != 0 {
+            after.sound_timer = reader.byte()?;
+        }
+        let writes = if flags & WROTE_MEMORY != 0 {
+            let start = reader.word()?;
+            let length = reader.byte()? as usize;
+            Some((start, reader.take(length)?.to_vec()))
+        } else {
+            None
+        };
+        steps.push(Step {
+            cycle,
+            address,
+            opcode,
+            operand,
+            before,
+            after,
+            writes,
+        });
+    }
+    Ok((platform, steps))
+}
+
+fn rom_map(symbols: &SymbolMap) -> RomMap {
+    RomMap {
+        base: 0,
+        kinds: Vec::new(),
+        labels: BTreeMap::new(),
+        names: symbols.labels.clone(),
+    }
+}
+
+fn write_text(step: &Step, platform: Platform, symbols: &SymbolMap, map: &RomMap) -> String {
+    let text = match Instruction::decode(step.opcode, platform) {
+        Some(instruction) => mnemonic(&instruction, step.operand, Syntax::Cowgod, map),
+        None => "???".to_string(),
+    };
+    let (before, after) = (&step.before, &step.after);
+    let v: String = before.v.iter().map(|x| format!("{:02X}", x)).collect();
+    let mut changes: Vec<String> = (0..16)
+        .filter(|&x| before.v[x] != after.v[x])
+        .map(|x| format!("V{:X}={:02X}", x, after.v[x]))
+        .collect();
+    if before.index != after.index {
+        changes.push(format!("I={:04X}", after.index));
+    }
+    if before.stack_pointer != after.stack_pointer {
+        changes.push(format!("SP={:X}", after.stack_pointer));
+    }
+    if before.delay_timer != after.delay_timer {
+        changes.push(format!("DT={:02X}", after.delay_timer));
+    }
+    if before.sound_timer != after.sound_timer {
+        changes.push(format!("ST={:02X}", after.sound_timer));
+    }
+    if let Some((start, bytes)) = &step.writes {
+        let bytes: String = bytes.iter().map(|x| format!("{:02X}", x)).collect();
+        changes.push(format!("[{:04X}]={}", start, bytes));
+    }
+    if changes.is_empty() {
+        changes.push("-".to_string());
+    }
+    format!(
+        "{:>8} {}  {:04X}  {:<20} V={} I={:04X} SP={:X} DT={:02X} ST={:02X} -> {}",
+        step.cycle,
+        symbols.annotate(step.address),
+        step.opcode,
+        text,
+        v,
+        before.index,
+        before.stack_pointer,
+        before.delay_timer,
+        before.sound_timer,
+        changes.join(" ")
+    )
+}
+
+/// Encodes a step as a binary record, `delta` cycles after the last one.
+fn encode(step: &Step, delta: u64) -> Vec<u8> {
+    let mut record = Vec::new();
+    let mut delta = delta;
+    while delta >= 0x80 {
+        record.push(delta as u8 | 0x80);
+        delta >>= 7;
+    }
+    record.push(delta as u8);
+    record.extend_from_slice(&step.address.to_le_bytes());
+    record.extend_from_slice(&step.opcode.to_le_bytes());
+    if let Some(operand) = step.operand {
+        record.extend_from_slice(&operand.to_le_bytes());
+    }
+    let (before, after) = (&step.before, &step.after);
+    record.extend_from_slice(&before.v);
+    record.extend_from_slice(&before.index.to_le_bytes());
+    record.extend_from_slice(&[before.stack_pointer, before.delay_timer, before.sound_timer]);
+
+    let mut flags = 0;
+    let mut changes = Vec::new();
+    for x in 0..16 {
+        if before.v[x] != after.v[x] {
+            flags |= 1 << x;
+            changes.push(after.v[x]);
+        }
+    }
+    if before.index != after.index {
+        flags |= CHANGED_INDEX;
+        changes.extend_from_slice(&after.index.to_le_bytes());
+    }
+    for (flag, old, new) in [
+        (
+            CHANGED_STACK_POINTER,
+            before.stack_pointer,
+            after.stack_pointer,
+        ),
+        (CHANGED_DELAY, before.delay_timer, after.delay_timer),
+        (CHANGED_SOUND, before.sound_timer, after.sound_timer),
+    ] {
+        if old != new {
+            flags |= flag;
+            changes.push(new);
+        }
+    }
+    if let Some((start, bytes)) = &step.writes {
+        flags |= WROTE_MEMORY;
+        changes.extend_from_slice(&start.to_le_bytes());
+        changes.push(bytes.len() as u8);
+        changes.extend_from_slice(bytes);
+    }
+    record.extend_from_slice(&flags.to_le_bytes()[..3]);
+    record.extend_from_slice(&changes);
+    record
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or("The binary trace ends in the middle of a record")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn word(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("A cycle count in the binary trace is too long".to_string())
+    }
+}
@@ -0,0 +1,100 @@
+#[cfg(test)]
+use crate::platform::Platform;
+#[cfg(test)]
+use crate::processor::CPU;
+#[cfg(test)]
+use crate::quirks::Quirks;
+#[cfg(test)]
+use crate::random::SeededRandom;
+#[cfg(test)]
+use crate::symbols::SymbolMap;
+#[cfg(test)]
+use crate::trace::{self, Filter, TraceFormat, Tracer};
+
+#[cfg(test)]
+const ROM: [u8; 12] = [
+    0x60, 0x05, // v0 := 5
+    0x61, 0x02, // v1 := 2
+    0x80, 0x14, // v0 += v1
+    0xA3, 0x00, // i := 0x300
+    0xF1, 0x55, // save v1
+    0x12, 0x00, // jump 0x200
+];
+
+/// Runs `ticks` instructions of `ROM` with a trace to a file, and returns
+/// what was written.
+#[cfg(test)]
+fn run(
+    name: &str,
+    format: TraceFormat,
+    filter: Filter,
+    symbols: SymbolMap,
+    ticks: usize,
+) -> Vec<u8> {
+    let path = std::env::temp_dir().join(name);
+    let path = path.to_string_lossy();
+    let mut cpu = CPU::with_rng(Platform::Chip8, Quirks::default(), SeededRandom::new(1));
+    cpu.load_rom(&ROM).unwrap();
+    let mut tracer = Tracer::create(&path, Platform::Chip8, format, filter).unwrap();
+    tracer.set_symbols(symbols);
+    for _ in 0..ticks {
+        tracer.begin(&cpu);
+        cpu.tick([false; 16]).unwrap();
+        tracer.end(&cpu).unwrap();
+    }
+    tracer.flush().unwrap();
+    std::fs::read(&*path).unwrap()
+}
+
+#[test]
+fn test_text_trace() {
+    let symbols = SymbolMap::parse("0200 main").unwrap();
+    let output = run(
+        "rusty8_trace.log",
+        TraceFormat::Text,
+        Filter::default(),
+        symbols,
+        5,
+    );
+    let text = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(
+        lines[2],
+        "       2 0204 <main+4>  8014  ADD V0, V1           \
+         V=05020000000000000000000000000000 I=0000 SP=0 DT=00 ST=00 -> V0=07"
+    );
+    assert!(lines[3].ends_with("-> I=0300"));
+    assert!(lines[4].ends_with("-> [0300]=0702"));
+}
+
+#[test]
+fn test_filters_and_binary_traces() {
+    let filter = Filter {
+        range: Some(Filter::parse_range("202-2FF").unwrap()),
+        classes: Some(Filter::parse_classes("8,f").unwrap()),
+    };
+    assert_eq!(filter.classes, Some(1 << 8 | 1 << 15));
+    assert!(Filter::parse_range("300-200").is_err());
+    assert!(Filter::parse_classes("8,G").is_err());
+
+    let output = run(
+        "rusty8_trace.bin",
+        TraceFormat::Binary,
+        filter,
+        SymbolMap::new(),
+        11,
+    );
+    let (platform, steps) = trace::read_binary(&output).unwrap();
+    assert_eq!(platform, Platform::Chip8);
+    // Only 8014 and F155, twice round the loop.
+    let cycles: Vec<u64> = steps.iter().map(|step| step.cycle).collect();
+    assert_eq!(cycles, [2, 4, 8, 10]);
+    assert_eq!(steps[1].writes, Some((0x300, vec![7, 2])));
+    assert_eq!(steps[2].before.v[..2], [5, 2]);
+    let line = trace::format_step(&steps[0], platform, &SymbolMap::new());
+    assert!(line.starts_with("       2 0204  8014  ADD V0, V1"));
+
+    assert!(trace::read_binary(b"RUSTY8TR").is_err());
+    assert!(trace::read_binary(&output[..output.len() - 1]).is_err());
+}
@@ -0,0 +1,418 @@
+use crate::disasm::{mnemonic, RomMap, Syntax};
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use crate::symbols::SymbolMap;
+use crate::trace::{self, Step};
+use std::collections::BTreeMap;
+
+/// The registers a trace can record, in the order `State` holds them.
+pub const FIELDS: [&str; 20] = [
+    "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8", "V9", "VA", "VB", "VC", "VD", "VE", "VF",
+    "I", "SP", "DT", "ST",
+];
+
+/// The registers by `FIELDS`, for those a trace gives.
+pub type State = [Option<u16>; 20];
+
+/// How many records before the divergence to show from each trace.
+const CONTEXT: usize = 3;
+
+/// One instruction in a trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// The line it is on, counting from 1, or its place in a binary trace.
+    pub line: usize,
+    pub address: u16,
+    pub opcode: Option<u16>,
+    /// The registers before it ran.
+    pub before: State,
+    /// The registers after it ran, for traces that show that.
+    pub after: Option<State>,
+    /// Where it stored to memory and the bytes stored, for traces that show
+    /// that.
+    pub writes: Option<(u16, Vec<u8>)>,
+    /// The record as the trace has it.
+    pub text: String,
+}
+
+/// Where two traces first disagree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The instruction in each trace that went differently, or the first
+    /// ones when the traces start differently.
+    pub a: usize,
+    pub b: usize,
+    /// What differs, as the name and then the value in each trace.
+    pub differences: Vec<(String, String, String)>,
+}
+
+/// Reads a trace from `--trace` in either format, or from another emulator.
+///
+/// Other emulators' traces are read a line per instruction, taking the state
+/// before it ran from whatever is written as `name:value` or `name=value`,
+/// in hex: `PC`, `OP` or `opcode`, `V0`-`VF`, `I`, `SP`, and `DT` and `ST`
+/// or `delay` and `sound`. Lines without a PC are skipped.
+pub fn read(path: &str) -> Result<Vec<Record>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    if !trace::is_binary(&bytes) {
+        return Ok(parse(&String::from_utf8_lossy(&bytes)));
+    }
+    let (platform, steps) = trace::read_binary(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+    let symbols = SymbolMap::new();
+    Ok(steps
+        .iter()
+        .enumerate()
+        .map(|(n, step)| from_step(n + 1, step, platform, &symbols))
+        .collect())
+}
+
+/// Reads a text trace.
+pub fn parse(text: &str) -> Vec<Record> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(n, line)| parse_line(n + 1, line))
+        .collect()
+}
+
+/// Compares two traces from where they first have the same PC, and returns
+/// the first instruction that went differently, leaving out the registers
+/// named in `ignore`.
+pub fn diff(a: &[Record], b: &[Record], ignore: &[String]) -> Option<Divergence> {
+    let (start_a, start_b) = align(a, b);
+    let compared = |x: &State, y: &State| -> Vec<(String, String, String)> {
+        FIELDS
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| !ignore.iter().any(|ignored| ignored == *name))
+            .filter_map(|(i, name)| match (x[i], y[i]) {
+                (Some(x), Some(y)) if x != y => {
+                    Some((name.to_string(), hex(name, x), hex(name, y)))
+                }
+                _ => None,
+            })
+            .collect()
+    };
+
+    let pairs = a[start_a..].iter().zip(&b[start_b..]).enumerate();
+    for (k, (x, y)) in pairs {
+        // A difference in what an instruction found is the last one's doing.
+        let mut differences = Vec::new();
+        if x.address != y.address {
+            differences.push((
+                "PC".to_string(),
+                format!("{:04X}", x.address),
+                format!("{:04X}", y.address),
+            ));
+        }
+        differences.extend(compared(&x.before, &y.before));
+        if !differences.is_empty() {
+            let back = (k > 0) as usize;
+            return Some(Divergence {
+                a: start_a + k - back,
+                b: start_b + k - back,
+                differences,
+            });
+        }
+
+        if let (Some(p), Some(q)) = (x.opcode, y.opcode) {
+            if p != q {
+                differences.push((
+                    "opcode".to_string(),
+                    format!("{:04X}", p),
+                    format!("{:04X}", q),
+                ));
+            }
+        }
+        if let (Some(p), Some(q)) = (&x.after, &y.after) {
+            differences.extend(compared(p, q));
+            if x.writes != y.writes {
+                let (address, p, q) = match (&x.writes, &y.writes) {
+                    (Some((p, _)), Some((q, _))) if p != q => (
+                        "stored to".to_string(),
+                        format!("{:04X}", p),
+                        format!("{:04X}", q),
+                    ),
+                    (Some((address, _)), _) | (_, Some((address, _))) => (
+                        format!("[{:04X}]", address),
+                        bytes(&x.writes),
+                        bytes(&y.writes),
+                    ),
+                    (None, None) => unreachable!(),
+                };
+                differences.push((address, p, q));
+            }
+        }
+        if !differences.is_empty() {
+            return Some(Divergence {
+                a: start_a + k,
+                b: start_b + k,
+                differences,
+            });
+        }
+    }
+    None
+}
+
+/// Describes a divergence between traces `a` and `b`, named `names`, with
+/// the records leading up to it from each.
+pub fn report(
+    a: &[Record],
+    b: &[Record],
+    names: (&str, &str),
+    divergence: &Divergence,
+    platform: Platform,
+) -> String {
+    let (x, y) = (&a[divergence.a], &b[divergence.b]);
+    let mut output = format!(
+        "First divergence at {} ({} line {}, {} line {})\n",
+        describe(x, platform),
+        names.0,
+        x.line,
+        names.1,
+        y.line
+    );
+    for (name, p, q) in &divergence.differences {
+        output.push_str(&format!(
+            "  {}: {} in {}, {} in {}\n",
+            name, p, names.0, q, names.1
+        ));
+    }
+    for (name, records, index) in [(names.0, a, divergence.a), (names.1, b, divergence.b)] {
+        output.push_str(&format!("\n{}:\n", name));
+        let end = (index + 2).min(records.len());
+        for (i, record) in records[..end]
+            .iter()
+            .enumerate()
+            .skip(index.saturating_sub(CONTEXT))
+        {
+            let marker = if i == index { '>' } else { ' ' };
+            output.push_str(&format!("{} {}\n", marker, record.text));
+        }
+    }
+    output
+}
+
+fn align(a: &[Record], b: &[Record]) -> (usize, usize) {
+    let (Some(first_a), Some(first_b)) = (a.first(), b.first()) else {
+        return (0, 0);
+    };
+    let in_a = a
+        .iter()
+        .position(|record| record.address == first_b.address);
+    let in_b = b
+        .iter()
+        .position(|record| record.address == first_a.address);
+    match (in_a, in_b) {
+        (Some(i), Some(j)) if j < i => (0, j),
+        (Some(i), _) => (i, 0),
+        (None, Some(j)) => (0, j),
+        (None, None) => (0, 0),
+    }
+}
+
+fn from_step(line: usize, step: &Step, platform: Platform, symbols: &SymbolMap) -> Record {
+    let state = |registers: &trace::Registers| {
+        let mut state = [None; 20];
+        for (x, &value) in registers.v.iter().enumerate() {
+            state[x] = Some(value as u16);
+        }
+        state[16] = Some(registers.index);
+        state[17] = Some(registers.stack_pointer as u16);
+        state[18] = Some(registers.delay_timer as u16);
+        state[19] = Some(registers.sound_timer as u16);
+        state
+    };
+    Record {
+        line,
+        address: step.address,
+        opcode: Some(step.opcode),
+        before: state(&step.before),
+        after: Some(state(&step.after)),
+        writes: step.writes.clone(),
+        text: trace::format_step(step, platform, symbols),
+    }
+}
+
+/// Reads a line of a text trace: one from `--trace` if it has the `->`
+/// between before and after, or otherwise another emulator's.
+fn parse_line(line: usize, text: &str) -> Option<Record> {
+    let mut record = Record {
+        line,
+        address: 0,
+        opcode: None,
+        before: [None; 20],
+        after: None,
+        writes: None,
+        text: text.to_string(),
+    };
+    let mut address = None;
+    match text.split_once(" -> ") {
+        Some((before, after)) => {
+            // The cycle, the PC, maybe its label, the opcode.
+            let mut words = before.split_whitespace().skip(1);
+            address = words.next().and_then(parse_hex);
+            let opcode = words.find(|word| !word.starts_with('<'));
+            record.opcode = opcode.and_then(parse_hex);
+            apply(
+                &mut record.before,
+                pairs(before),
+                &mut None,
+                &mut None,
+                &mut None,
+            );
+            let mut after_state = record.before;
+            apply(
+                &mut after_state,
+                pairs(after_text(after)),
+                &mut None,
+                &mut None,
+                &mut record.writes,
+            );
+            record.after = Some(after_state);
+        }
+        None => {
+            let mut opcode = None;
+            apply(
+                &mut record.before,
+                pairs(text),
+                &mut address,
+                &mut opcode,
+                &mut None,
+            );
+            record.opcode = opcode;
+        }
+    }
+    record.address = address?;
+    Some(record)
+}
+
+/// `-` is written when an instruction changed nothing.
+fn after_text(text: &str) -> &str {
+    if text.trim() == "-" {
+        ""
+    } else {
+        text
+    }
+}
+
+/// Finds the `name:value` and `name=value` pairs in a line, which may have
+/// a space after the `:` or `=`.
+fn pairs(text: &str) -> Vec<(String, &str)> {
+    let words: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '|')
+        .filter(|word| !word.is_empty())
+        .collect();
+    let mut pairs = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        if let Some(split) = words[i].find([':', '=']) {
+            let (name, value) = (&words[i][..split], &words[i][split + 1..]);
+            let value = match words.get(i + 1) {
+                Some(&next) if value.is_empty() => {
+                    i += 1;
+                    next
+                }
+                _ => value,
+            };
+            if !name.is_empty() {
+                pairs.push((name.to_ascii_uppercase(), value));
+            }
+        }
+        i += 1;
+    }
+    pairs
+}
+
+/// Sets what `pairs` give, in `state` or in the others for the PC, opcode
+/// and stores.
+fn apply(
+    state: &mut State,
+    pairs: Vec<(String, &str)>,
+    address: &mut Option<u16>,
+    opcode: &mut Option<u16>,
+    writes: &mut Option<(u16, Vec<u8>)>,
+) {
+    for (name, value) in pairs {
+        let field = match name.as_str() {
+            "PC" => {
+                *address = parse_hex(value);
+                continue;
+            }
+            "OP" | "OPCODE" => {
+                *opcode = parse_hex(value);
+                continue;
+            }
+            // All of V0-VF at once, as `--trace` writes them.
+            "V" => {
+                let bytes = parse_bytes(value).filter(|bytes| bytes.len() == 16);
+                for (x, byte) in bytes.into_iter().flatten().enumerate() {
+                    state[x] = Some(byte as u16);
+                }
+                continue;
+            }
+            "DELAY" => "DT",
+            "SOUND" => "ST",
+            name if name.starts_with('[') && name.ends_with(']') => {
+                let start = parse_hex(&name[1..name.len() - 1]);
+                *writes = start.zip(parse_bytes(value));
+                continue;
+            }
+            name => name,
+        };
+        if let Some(i) = FIELDS.iter().position(|&known| known == field) {
+            state[i] = parse_hex(value);
+        }
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn hex(name: &str, value: u16) -> String {
+    match name {
+        "I" => format!("{:04X}", value),
+        _ => format!("{:02X}", value),
+    }
+}
+
+fn bytes(writes: &Option<(u16, Vec<u8>)>) -> String {
+    match writes {
+        Some((_, bytes)) => bytes.iter().map(|byte| format!("{:02X}", byte)).collect(),
+        None => "nothing".to_string(),
+    }
+}
+
+fn describe(record: &Record, platform: Platform) -> String {
+    let map = RomMap {
+        base: 0,
+        kinds: Vec::new(),
+        labels: BTreeMap::new(),
+        names: BTreeMap::new(),
+    };
+    match record.opcode {
+        Some(opcode) => {
+            let text = match Instruction::decode(opcode, platform) {
+                Some(instruction) => mnemonic(&instruction, None, Syntax::Cowgod, &map),
+                None => "???".to_string(),
+            };
+            format!("{:04X}  {:04X}  {}", record.address, opcode, text)
+        }
+        None => format!("{:04X}", record.address),
+    }
+}
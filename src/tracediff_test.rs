@@ -0,0 +1,106 @@
+#[cfg(test)]
+use crate::platform::Platform;
+#[cfg(test)]
+use crate::processor::CPU;
+#[cfg(test)]
+use crate::quirks::Quirks;
+#[cfg(test)]
+use crate::random::SeededRandom;
+#[cfg(test)]
+use crate::trace::{Filter, TraceFormat, Tracer};
+#[cfg(test)]
+use crate::tracediff;
+
+#[cfg(test)]
+const ROM: [u8; 10] = [
+    0x60, 0x05, // v0 := 5
+    0x61, 0x03, // v1 := 3
+    0x80, 0x16, // v0 >>= v1
+    0xA3, 0x00, // i := 0x300
+    0xF0, 0x33, // bcd v0
+];
+
+/// Traces `ROM` run with `quirks` to a temporary file and returns its path.
+#[cfg(test)]
+fn trace(name: &str, quirks: Quirks, format: TraceFormat) -> String {
+    let path = std::env::temp_dir().join(name);
+    let path = path.to_string_lossy().into_owned();
+    let mut cpu = CPU::with_rng(Platform::Chip8, quirks, SeededRandom::new(1));
+    cpu.load_rom(&ROM).unwrap();
+    let mut tracer = Tracer::create(&path, Platform::Chip8, format, Filter::default()).unwrap();
+    for _ in 0..5 {
+        tracer.begin(&cpu);
+        cpu.tick([false; 16]).unwrap();
+        tracer.end(&cpu).unwrap();
+    }
+    tracer.flush().unwrap();
+    path
+}
+
+#[test]
+fn test_finds_the_instruction_that_differs() {
+    let a = trace(
+        "rusty8_tracediff_a.log",
+        Quirks::default(),
+        TraceFormat::Text,
+    );
+    let b = trace(
+        "rusty8_tracediff_b.bin",
+        Quirks::COSMAC_VIP,
+        TraceFormat::Binary,
+    );
+    let (a, b) = (tracediff::read(&a).unwrap(), tracediff::read(&b).unwrap());
+    assert_eq!(a.len(), 5);
+    assert_eq!(a[4].writes, Some((0x300, vec![0, 0, 2])));
+    assert_eq!(tracediff::diff(&a, &a, &[]), None);
+
+    let divergence = tracediff::diff(&a, &b, &[]).unwrap();
+    assert_eq!((divergence.a, divergence.b), (2, 2));
+    assert_eq!(
+        divergence.differences,
+        [("V0".to_string(), "02".to_string(), "01".to_string())]
+    );
+    let report = tracediff::report(&a, &b, ("a.log", "b.bin"), &divergence, Platform::Chip8);
+    assert!(report.starts_with(
+        "First divergence at 0204  8016  SHR V0, V1 (a.log line 3, b.bin line 3)\n  \
+         V0: 02 in a.log, 01 in b.bin\n"
+    ));
+    assert!(report.contains("\n>        2 0204  8016"));
+}
+
+#[test]
+fn test_reads_other_emulators_traces() {
+    let ours = trace(
+        "rusty8_tracediff_ours.log",
+        Quirks::default(),
+        TraceFormat::Text,
+    );
+    let ours = tracediff::read(&ours).unwrap();
+    // Another emulator's trace, with a boot line to skip and a different
+    // delay timer.
+    let theirs = tracediff::parse(
+        "\
+PC: 0x0000 OP: 0000
+cycle 0
+PC: 0x0200 OP: 6005 V0: 00 V1: 00 I: 0000 DT: 3
+PC=$0202 op=6103 v0=05 v1=00 i=0000 delay=3
+PC:0204 OP:8016 V0:05 V1:03 I:0000 DT:3
+PC:0206 OP:A300 V0:03 V1:03 I:0000 DT:3
+",
+    );
+    assert_eq!(theirs.len(), 5);
+    assert_eq!(theirs[2].address, 0x202);
+    assert_eq!(theirs[2].opcode, Some(0x6103));
+    assert_eq!(theirs[2].before[0], Some(5));
+
+    let ignore = ["DT".to_string()];
+    let divergence = tracediff::diff(&ours, &theirs, &ignore).unwrap();
+    // V0 is wrong going into the fourth instruction, so the third did it.
+    assert_eq!((divergence.a, divergence.b), (2, 3));
+    assert_eq!(
+        divergence.differences,
+        [("V0".to_string(), "02".to_string(), "03".to_string())]
+    );
+    let divergence = tracediff::diff(&ours, &theirs, &[]).unwrap();
+    assert_eq!(divergence.differences[0].0, "DT");
+}